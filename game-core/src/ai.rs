@@ -0,0 +1,411 @@
+//! Computer-controlled players.
+//!
+//! An [`Ai`] looks at the match one command at a time, so each decision sees the results of the
+//! last one. It always finishes with [`Command::EndTurn`].
+use std::collections::BTreeSet;
+
+use rand::{Rng, seq::IndexedRandom};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use world_generation::CellId;
+
+use crate::{
+    Ability, Board, Command, ConstructionKind, Improvement, Match, PlayerId, Settlement,
+    SettlementId, Unit, UnitId,
+};
+
+/// Settlements a computer player stops building settlers at.
+const MAX_SETTLEMENTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Difficulty {
+    /// Builds and wanders at random, and often passes up an attack.
+    Easy,
+    #[default]
+    Normal,
+    /// Builds the strongest units it can afford and focuses fire on the enemy it's most likely to
+    /// destroy.
+    Hard,
+}
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ai {
+    pub player: PlayerId,
+    pub difficulty: Difficulty,
+    rng: ChaCha20Rng,
+    /// The turn the `attacked` and `moved` sets belong to.
+    turn: u32,
+    attacked: BTreeSet<UnitId>,
+    moved: BTreeSet<UnitId>,
+}
+impl Ai {
+    pub fn new(player: PlayerId, difficulty: Difficulty, rng: ChaCha20Rng) -> Self {
+        Self {
+            player,
+            difficulty,
+            rng,
+            turn: 0,
+            attacked: BTreeSet::new(),
+            moved: BTreeSet::new(),
+        }
+    }
+    /// The next thing this player wants to do, `None` while it's someone else's turn or once the
+    /// match is over.
+    pub fn next_command(&mut self, game: &Match, board: Board) -> Option<Command> {
+        if game.active_player() != self.player || game.outcome().is_some() {
+            return None;
+        }
+        if self.turn != game.turn() {
+            self.turn = game.turn();
+            self.attacked.clear();
+            self.moved.clear();
+        }
+        if let Some(tech) = self.pick_research(game) {
+            return Some(Command::SetResearch { tech });
+        }
+        let own_settlements = game
+            .settlements()
+            .filter(|s| s.controller == self.player)
+            .collect::<Vec<_>>();
+        for settlement in own_settlements.iter() {
+            if settlement.construction.is_some() {
+                continue;
+            }
+            let construction = self.pick_construction(game, settlement);
+            let idle = construction.is_none_or(|i| {
+                settlement.available_constructions[i].kind == ConstructionKind::Sink
+            });
+            if idle && let Some(command) = self.pick_improvement(game, board, settlement) {
+                return Some(command);
+            }
+            if let Some(construction) = construction {
+                return Some(Command::SetConstruction {
+                    settlement: settlement.id,
+                    construction,
+                });
+            }
+        }
+        let own_units = game
+            .units()
+            .filter(|u| u.controller == self.player)
+            .collect::<Vec<_>>();
+        let enemies = game
+            .units()
+            .filter(|u| u.controller != self.player)
+            .collect::<Vec<_>>();
+        for unit in own_units.iter() {
+            if unit.can(Ability::FoundSettlement)
+                && game.check_settlement_site(board, unit.cell).is_ok()
+            {
+                // Named by the app from its pool of settlement names.
+                return Some(Command::FoundSettlement {
+                    unit: unit.id,
+                    name: String::new(),
+                });
+            }
+        }
+        for unit in own_units.iter() {
+            if !self.attacked.insert(unit.id) {
+                continue;
+            }
+            if let Some(defender) = self.pick_target(game, board, unit, &enemies) {
+                return Some(Command::Attack {
+                    attacker: unit.id,
+                    defender,
+                });
+            }
+            if let Some(settlement) = self.pick_siege(game, board, unit) {
+                return Some(Command::AttackSettlement {
+                    attacker: unit.id,
+                    settlement,
+                });
+            }
+        }
+        for unit in own_units.iter() {
+            if unit.can(Ability::FoundSettlement) || self.moved.contains(&unit.id) {
+                continue;
+            }
+            // Walls are patched up on the owner's turn, so breached settlements are taken at once.
+            let reachable = game.reachable_cells(board, unit.id);
+            if let Some(target) = game.settlements().find(|s| {
+                s.controller != self.player && s.breached() && reachable.contains_key(&s.cell)
+            }) {
+                self.moved.insert(unit.id);
+                return Some(Command::MoveUnit {
+                    unit: unit.id,
+                    goal: Some(target.cell),
+                });
+            }
+        }
+        for unit in own_units.iter() {
+            if self.moved.insert(unit.id)
+                && let Some(goal) = self.pick_goal(game, board, unit)
+                && Some(goal) != unit.goal
+            {
+                return Some(Command::MoveUnit {
+                    unit: unit.id,
+                    goal: Some(goal),
+                });
+            }
+        }
+        Some(Command::EndTurn)
+    }
+    /// Something to research if nothing is being researched, the cheapest tech unless on Easy.
+    fn pick_research(&mut self, game: &Match) -> Option<String> {
+        let research = &game.player(self.player)?.research;
+        if research.current.is_some() {
+            return None;
+        }
+        let available = game.tech_tree().available(research).collect::<Vec<_>>();
+        let tech = match self.difficulty {
+            Difficulty::Easy => available.choose(&mut self.rng).copied(),
+            _ => available
+                .iter()
+                .min_by(|a, b| a.cost.total_cmp(&b.cost))
+                .copied(),
+        };
+        tech.map(|t| t.id.clone())
+    }
+    fn pick_construction(&mut self, game: &Match, settlement: &Settlement) -> Option<usize> {
+        let player = game.player(self.player)?;
+        let affordable = settlement
+            .available_constructions
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.materials <= player.materials);
+        let unit_types = affordable.clone().filter_map(|(i, c)| match &c.kind {
+            ConstructionKind::Unit(name) => player
+                .unit_types
+                .iter()
+                .find(|u| u.name == *name)
+                .map(|u| (i, c.cost, u)),
+            ConstructionKind::Improvement { .. } | ConstructionKind::Sink => None,
+        });
+        let (settlers, units): (Vec<_>, Vec<_>) =
+            unit_types.partition(|(_, _, u)| u.abilities.contains(&Ability::FoundSettlement));
+        if self.difficulty != Difficulty::Easy
+            && let Some((i, _, _)) = settlers.first()
+            && self.wants_settler(game)
+        {
+            return Some(*i);
+        }
+        let units = units
+            .into_iter()
+            .map(|(i, cost, u)| (i, cost, u.health * (u.range as f32 + 1.0)))
+            .collect::<Vec<_>>();
+        let choice = match self.difficulty {
+            Difficulty::Easy => {
+                return affordable
+                    .map(|(i, _)| i)
+                    .collect::<Vec<_>>()
+                    .choose(&mut self.rng)
+                    .copied();
+            }
+            Difficulty::Normal => units.iter().min_by(|a, b| a.1.total_cmp(&b.1)),
+            Difficulty::Hard => units
+                .iter()
+                .max_by(|a, b| (a.2 / a.1).total_cmp(&(b.2 / b.1))),
+        };
+        choice.map(|(i, _, _)| *i).or_else(|| {
+            settlement
+                .available_constructions
+                .iter()
+                .position(|c| c.kind == ConstructionKind::Sink)
+        })
+    }
+    /// Whether to expand, with room for more settlements and no settler already on its way.
+    fn wants_settler(&self, game: &Match) -> bool {
+        let Some(player) = game.player(self.player) else {
+            return false;
+        };
+        let settlers = player
+            .unit_types
+            .iter()
+            .filter(|u| u.abilities.contains(&Ability::FoundSettlement))
+            .map(|u| ConstructionKind::Unit(u.name.clone()))
+            .collect::<Vec<_>>();
+        let settlements = game
+            .settlements()
+            .filter(|s| s.controller == self.player)
+            .collect::<Vec<_>>();
+        let settler_queued = settlements
+            .iter()
+            .filter_map(|s| s.construction.as_ref())
+            .any(|c| settlers.contains(&c.kind));
+        let settler_out = game
+            .units()
+            .any(|u| u.controller == self.player && u.can(Ability::FoundSettlement));
+        settlements.len() < MAX_SETTLEMENTS && !settler_queued && !settler_out
+    }
+    /// The affordable improvement adding the most to its cell, when there's no unit worth
+    /// building. Easy never improves its land.
+    fn pick_improvement(
+        &mut self,
+        game: &Match,
+        board: Board,
+        settlement: &Settlement,
+    ) -> Option<Command> {
+        if self.difficulty == Difficulty::Easy {
+            return None;
+        }
+        let materials = game.player(self.player)?.materials;
+        let value = |cell: CellId, improvement: &Improvement| {
+            let yields = improvement.yields_on(board.world_map, cell);
+            yields.food + yields.production + yields.materials
+        };
+        game.buildable_improvements(board, settlement.id)
+            .into_iter()
+            .filter(|(_, i)| i.materials <= materials)
+            .max_by(|a, b| value(a.0, a.1).total_cmp(&value(b.0, b.1)))
+            .map(|(cell, improvement)| Command::BuildImprovement {
+                settlement: settlement.id,
+                improvement: improvement.name.clone(),
+                cell,
+            })
+    }
+    fn pick_target(
+        &mut self,
+        game: &Match,
+        board: Board,
+        unit: &Unit,
+        enemies: &[&Unit],
+    ) -> Option<UnitId> {
+        if self.difficulty == Difficulty::Easy && self.rng.random_bool(0.5) {
+            return None;
+        }
+        let cells = board.cells_in_range(unit.cell, unit.range);
+        let in_range = enemies.iter().filter(|e| cells.contains_key(&e.cell));
+        let target = match self.difficulty {
+            Difficulty::Hard => in_range
+                .filter_map(|e| {
+                    crate::predict_combat(game, board, unit.id, e.id)
+                        .ok()
+                        .map(|odds| (e, odds.kill_chance))
+                })
+                .max_by(|a, b| {
+                    a.1.total_cmp(&b.1)
+                        .then_with(|| b.0.health.total_cmp(&a.0.health))
+                })
+                .map(|(e, _)| e),
+            _ => in_range.min_by_key(|e| e.id),
+        };
+        target.map(|e| e.id)
+    }
+    /// An enemy settlement in range with its walls still up. Unless on Easy, only attacked when
+    /// the walls can't strike back hard enough to destroy the unit.
+    fn pick_siege(&self, game: &Match, board: Board, unit: &Unit) -> Option<SettlementId> {
+        if unit.can(Ability::FoundSettlement) {
+            return None;
+        }
+        game.settlements()
+            .filter(|s| s.controller != self.player)
+            .find_map(|s| {
+                let odds = crate::predict_siege(game, board, unit.id, s.id).ok()?;
+                let worst = odds.counter_damage.map_or(0.0, |c| *c.end());
+                (self.difficulty == Difficulty::Easy || worst < unit.health).then_some(s.id)
+            })
+    }
+    /// Heads for the nearest enemy settlement, or the nearest enemy unit if there are none.
+    /// Settlers head for the nearest place they can settle instead.
+    fn pick_goal(&mut self, game: &Match, board: Board, unit: &Unit) -> Option<CellId> {
+        if unit.can(Ability::FoundSettlement) {
+            return settlement_site(game, board, unit);
+        }
+        if self.difficulty == Difficulty::Easy {
+            let mut cells = game
+                .reachable_cells(board, unit.id)
+                .into_keys()
+                .filter(|c| *c != unit.cell)
+                .collect::<Vec<_>>();
+            // Sorted so the choice doesn't depend on hash order.
+            cells.sort();
+            return cells.choose(&mut self.rng).copied();
+        }
+        let enemy_settlements = game
+            .settlements()
+            .filter(|s| s.controller != self.player)
+            .map(|s| s.cell)
+            .collect::<Vec<_>>();
+        let targets = if enemy_settlements.is_empty() {
+            game.units()
+                .filter(|u| u.controller != self.player)
+                .map(|u| u.cell)
+                .collect::<Vec<_>>()
+        } else {
+            enemy_settlements
+        };
+        targets
+            .into_iter()
+            .filter_map(|cell| {
+                board
+                    .path(unit.cell, cell, &unit.movement)
+                    .map(|p| (p.len(), cell))
+            })
+            .min_by_key(|(d, _)| *d)
+            .map(|(_, cell)| cell)
+    }
+}
+
+/// The closest cell as the crow flies that `unit` can reach and found a settlement on.
+fn settlement_site(game: &Match, board: Board, unit: &Unit) -> Option<CellId> {
+    let origin = board.world_map.get_position_for_cell(unit.cell);
+    let mut sites = game
+        .settlement_sites(board)
+        .into_iter()
+        .map(|c| (board.world_map.get_position_for_cell(c).distance(origin), c))
+        .collect::<Vec<_>>();
+    sites.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+    // Sites across water fail the whole search, so only the closest few are tried.
+    sites
+        .into_iter()
+        .take(8)
+        .map(|(_, c)| c)
+        .find(|c| board.path(unit.cell, *c, &unit.movement).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Player,
+        test_support::{player, test_board, unit_type},
+    };
+
+    #[test]
+    fn ai_players_take_their_turns() {
+        let test = test_board(3);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let players = (0..2)
+            .map(|i| Player {
+                unit_types: vec![unit_type("Spear")],
+                ..player(i)
+            })
+            .collect();
+        let mut game = test.new_match(players);
+        let mut cells = world_map.get_valid_settlement_cells();
+        if cells.len() < 2 {
+            cells = world_map.iter_cells().map(|c| CellId(c.site())).collect();
+        }
+        for (i, cell) in [cells[0], cells[cells.len() - 1]].into_iter().enumerate() {
+            game.found_settlement(PlayerId(i), i.to_string(), cell, vec![]);
+        }
+        let mut ais = Difficulty::ALL[1..]
+            .iter()
+            .enumerate()
+            .map(|(i, d)| Ai::new(PlayerId(i), *d, test.rng.clone()))
+            .collect::<Vec<_>>();
+        for _ in 0..500 {
+            if game.turn() > 3 {
+                break;
+            }
+            let ai = &mut ais[game.active_player().0];
+            let command = ai.next_command(&game, board).unwrap();
+            game.apply(board, command).unwrap();
+        }
+        assert!(game.turn() > 3);
+        assert!(game.units().count() > 0);
+    }
+}
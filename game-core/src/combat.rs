@@ -0,0 +1,491 @@
+//! Resolving attacks between units.
+//!
+//! Damage starts from the attacker's `attack` (or the defender's `defence` when it strikes back),
+//! scaled by how healthy the unit is, archetype counters, high ground and flanking. One roll then
+//! spreads it by up to [`ROLL_SPREAD`] either way. [`predict_combat`] runs the same numbers
+//! without rolling. Settlements are attacked the same way, with their walls standing in for
+//! health.
+use std::ops::RangeInclusive;
+
+use pathfinding::WATER_LEVEL;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use world_generation::CellId;
+
+use crate::{Board, CommandError, Event, Match, SETTLEMENT_DEFENCE, SettlementId, Unit, UnitId};
+
+/// How far a roll can move damage away from its expected value, as a fraction.
+pub const ROLL_SPREAD: f32 = 0.5;
+/// Damage multiplier against an archetype the unit counters.
+pub const COUNTER_BONUS: f32 = 1.5;
+/// Extra damage for each other friendly unit next to the defender.
+pub const FLANKING_BONUS: f32 = 0.15;
+const MAX_FLANKERS: usize = 3;
+/// Damage change per unit of raw height between the two units, in the higher unit's favour.
+const HEIGHT_BONUS: f32 = 2.0;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub enum Archetype {
+    #[default]
+    Melee,
+    Ranged,
+    Magic,
+}
+impl Archetype {
+    /// The archetype this one counters when a unit type doesn't say otherwise. Melee runs down
+    /// ranged units, arrows outpace spellcasting and magic breaks through armour.
+    pub fn default_counter(self) -> Archetype {
+        match self {
+            Archetype::Melee => Archetype::Ranged,
+            Archetype::Ranged => Archetype::Magic,
+            Archetype::Magic => Archetype::Melee,
+        }
+    }
+}
+
+/// How a unit type fights, as described in its `.civ.ron` file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CombatStats {
+    pub archetype: Archetype,
+    /// Damage dealt when attacking, before modifiers.
+    pub attack: f32,
+    /// Damage dealt when striking back at an attacker, before modifiers.
+    pub defence: f32,
+    /// Archetypes this unit deals [`COUNTER_BONUS`] damage to, the archetype's
+    /// [`default_counter`](Archetype::default_counter) if left empty.
+    pub counters: Vec<Archetype>,
+}
+impl Default for CombatStats {
+    fn default() -> Self {
+        Self {
+            archetype: Archetype::Melee,
+            attack: 2.0,
+            defence: 1.0,
+            counters: vec![],
+        }
+    }
+}
+impl CombatStats {
+    pub fn counters(&self, archetype: Archetype) -> bool {
+        if self.counters.is_empty() {
+            self.archetype.default_counter() == archetype
+        } else {
+            self.counters.contains(&archetype)
+        }
+    }
+}
+
+/// Expected outcome of an attack, for showing the odds before committing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CombatPrediction {
+    /// Damage the attacker deals, from the lowest to the highest roll.
+    pub damage: RangeInclusive<f32>,
+    /// Damage the defender deals back, `None` if the attacker is out of its range or the defender
+    /// can't survive the attack.
+    pub counter_damage: Option<RangeInclusive<f32>>,
+    /// Chance the defender is destroyed, from 0 to 1.
+    pub kill_chance: f32,
+}
+
+/// What an attack will do before the dice are rolled.
+struct Engagement {
+    /// Damage dealt to the defender on an average roll.
+    damage: f32,
+    /// Damage the defender deals back at full health on an average roll, if it can reach.
+    counter: Option<f32>,
+    defender_health: f32,
+    defender_max_health: f32,
+}
+impl Engagement {
+    fn counter_damage(&self, defender_health: f32) -> Option<f32> {
+        if defender_health <= 0.0 {
+            return None;
+        }
+        self.counter
+            .map(|c| c * health_factor(defender_health, self.defender_max_health))
+    }
+}
+
+/// The odds of `attacker` attacking `defender`, with the same checks as [`Command::Attack`].
+///
+/// [`Command::Attack`]: crate::Command::Attack
+pub fn predict_combat(
+    game: &Match,
+    board: Board,
+    attacker: UnitId,
+    defender: UnitId,
+) -> Result<CombatPrediction, CommandError> {
+    Ok(predict(&engage(game, board, attacker, defender)?))
+}
+
+/// The odds of `attacker` attacking `settlement`, with the same checks as
+/// [`Command::AttackSettlement`].
+///
+/// [`Command::AttackSettlement`]: crate::Command::AttackSettlement
+pub fn predict_siege(
+    game: &Match,
+    board: Board,
+    attacker: UnitId,
+    settlement: SettlementId,
+) -> Result<CombatPrediction, CommandError> {
+    Ok(predict(&besiege(game, board, attacker, settlement)?))
+}
+
+fn predict(engagement: &Engagement) -> CombatPrediction {
+    let roll = |damage: f32| damage * (1.0 - ROLL_SPREAD)..=damage * (1.0 + ROLL_SPREAD);
+    let damage = roll(engagement.damage);
+    let kill_chance = if engagement.defender_health <= *damage.start() {
+        1.0
+    } else if engagement.defender_health > *damage.end() {
+        0.0
+    } else {
+        (damage.end() - engagement.defender_health) / (damage.end() - damage.start())
+    };
+    // Hardest hit back comes after the weakest attack, and the other way round.
+    let counter_damage = engagement
+        .counter_damage(engagement.defender_health - damage.start())
+        .map(|high| {
+            let low = engagement
+                .counter_damage(engagement.defender_health - damage.end())
+                .unwrap_or(0.0);
+            *roll(low).start()..=*roll(high).end()
+        });
+    CombatPrediction {
+        damage,
+        counter_damage,
+        kill_chance,
+    }
+}
+
+pub(crate) fn resolve(
+    game: &mut Match,
+    board: Board,
+    attacker: UnitId,
+    defender: UnitId,
+    events: &mut Vec<Event>,
+) -> Result<(), CommandError> {
+    let engagement = engage(game, board, attacker, defender)?;
+    let damage = engagement.damage * roll(game);
+    game.damage(defender, damage, events);
+    if let Some(counter) = engagement.counter_damage(engagement.defender_health - damage) {
+        let damage = counter * roll(game);
+        game.damage(attacker, damage, events);
+    }
+    Ok(())
+}
+
+pub(crate) fn resolve_siege(
+    game: &mut Match,
+    board: Board,
+    attacker: UnitId,
+    settlement: SettlementId,
+    events: &mut Vec<Event>,
+) -> Result<(), CommandError> {
+    let engagement = besiege(game, board, attacker, settlement)?;
+    let damage = engagement.damage * roll(game);
+    game.damage_settlement(settlement, damage, events);
+    if let Some(counter) = engagement.counter_damage(engagement.defender_health - damage) {
+        let damage = counter * roll(game);
+        game.damage(attacker, damage, events);
+    }
+    Ok(())
+}
+
+fn roll(game: &mut Match) -> f32 {
+    game.rng.random_range(1.0 - ROLL_SPREAD..=1.0 + ROLL_SPREAD)
+}
+
+fn engage(
+    game: &Match,
+    board: Board,
+    attacker: UnitId,
+    defender: UnitId,
+) -> Result<Engagement, CommandError> {
+    let attacking = game.own_unit(attacker)?;
+    let defending = game
+        .unit(defender)
+        .ok_or(CommandError::UnknownUnit(defender))?;
+    if defending.controller == attacking.controller {
+        return Err(CommandError::FriendlyFire);
+    }
+    let distance = board
+        .cells_in_range(attacking.cell, attacking.range.max(defending.range))
+        .get(&defending.cell)
+        .map(|d| *d as usize)
+        .filter(|d| *d <= attacking.range)
+        .ok_or(CommandError::OutOfRange(defender))?;
+    let flankers = board
+        .world_map
+        .get_neighbours(defending.cell)
+        .into_iter()
+        .filter_map(|cell| game.unit_at(cell))
+        .filter(|u| u.controller == attacking.controller && u.id != attacker)
+        .count()
+        .min(MAX_FLANKERS);
+    let damage = attacking.combat.attack
+        * health_factor(attacking.health, attacking.max_health)
+        * counter_factor(attacking, defending)
+        * height_factor(board, attacking.cell, defending.cell)
+        * (1.0 + FLANKING_BONUS * flankers as f32);
+    let counter = (distance <= defending.range).then(|| {
+        defending.combat.defence
+            * counter_factor(defending, attacking)
+            * height_factor(board, defending.cell, attacking.cell)
+    });
+    Ok(Engagement {
+        damage,
+        counter,
+        defender_health: defending.health,
+        defender_max_health: defending.max_health,
+    })
+}
+
+/// Settlements don't care for archetypes or flanking, but strike back at anyone next to their
+/// walls until they're breached.
+fn besiege(
+    game: &Match,
+    board: Board,
+    attacker: UnitId,
+    settlement: SettlementId,
+) -> Result<Engagement, CommandError> {
+    let attacking = game.own_unit(attacker)?;
+    let defending = game
+        .settlement(settlement)
+        .ok_or(CommandError::UnknownSettlement(settlement))?;
+    if defending.controller == attacking.controller {
+        return Err(CommandError::FriendlyFire);
+    }
+    if defending.breached() {
+        return Err(CommandError::Breached(settlement));
+    }
+    let distance = board
+        .cells_in_range(attacking.cell, attacking.range)
+        .get(&defending.cell)
+        .map(|d| *d as usize)
+        .ok_or(CommandError::SettlementOutOfRange(settlement))?;
+    let damage = attacking.combat.attack
+        * health_factor(attacking.health, attacking.max_health)
+        * height_factor(board, attacking.cell, defending.cell);
+    let counter = (distance <= 1)
+        .then(|| SETTLEMENT_DEFENCE * height_factor(board, defending.cell, attacking.cell));
+    Ok(Engagement {
+        damage,
+        counter,
+        defender_health: defending.health(),
+        defender_max_health: defending.max_health(),
+    })
+}
+
+/// Wounded units hit softer, down to half as hard.
+fn health_factor(health: f32, max_health: f32) -> f32 {
+    0.5 + 0.5 * (health / max_health).clamp(0.0, 1.0)
+}
+
+fn counter_factor(from: &Unit, to: &Unit) -> f32 {
+    if from.combat.counters(to.combat.archetype) {
+        COUNTER_BONUS
+    } else {
+        1.0
+    }
+}
+
+fn height_factor(board: Board, from: CellId, to: CellId) -> f32 {
+    let height = |cell| board.world_map.get_raw_height(&cell).max(WATER_LEVEL);
+    (1.0 + HEIGHT_BONUS * (height(from) - height(to))).clamp(0.5, 1.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinding::Waypoint;
+
+    use super::*;
+    use crate::{
+        Command, ConstructionKind, Player, PlayerId, UnitType,
+        test_support::{self, player, test_board},
+    };
+
+    fn unit_type(archetype: Archetype) -> UnitType {
+        UnitType {
+            health: 20.0,
+            combat: CombatStats {
+                archetype,
+                ..Default::default()
+            },
+            ..test_support::unit_type(&format!("{archetype:?}"))
+        }
+    }
+
+    #[test]
+    fn attacks_land_within_prediction() {
+        let test = test_board(9);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let players = [Archetype::Melee, Archetype::Ranged]
+            .into_iter()
+            .enumerate()
+            .map(|(i, archetype)| Player {
+                unit_types: vec![unit_type(archetype)],
+                ..player(i)
+            })
+            .collect();
+        let mut game = test.new_match(players);
+        let (cell, neighbour) = world_map
+            .iter_cells()
+            .map(|c| CellId(c.site()))
+            .find_map(|cell| {
+                let mut in_range = board
+                    .cells_in_range(cell, 1)
+                    .into_keys()
+                    .collect::<Vec<_>>();
+                in_range.sort();
+                in_range.into_iter().find(|c| *c != cell).map(|n| (cell, n))
+            })
+            .unwrap();
+        let (attacker, defender) = (UnitId(100), UnitId(101));
+        for (id, player, cell) in [(attacker, 0, cell), (defender, 1, neighbour)] {
+            let unit_type = game.players[&PlayerId(player)].unit_types[0].clone();
+            game.units
+                .insert(id, Unit::new(id, &unit_type, PlayerId(player), cell));
+        }
+        let prediction = predict_combat(&game, board, attacker, defender).unwrap();
+        let mut replay = game.clone();
+        let command = Command::Attack { attacker, defender };
+        let events = game.apply(board, command.clone()).unwrap();
+        let damage = |unit: UnitId| {
+            events.iter().find_map(|e| match e {
+                Event::UnitDamaged {
+                    unit: u, damage, ..
+                } if *u == unit => Some(*damage),
+                _ => None,
+            })
+        };
+        assert!(prediction.damage.contains(&damage(defender).unwrap()));
+        assert!(
+            prediction
+                .counter_damage
+                .unwrap()
+                .contains(&damage(attacker).unwrap())
+        );
+        // Same state, same rolls.
+        assert_eq!(replay.apply(board, command).unwrap(), events);
+    }
+
+    #[test]
+    fn breached_settlements_are_captured() {
+        let test = test_board(14);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let mut siege_engine = unit_type(Archetype::Melee);
+        siege_engine.combat.attack = 100.0;
+        let players = (0..2)
+            .map(|i| Player {
+                unit_types: vec![siege_engine.clone()],
+                ..player(i)
+            })
+            .collect();
+        let mut game = test.new_match(players);
+        let costs = siege_engine.movement.costs();
+        let (site, outside) = world_map
+            .get_valid_settlement_cells()
+            .into_iter()
+            .find_map(|site| {
+                world_map
+                    .get_neighbours(site)
+                    .into_iter()
+                    .find(|n| board.step_cost(*n, site, &costs).is_some())
+                    .map(|n| (site, n))
+            })
+            .unwrap();
+        let settlement = game.found_settlement(PlayerId(1), "Keep".to_string(), site, vec![]);
+        let attacker = UnitId(100);
+        game.units.insert(
+            attacker,
+            Unit::new(attacker, &siege_engine, PlayerId(0), outside),
+        );
+        let walk_in = Command::MoveUnit {
+            unit: attacker,
+            goal: Some(site),
+        };
+        // The walls hold the unit back until they're down.
+        let events = game.apply(board, walk_in.clone()).unwrap();
+        assert!(events.is_empty());
+        assert_eq!(game.units[&attacker].cell, outside);
+
+        game.apply(
+            board,
+            Command::AttackSettlement {
+                attacker,
+                settlement,
+            },
+        )
+        .unwrap();
+        assert!(game.settlement(settlement).unwrap().breached());
+        let events = game.apply(board, walk_in).unwrap();
+        assert!(events.contains(&Event::SettlementCaptured {
+            settlement,
+            from: PlayerId(1),
+            to: PlayerId(0),
+        }));
+        let captured = game.settlement(settlement).unwrap();
+        assert_eq!(captured.controller, PlayerId(0));
+        assert!(!captured.breached());
+        assert!(
+            captured
+                .available_constructions
+                .iter()
+                .any(|c| c.kind == ConstructionKind::Unit(siege_engine.name.clone()))
+        );
+    }
+
+    #[test]
+    fn refused_steps_into_breached_settlements_capture_nothing() {
+        let test = test_board(14);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let mut siege_engine = unit_type(Archetype::Melee);
+        siege_engine.combat.attack = 100.0;
+        let players = (0..2)
+            .map(|i| Player {
+                unit_types: vec![siege_engine.clone()],
+                ..player(i)
+            })
+            .collect();
+        let mut game = test.new_match(players);
+        let site = world_map.get_valid_settlement_cells()[0];
+        let outside = world_map.get_neighbours(site)[0];
+        let settlement = game.found_settlement(PlayerId(1), "Keep".to_string(), site, vec![]);
+        let attacker = UnitId(100);
+        game.units.insert(
+            attacker,
+            Unit::new(attacker, &siege_engine, PlayerId(0), outside),
+        );
+        game.apply(
+            board,
+            Command::AttackSettlement {
+                attacker,
+                settlement,
+            },
+        )
+        .unwrap();
+        assert!(game.settlement(settlement).unwrap().breached());
+        // A route planned before the terrain turned impassable for the unit, its last step into
+        // the settlement now refused.
+        let unit = game.units.get_mut(&attacker).unwrap();
+        unit.movement.max_slope = 0.0;
+        unit.goal = Some(site);
+        unit.route = [Waypoint {
+            cell: site,
+            turn: game.turn,
+        }]
+        .into();
+        let mut events = vec![];
+        game.advance_unit(board, attacker, &mut events);
+        assert!(
+            !events
+                .iter()
+                .any(|e| matches!(e, Event::SettlementCaptured { .. }))
+        );
+        assert_eq!(game.units[&attacker].cell, outside);
+        assert_eq!(game.settlement(settlement).unwrap().controller, PlayerId(1));
+    }
+}
@@ -0,0 +1,210 @@
+//! What the land gives the settlements controlling it.
+use std::ops::{Add, AddAssign, Mul};
+
+use pathfinding::WATER_LEVEL;
+use serde::{Deserialize, Serialize};
+use world_generation::{CellId, WorldMap};
+
+/// What one unit of each resource yields.
+const RESOURCE_YIELDS: [(&str, Yields); 3] = [
+    (
+        "Grain",
+        Yields {
+            food: 2.0,
+            production: 0.0,
+            materials: 0.0,
+        },
+    ),
+    (
+        "Forest",
+        Yields {
+            food: 0.25,
+            production: 1.0,
+            materials: 0.0,
+        },
+    ),
+    (
+        "Metal",
+        Yields {
+            food: 0.0,
+            production: 0.25,
+            materials: 1.0,
+        },
+    ),
+];
+/// Fish, the only thing water cells yield.
+const WATER_YIELDS: Yields = Yields {
+    food: 0.5,
+    production: 0.0,
+    materials: 0.0,
+};
+/// Extra food from a land cell a river runs through.
+const RIVER_YIELDS: Yields = Yields {
+    food: 0.5,
+    production: 0.0,
+    materials: 0.0,
+};
+/// Food each cell a settlement controls eats every turn, the centre included.
+pub const FOOD_UPKEEP: f32 = 0.25;
+/// Stored food a settlement needs per cell it controls before it grows.
+pub const GROWTH_FOOD: f32 = 1.5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Yields {
+    /// Stored by the settlement until it grows.
+    pub food: f32,
+    /// Goes into the settlement's construction.
+    pub production: f32,
+    /// Added to the controller's stockpile.
+    pub materials: f32,
+}
+impl Add for Yields {
+    type Output = Yields;
+    fn add(self, rhs: Yields) -> Yields {
+        Yields {
+            food: self.food + rhs.food,
+            production: self.production + rhs.production,
+            materials: self.materials + rhs.materials,
+        }
+    }
+}
+impl AddAssign for Yields {
+    fn add_assign(&mut self, rhs: Yields) {
+        *self = *self + rhs;
+    }
+}
+impl Mul<f32> for Yields {
+    type Output = Yields;
+    fn mul(self, rhs: f32) -> Yields {
+        Yields {
+            food: self.food * rhs,
+            production: self.production * rhs,
+            materials: self.materials * rhs,
+        }
+    }
+}
+
+/// What a cell gives the settlement controlling it each turn.
+pub fn cell_yields(world_map: &WorldMap, cell: CellId) -> Yields {
+    if world_map.get_raw_height(&cell) < WATER_LEVEL {
+        return WATER_YIELDS;
+    }
+    let river = if world_map.has_river(cell) {
+        RIVER_YIELDS
+    } else {
+        Yields::default()
+    };
+    let Some(resources) = world_map.get_resources_for_cell(cell) else {
+        return river;
+    };
+    RESOURCE_YIELDS
+        .iter()
+        .filter_map(|(name, yields)| resources.get(*name).map(|amount| *yields * *amount))
+        .fold(river, Add::add)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Command, Event, PlayerId,
+        test_support::{player, test_board},
+    };
+
+    #[test]
+    fn settlements_stockpile_their_yields() {
+        let test = test_board(13);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let mut game = test.new_match(vec![player(0)]);
+        let cell = world_map
+            .get_valid_settlement_cells()
+            .first()
+            .copied()
+            .unwrap_or(CellId(0));
+        let controlled_cells = world_map.get_neighbours(cell);
+        let settlement = game.found_settlement(
+            PlayerId(0),
+            "Home".to_string(),
+            cell,
+            controlled_cells.clone(),
+        );
+        let yields = game.settlement_yields(board, settlement);
+        let expected = controlled_cells
+            .iter()
+            .map(|c| cell_yields(&world_map, *c))
+            .fold(cell_yields(&world_map, cell), Add::add);
+        assert_eq!(yields.materials, expected.materials);
+        assert_eq!(yields.production, expected.production + 1.0);
+
+        game.apply(board, Command::EndTurn).unwrap();
+        assert_eq!(
+            game.player(PlayerId(0)).unwrap().materials,
+            yields.materials
+        );
+        let settlement = game.settlement(settlement).unwrap();
+        assert_eq!(
+            settlement.food_upkeep(),
+            FOOD_UPKEEP * (controlled_cells.len() + 1) as f32
+        );
+    }
+
+    #[test]
+    fn settlements_grow_once_they_store_enough_food() {
+        let test = test_board(11);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let mut game = test.new_match(vec![player(0)]);
+        let is_water = |cell: &CellId| world_map.get_raw_height(cell) < WATER_LEVEL;
+        // Fish are the only yields on water, so a settlement out at sea stores an exact amount.
+        let (cell, fishery) = world_map
+            .iter_cells()
+            .map(|c| CellId(c.site()))
+            .filter(is_water)
+            .find_map(|cell| {
+                world_map
+                    .get_neighbours(cell)
+                    .into_iter()
+                    .filter(is_water)
+                    .find(|n| {
+                        world_map.get_neighbours(*n).iter().any(|next| {
+                            *next != cell && is_water(next) && board.distance(cell, *next).is_some()
+                        })
+                    })
+                    .map(|n| (cell, n))
+            })
+            .unwrap();
+        let settlement =
+            game.found_settlement(PlayerId(0), "Port".to_string(), cell, vec![fishery]);
+        // Two cells of fish less two cells of upkeep leaves 0.5 a turn towards the 3.0 growth needs.
+        assert_eq!(
+            game.settlement_yields(board, settlement).food,
+            2.0 * WATER_YIELDS.food
+        );
+        assert_eq!(
+            game.settlement(settlement).unwrap().food_upkeep(),
+            2.0 * FOOD_UPKEEP
+        );
+        assert_eq!(
+            game.settlement(settlement).unwrap().growth_food(),
+            2.0 * GROWTH_FOOD
+        );
+        for turn in 1..=5 {
+            let events = game.apply(board, Command::EndTurn).unwrap();
+            assert!(
+                !events
+                    .iter()
+                    .any(|e| matches!(e, Event::SettlementGrew { .. }))
+            );
+            assert_eq!(game.settlement(settlement).unwrap().food, 0.5 * turn as f32);
+        }
+        let events = game.apply(board, Command::EndTurn).unwrap();
+        assert!(
+            events.iter().any(
+                |e| matches!(e, Event::SettlementGrew { settlement: s, .. } if *s == settlement)
+            )
+        );
+        let grown = game.settlement(settlement).unwrap();
+        assert_eq!(grown.food, 0.0);
+        assert_eq!(grown.controlled_cells.len(), 2);
+    }
+}
@@ -0,0 +1,116 @@
+//! Structures settlements build on the cells they control to get more out of their resources.
+use serde::{Deserialize, Serialize};
+use world_generation::{CellId, WorldMap};
+
+use crate::Yields;
+
+/// Something a settlement can build on one of its cells, as described in an `.improvement.ron`
+/// file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Improvement {
+    /// What [`Building`](crate::Unlock::Building) unlocks and commands refer to the improvement
+    /// by. Improvements no tech unlocks can be built from the start.
+    pub name: String,
+    /// The resource it extracts, one of the names resource maps are built with.
+    pub resource: String,
+    pub cost: f32,
+    #[serde(default)]
+    pub materials: f32,
+    /// Added to the cell's yields for each unit of `resource` on it.
+    pub yields: Yields,
+    /// Model shown on the improved cell, a plain marker if left out.
+    #[serde(default)]
+    pub mesh_path: Option<String>,
+    #[serde(default)]
+    pub description: String,
+}
+impl Improvement {
+    /// What the improvement adds to `cell`, nothing if the cell lacks its resource.
+    pub fn yields_on(&self, world_map: &WorldMap, cell: CellId) -> Yields {
+        world_map
+            .get_resources_for_cell(cell)
+            .and_then(|r| r.get(&self.resource))
+            .map_or(Yields::default(), |amount| self.yields * *amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Command, Event, Match, PlayerId, TechTree, cell_yields,
+        test_support::{player, test_board},
+    };
+
+    #[test]
+    fn improvements_raise_settlement_yields() {
+        let test = test_board(21);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let farm = Improvement {
+            name: "Farm".to_string(),
+            resource: "Grain".to_string(),
+            cost: 1.0,
+            materials: 0.0,
+            yields: Yields {
+                food: 1.0,
+                ..Default::default()
+            },
+            mesh_path: None,
+            description: String::new(),
+        };
+        let mut game = Match::new(
+            vec![player(0)],
+            TechTree::default(),
+            vec![farm],
+            test.rng.clone(),
+        );
+        let (cell, grain) = world_map
+            .get_valid_settlement_cells()
+            .into_iter()
+            .find_map(|cell| {
+                world_map
+                    .get_neighbours(cell)
+                    .into_iter()
+                    .find(|n| {
+                        world_map
+                            .get_resources_for_cell(*n)
+                            .is_some_and(|r| r.get("Grain").is_some_and(|g| *g > 0.0))
+                    })
+                    .map(|n| (cell, n))
+            })
+            .unwrap();
+        let settlement = game.found_settlement(PlayerId(0), "Home".to_string(), cell, vec![grain]);
+        let farm_on = |cell| Command::BuildImprovement {
+            settlement,
+            improvement: "Farm".to_string(),
+            cell,
+        };
+        // The settlement's own cell isn't one it controls.
+        assert!(game.apply(board, farm_on(cell)).is_err());
+        game.apply(board, farm_on(grain)).unwrap();
+        let events = game.apply(board, Command::EndTurn).unwrap();
+        assert!(events.contains(&Event::ImprovementBuilt {
+            settlement,
+            cell: grain,
+            improvement: "Farm".to_string(),
+        }));
+        // The settlement may have grown as well, so compare against the cells it has now.
+        let unimproved = game
+            .settlement(settlement)
+            .unwrap()
+            .controlled_cells
+            .iter()
+            .map(|c| cell_yields(&world_map, *c).food)
+            .sum::<f32>()
+            + cell_yields(&world_map, cell).food;
+        let bonus = world_map.get_resources_for_cell(grain).unwrap()["Grain"];
+        let food = game.settlement_yields(board, settlement).food;
+        assert!((food - unimproved - bonus).abs() < 1e-4);
+        assert!(
+            !game
+                .buildable_improvements(board, settlement)
+                .iter()
+                .any(|(c, _)| *c == grain)
+        );
+    }
+}
@@ -0,0 +1,1175 @@
+#![forbid(unsafe_code)]
+//! The rules of a match, with no rendering or input attached.
+//!
+//! A [`Match`] is plain data. Everything that changes it goes through [`Match::apply`], which
+//! returns the [`Event`]s a presentation layer needs to catch up.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use pathfinding::{Distance, MovementCostModel, Steps, Waypoint};
+use petgraph::{Graph, graph::NodeIndex};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use world_generation::{CellId, WorldMap};
+
+pub mod ai;
+mod combat;
+mod economy;
+mod improvement;
+mod settlement;
+mod tech;
+#[cfg(test)]
+pub(crate) mod test_support;
+mod undo;
+mod unit;
+mod victory;
+mod visibility;
+pub use combat::{Archetype, CombatPrediction, CombatStats, predict_combat, predict_siege};
+pub use economy::{FOOD_UPKEEP, GROWTH_FOOD, Yields, cell_yields};
+pub use improvement::Improvement;
+pub use settlement::{
+    Construction, ConstructionKind, MIN_SETTLEMENT_DISTANCE, SETTLEMENT_DEFENCE, SETTLEMENT_HEALTH,
+    Settlement,
+};
+pub use tech::{RESEARCH_PER_SETTLEMENT, Research, Tech, TechTree, Unlock};
+pub use unit::{Ability, Unit, UnitType};
+pub use victory::{
+    Conquest, DEFAULT_TERRITORY_SHARE, DEFAULT_TURN_LIMIT, Outcome, PlayerStats, Territorial,
+    TurnLimit, Victory, VictoryCondition,
+};
+pub use visibility::{CellVisibility, DEFAULT_SIGHT, Visibility, sight_range};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PlayerId(pub usize);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct UnitId(pub u64);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SettlementId(pub u64);
+
+/// The terrain a match is played on, along with the movement graph built from it.
+#[derive(Clone, Copy)]
+pub struct Board<'a> {
+    pub world_map: &'a WorldMap,
+    pub graph: &'a Graph<CellId, f32>,
+    pub nodes: &'a HashMap<CellId, NodeIndex>,
+}
+impl Board<'_> {
+    /// Cheapest path from `start` to `goal` under `costs`, both included.
+    pub fn path(
+        &self,
+        start: CellId,
+        goal: CellId,
+        costs: &impl MovementCostModel,
+    ) -> Option<Vec<CellId>> {
+        let mut path =
+            pathfinding::a_star(start, goal, self.graph, self.nodes, self.world_map, costs)?;
+        path.reverse();
+        Some(path)
+    }
+    /// Movement spent stepping between two neighbouring cells, `None` if the step isn't allowed.
+    pub fn step_cost(
+        &self,
+        from: CellId,
+        to: CellId,
+        costs: &impl MovementCostModel,
+    ) -> Option<f32> {
+        costs.step_cost(self.world_map, from, to)
+    }
+    /// Every cell within `range` steps of `cell` ignoring terrain, with the number of steps.
+    pub fn cells_in_range(&self, cell: CellId, range: usize) -> HashMap<CellId, f32> {
+        pathfinding::reachable_within(
+            cell,
+            range as f32,
+            self.graph,
+            self.nodes,
+            self.world_map,
+            &Steps,
+        )
+    }
+    /// Number of steps between two cells ignoring terrain, `None` if one can't be reached from
+    /// the other.
+    pub fn distance(&self, from: CellId, to: CellId) -> Option<usize> {
+        self.path(from, to, &Distance).map(|p| p.len() - 1)
+    }
+    fn is_on_map(&self, cell: CellId) -> bool {
+        self.nodes.contains_key(&cell)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Player {
+    pub id: PlayerId,
+    /// Position in the turn order.
+    pub order: usize,
+    pub unit_types: Vec<UnitType>,
+    #[serde(default)]
+    pub research: Research,
+    /// Materials stockpiled from every settlement.
+    #[serde(default)]
+    pub materials: f32,
+}
+impl Player {
+    /// Whether the tech a unit type needs, if any, has been researched.
+    pub fn can_build(&self, unit_type: &UnitType) -> bool {
+        unit_type
+            .requires
+            .as_ref()
+            .is_none_or(|tech| self.research.has(tech))
+    }
+}
+
+/// Something a player asks to happen, always on behalf of the active player.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Command {
+    /// Sets or clears where a unit is heading, it moves as far as it can straight away.
+    MoveUnit {
+        unit: UnitId,
+        goal: Option<CellId>,
+    },
+    Attack {
+        attacker: UnitId,
+        defender: UnitId,
+    },
+    /// Attacks an enemy settlement's walls. Once they're down, moving a unit in captures it.
+    AttackSettlement {
+        attacker: UnitId,
+        settlement: SettlementId,
+    },
+    /// Burns down a breached enemy settlement next to `unit` instead of capturing it.
+    RazeSettlement {
+        unit: UnitId,
+        settlement: SettlementId,
+    },
+    /// Queues one of the settlement's `available_constructions` by index.
+    SetConstruction {
+        settlement: SettlementId,
+        construction: usize,
+    },
+    /// Queues an improvement on one of the settlement's controlled cells.
+    BuildImprovement {
+        settlement: SettlementId,
+        improvement: String,
+        cell: CellId,
+    },
+    /// Uses up a unit that can found settlements to found one on its cell.
+    FoundSettlement {
+        unit: UnitId,
+        name: String,
+    },
+    /// Switches what the active player researches, progress on the previous tech is lost.
+    SetResearch {
+        tech: String,
+    },
+    /// Takes back the last move or construction order this turn, see [`Match::can_undo`].
+    Undo,
+    EndTurn,
+}
+
+/// Something that happened to a [`Match`] as the result of a [`Command`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    TurnStarted {
+        player: PlayerId,
+        turn: u32,
+    },
+    UnitMoved {
+        unit: UnitId,
+        from: CellId,
+        to: CellId,
+    },
+    GoalReached {
+        unit: UnitId,
+    },
+    UnitDamaged {
+        unit: UnitId,
+        damage: f32,
+        health: f32,
+    },
+    UnitDestroyed {
+        unit: UnitId,
+    },
+    ConstructionStarted {
+        settlement: SettlementId,
+        kind: ConstructionKind,
+    },
+    ConstructionCompleted {
+        settlement: SettlementId,
+        kind: ConstructionKind,
+    },
+    /// A unit finished but every neighbouring cell is occupied, it will try again next turn.
+    SpawnBlocked {
+        settlement: SettlementId,
+    },
+    UnitSpawned {
+        unit: UnitId,
+        settlement: SettlementId,
+    },
+    SettlementGrew {
+        settlement: SettlementId,
+        cell: CellId,
+    },
+    SettlementDamaged {
+        settlement: SettlementId,
+        damage: f32,
+        health: f32,
+    },
+    SettlementCaptured {
+        settlement: SettlementId,
+        from: PlayerId,
+        to: PlayerId,
+    },
+    /// The settlement is gone, `name` is what it was called.
+    SettlementRazed {
+        settlement: SettlementId,
+        name: String,
+        from: PlayerId,
+        by: PlayerId,
+    },
+    /// A unit was used up founding a new settlement.
+    SettlementFounded {
+        settlement: SettlementId,
+        unit: UnitId,
+    },
+    /// A move was taken back, the unit is on `cell` again.
+    MoveUndone {
+        unit: UnitId,
+        cell: CellId,
+    },
+    /// The settlement's construction order was taken back.
+    ConstructionUndone {
+        settlement: SettlementId,
+    },
+    ImprovementBuilt {
+        settlement: SettlementId,
+        cell: CellId,
+        improvement: String,
+    },
+    ResearchStarted {
+        player: PlayerId,
+        tech: String,
+    },
+    TechResearched {
+        player: PlayerId,
+        tech: String,
+    },
+    /// The match is over, no more commands are accepted.
+    MatchWon {
+        outcome: Outcome,
+    },
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error, PartialEq)]
+pub enum CommandError {
+    #[error("There is no unit {0:?}")]
+    UnknownUnit(UnitId),
+    #[error("There is no settlement {0:?}")]
+    UnknownSettlement(SettlementId),
+    #[error("Unit {0:?} is not controlled by the active player")]
+    NotOwnUnit(UnitId),
+    #[error("Settlement {0:?} is not controlled by the active player")]
+    NotOwnSettlement(SettlementId),
+    #[error("Units can't attack their own side")]
+    FriendlyFire,
+    #[error("Settlement has no construction {0}")]
+    UnknownConstruction(usize),
+    #[error("Cell {0:?} is not on the map")]
+    OffMap(CellId),
+    #[error("Cell {0:?} can't be reached")]
+    Unreachable(CellId),
+    #[error("Unit {0:?} is out of range")]
+    OutOfRange(UnitId),
+    #[error("Not enough materials, {0} are needed")]
+    NotEnoughMaterials(f32),
+    #[error("Settlement {0:?} is out of range")]
+    SettlementOutOfRange(SettlementId),
+    #[error("The walls of settlement {0:?} are already down")]
+    Breached(SettlementId),
+    #[error("The walls of settlement {0:?} are still standing")]
+    NotBreached(SettlementId),
+    #[error("Unit {0:?} can't found settlements")]
+    CantFoundSettlement(UnitId),
+    #[error("Cell {0:?} isn't flat dry land")]
+    InvalidSettlementSite(CellId),
+    #[error("Cell {0:?} is too close to another settlement")]
+    TooCloseToSettlement(CellId),
+    #[error("There is no improvement {0}")]
+    UnknownImprovement(String),
+    #[error("Improvement {0} can't be built on cell {1:?}")]
+    CantImprove(String, CellId),
+    #[error("There is no tech {0}")]
+    UnknownTech(String),
+    #[error("Tech {0} is already researched or needs other techs first")]
+    TechUnavailable(String),
+    #[error("There is nothing to undo this turn")]
+    NothingToUndo,
+    #[error("The match is over")]
+    MatchOver,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Match {
+    players: BTreeMap<PlayerId, Player>,
+    units: BTreeMap<UnitId, Unit>,
+    settlements: BTreeMap<SettlementId, Settlement>,
+    #[serde(default)]
+    tech_tree: TechTree,
+    #[serde(default)]
+    improvements: Vec<Improvement>,
+    /// Checked at the start of every turn, the match never ends without any.
+    #[serde(default)]
+    victories: Vec<Victory>,
+    #[serde(default)]
+    outcome: Option<Outcome>,
+    /// Cells each player has seen so far.
+    #[serde(default)]
+    explored: BTreeMap<PlayerId, BTreeSet<CellId>>,
+    /// What the active player can take back this turn, latest last.
+    #[serde(default)]
+    undo: Vec<undo::UndoStep>,
+    active_player: PlayerId,
+    turn: u32,
+    next_id: u64,
+    /// Rules that roll dice draw from here, so a match replays identically from the same state.
+    rng: ChaCha20Rng,
+}
+impl Match {
+    /// Starts a match on turn 1 with the player first in turn order active.
+    pub fn new(
+        players: Vec<Player>,
+        tech_tree: TechTree,
+        improvements: Vec<Improvement>,
+        rng: ChaCha20Rng,
+    ) -> Self {
+        let active_player = players
+            .iter()
+            .min_by_key(|p| p.order)
+            .expect("A match needs at least one player")
+            .id;
+        Self {
+            players: players.into_iter().map(|p| (p.id, p)).collect(),
+            units: BTreeMap::new(),
+            settlements: BTreeMap::new(),
+            tech_tree,
+            improvements,
+            victories: vec![],
+            outcome: None,
+            explored: BTreeMap::new(),
+            undo: vec![],
+            active_player,
+            turn: 1,
+            next_id: 0,
+            rng,
+        }
+    }
+    pub fn set_victories(&mut self, victories: Vec<Victory>) {
+        self.victories = victories;
+    }
+    pub fn victories(&self) -> &[Victory] {
+        &self.victories
+    }
+    /// How the match ended, `None` while it's still being played.
+    pub fn outcome(&self) -> Option<&Outcome> {
+        self.outcome.as_ref()
+    }
+    /// Whether the active player has a move or construction order they can take back.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+    /// What `player` sees of the map right now and has seen before.
+    pub fn visibility(&self, board: Board, player: PlayerId) -> Visibility {
+        let visible = visibility::visible_cells(self, board, player);
+        let mut explored = self.explored.get(&player).cloned().unwrap_or_default();
+        explored.extend(visible.iter().copied());
+        Visibility { visible, explored }
+    }
+    pub fn stats(&self, player: PlayerId) -> PlayerStats {
+        let settlements = self.settlements.values().filter(|s| s.controller == player);
+        PlayerStats {
+            settlements: settlements.clone().count(),
+            cells: settlements.map(|s| s.controlled_cells.len() + 1).sum(),
+            units: self
+                .units
+                .values()
+                .filter(|u| u.controller == player)
+                .count(),
+            techs: self
+                .players
+                .get(&player)
+                .map_or(0, |p| p.research.researched.len()),
+        }
+    }
+    pub fn players(&self) -> impl Iterator<Item = &Player> {
+        self.players.values()
+    }
+    pub fn player(&self, id: PlayerId) -> Option<&Player> {
+        self.players.get(&id)
+    }
+    pub fn units(&self) -> impl Iterator<Item = &Unit> {
+        self.units.values()
+    }
+    pub fn unit(&self, id: UnitId) -> Option<&Unit> {
+        self.units.get(&id)
+    }
+    pub fn unit_at(&self, cell: CellId) -> Option<&Unit> {
+        self.units.values().find(|u| u.cell == cell)
+    }
+    pub fn settlements(&self) -> impl Iterator<Item = &Settlement> {
+        self.settlements.values()
+    }
+    pub fn settlement(&self, id: SettlementId) -> Option<&Settlement> {
+        self.settlements.get(&id)
+    }
+    /// What a settlement makes each turn from its own work, its cells and its controller's techs.
+    pub fn settlement_yields(&self, board: Board, id: SettlementId) -> Yields {
+        let Some(settlement) = self.settlements.get(&id) else {
+            return Yields::default();
+        };
+        let bonus = self
+            .players
+            .get(&settlement.controller)
+            .map_or(0.0, |p| self.tech_tree.production_bonus(&p.research));
+        let own = Yields {
+            production: settlement.production + bonus,
+            ..Default::default()
+        };
+        let improved = settlement.improvements.iter().filter_map(|(cell, name)| {
+            self.improvement(name)
+                .map(|i| i.yields_on(board.world_map, *cell))
+        });
+        [settlement.cell]
+            .iter()
+            .chain(settlement.controlled_cells.iter())
+            .map(|cell| cell_yields(board.world_map, *cell))
+            .chain(improved)
+            .fold(own, |total, cell| total + cell)
+    }
+    pub fn tech_tree(&self) -> &TechTree {
+        &self.tech_tree
+    }
+    pub fn improvements(&self) -> &[Improvement] {
+        &self.improvements
+    }
+    pub fn improvement(&self, name: &str) -> Option<&Improvement> {
+        self.improvements.iter().find(|i| i.name == name)
+    }
+    /// Every improvement the settlement's controller could queue, with the cell it would go on.
+    /// Each cell holds one improvement, and only if it has the improvement's resource.
+    pub fn buildable_improvements(
+        &self,
+        board: Board,
+        id: SettlementId,
+    ) -> Vec<(CellId, &Improvement)> {
+        let Some(settlement) = self.settlements.get(&id) else {
+            return vec![];
+        };
+        let Some(player) = self.players.get(&settlement.controller) else {
+            return vec![];
+        };
+        let improvements = self
+            .improvements
+            .iter()
+            .filter(|i| self.tech_tree.building_available(&player.research, &i.name))
+            .collect::<Vec<_>>();
+        settlement
+            .controlled_cells
+            .iter()
+            .filter(|cell| !settlement.improvements.contains_key(cell))
+            .flat_map(|cell| improvements.iter().map(move |i| (*cell, *i)))
+            .filter(|(cell, i)| {
+                board
+                    .world_map
+                    .get_resources_for_cell(*cell)
+                    .and_then(|r| r.get(&i.resource))
+                    .is_some_and(|amount| *amount > 0.0)
+            })
+            .collect()
+    }
+    pub fn active_player(&self) -> PlayerId {
+        self.active_player
+    }
+    /// The current round, incremented each time play returns to the first player.
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+    /// Whether every unit of the active player has been given something to do and every
+    /// settlement is building something.
+    pub fn turn_ready_to_end(&self) -> bool {
+        let units_used = self
+            .units
+            .values()
+            .filter(|u| u.controller == self.active_player)
+            .all(|u| u.goal.is_some() || u.used_speed > 0.0);
+        let settlements_busy = self
+            .settlements
+            .values()
+            .filter(|s| s.controller == self.active_player)
+            .all(|s| s.construction.is_some());
+        units_used && settlements_busy
+    }
+    /// The route `unit` would take to `goal`, split into the turns it spends getting there.
+    pub fn plan_route(&self, board: Board, unit: UnitId, goal: CellId) -> Option<Vec<Waypoint>> {
+        let unit = self.units.get(&unit)?;
+        let path = board.path(unit.cell, goal, &unit.movement)?;
+        pathfinding::split_into_turns(
+            &path,
+            board.world_map,
+            &unit.movement,
+            unit.speed,
+            unit.used_speed,
+            self.turn,
+        )
+    }
+    /// Every cell `unit` can still get to this turn, with what it costs to get there.
+    pub fn reachable_cells(&self, board: Board, unit: UnitId) -> HashMap<CellId, f32> {
+        let Some(unit) = self.units.get(&unit) else {
+            return HashMap::new();
+        };
+        let mut cells = pathfinding::reachable_within(
+            unit.cell,
+            unit.speed - unit.used_speed,
+            board.graph,
+            board.nodes,
+            board.world_map,
+            &unit.movement,
+        );
+        // A unit that hasn't moved yet can always take one step, see `split_into_turns`.
+        if unit.used_speed == 0.0 {
+            for next in board.world_map.get_neighbours(unit.cell) {
+                if let Some(cost) = board.step_cost(unit.cell, next, &unit.movement) {
+                    cells.entry(next).or_insert(cost);
+                }
+            }
+        }
+        cells
+    }
+    /// Whether a new settlement can be founded on `cell`: flat dry land, outside every other
+    /// settlement's borders, at least [`MIN_SETTLEMENT_DISTANCE`] steps from their centres and
+    /// with at least one neighbour left for its own borders.
+    pub fn check_settlement_site(&self, board: Board, cell: CellId) -> Result<(), CommandError> {
+        if !board.world_map.get_valid_settlement_cells().contains(&cell) {
+            return Err(CommandError::InvalidSettlementSite(cell));
+        }
+        let nearby = board.cells_in_range(cell, MIN_SETTLEMENT_DISTANCE - 1);
+        if self
+            .settlements
+            .values()
+            .any(|s| nearby.contains_key(&s.cell) || s.controlled_cells.contains(&cell))
+            || self.unclaimed_neighbours(board, cell).is_empty()
+        {
+            return Err(CommandError::TooCloseToSettlement(cell));
+        }
+        Ok(())
+    }
+    /// Every cell that passes [`check_settlement_site`](Self::check_settlement_site).
+    pub fn settlement_sites(&self, board: Board) -> Vec<CellId> {
+        let crowded = self
+            .settlements
+            .values()
+            .flat_map(|s| {
+                board
+                    .cells_in_range(s.cell, MIN_SETTLEMENT_DISTANCE - 1)
+                    .into_keys()
+                    .chain(s.controlled_cells.iter().copied())
+            })
+            .collect::<HashSet<_>>();
+        board
+            .world_map
+            .get_valid_settlement_cells()
+            .into_iter()
+            .filter(|c| !crowded.contains(c) && !self.unclaimed_neighbours(board, *c).is_empty())
+            .collect()
+    }
+    /// The neighbours of `cell` that aren't a settlement or inside one's borders, which a
+    /// settlement founded there starts out controlling.
+    fn unclaimed_neighbours(&self, board: Board, cell: CellId) -> Vec<CellId> {
+        board
+            .world_map
+            .get_neighbours(cell)
+            .into_iter()
+            .filter(|n| {
+                !self
+                    .settlements
+                    .values()
+                    .any(|s| s.cell == *n || s.controlled_cells.contains(n))
+            })
+            .collect()
+    }
+    /// Places a settlement during match setup.
+    pub fn found_settlement(
+        &mut self,
+        controller: PlayerId,
+        name: String,
+        cell: CellId,
+        controlled_cells: Vec<CellId>,
+    ) -> SettlementId {
+        let id = SettlementId(self.next_id());
+        let unit_types = self.buildable_unit_types(controller);
+        let settlement = Settlement::new(id, name, controller, cell, controlled_cells, &unit_types);
+        self.settlements.insert(id, settlement);
+        id
+    }
+    pub fn apply(&mut self, board: Board, command: Command) -> Result<Vec<Event>, CommandError> {
+        if self.outcome.is_some() {
+            return Err(CommandError::MatchOver);
+        }
+        let mut events = vec![];
+        let undo_step = undo::step(self, &command);
+        let sighting = undo_step.as_ref().map(|_| undo::Sighting::new(self, board));
+        let keeps_history = matches!(command, Command::SetResearch { .. } | Command::Undo);
+        match command {
+            Command::MoveUnit { unit, goal } => {
+                self.own_unit(unit)?;
+                if let Some(goal) = goal
+                    && !board.is_on_map(goal)
+                {
+                    return Err(CommandError::OffMap(goal));
+                }
+                let moving = self.units.get_mut(&unit).unwrap();
+                moving.goal = goal;
+                moving.route.clear();
+                self.advance_unit(board, unit, &mut events);
+            }
+            Command::Attack { attacker, defender } => {
+                combat::resolve(self, board, attacker, defender, &mut events)?;
+            }
+            Command::AttackSettlement {
+                attacker,
+                settlement,
+            } => {
+                combat::resolve_siege(self, board, attacker, settlement, &mut events)?;
+            }
+            Command::RazeSettlement { unit, settlement } => {
+                let by = self.own_unit(unit)?.controller;
+                let cell = self.own_unit(unit)?.cell;
+                let target = self
+                    .settlements
+                    .get(&settlement)
+                    .ok_or(CommandError::UnknownSettlement(settlement))?;
+                if target.controller == by {
+                    return Err(CommandError::FriendlyFire);
+                }
+                if !target.breached() {
+                    return Err(CommandError::NotBreached(settlement));
+                }
+                if !board.cells_in_range(cell, 1).contains_key(&target.cell) {
+                    return Err(CommandError::SettlementOutOfRange(settlement));
+                }
+                let from = target.controller;
+                let name = self.settlements.remove(&settlement).unwrap().name;
+                events.push(Event::SettlementRazed {
+                    settlement,
+                    name,
+                    from,
+                    by,
+                });
+            }
+            Command::SetConstruction {
+                settlement,
+                construction,
+            } => {
+                let job = self
+                    .own_settlement(settlement)?
+                    .available_constructions
+                    .get(construction)
+                    .ok_or(CommandError::UnknownConstruction(construction))?
+                    .clone();
+                self.queue_construction(settlement, job, &mut events)?;
+            }
+            Command::BuildImprovement {
+                settlement,
+                improvement,
+                cell,
+            } => {
+                self.own_settlement(settlement)?;
+                if self.improvement(&improvement).is_none() {
+                    return Err(CommandError::UnknownImprovement(improvement));
+                }
+                let job = self
+                    .buildable_improvements(board, settlement)
+                    .into_iter()
+                    .find(|(c, i)| *c == cell && i.name == improvement)
+                    .map(|(c, i)| Construction::improvement(i, c))
+                    .ok_or(CommandError::CantImprove(improvement, cell))?;
+                self.queue_construction(settlement, job, &mut events)?;
+            }
+            Command::FoundSettlement { unit, name } => {
+                let settler = self.own_unit(unit)?;
+                if !settler.can(Ability::FoundSettlement) {
+                    return Err(CommandError::CantFoundSettlement(unit));
+                }
+                let (controller, cell) = (settler.controller, settler.cell);
+                self.check_settlement_site(board, cell)?;
+                let controlled_cells = self.unclaimed_neighbours(board, cell);
+                self.units.remove(&unit);
+                let settlement = self.found_settlement(controller, name, cell, controlled_cells);
+                events.push(Event::SettlementFounded { settlement, unit });
+            }
+            Command::SetResearch { tech } => {
+                let player = self.players.get_mut(&self.active_player).unwrap();
+                if self.tech_tree.get(&tech).is_none() {
+                    return Err(CommandError::UnknownTech(tech));
+                }
+                if !self
+                    .tech_tree
+                    .available(&player.research)
+                    .any(|t| t.id == tech)
+                {
+                    return Err(CommandError::TechUnavailable(tech));
+                }
+                if player.research.current.as_ref() != Some(&tech) {
+                    player.research.current = Some(tech.clone());
+                    player.research.progress = 0.0;
+                }
+                events.push(Event::ResearchStarted {
+                    player: player.id,
+                    tech,
+                });
+            }
+            Command::EndTurn => {
+                let current = self.players.get(&self.active_player).unwrap().order;
+                let next_order = (current + 1) % self.players.len();
+                let next = self
+                    .players
+                    .values()
+                    .find(|p| p.order == next_order)
+                    .unwrap()
+                    .id;
+                self.start_turn(board, next, &mut events);
+            }
+            Command::Undo => {
+                undo::undo(self, &mut events)?;
+            }
+        }
+        self.explore(board);
+        let captured = events
+            .iter()
+            .any(|e| matches!(e, Event::SettlementCaptured { .. }));
+        if let (Some(step), Some(before)) = (undo_step, sighting)
+            && !captured
+            && before.reveals_nothing(&undo::Sighting::new(self, board))
+        {
+            self.undo.push(step);
+        } else if !keeps_history {
+            self.undo.clear();
+        }
+        Ok(events)
+    }
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+    /// Marks what every player sees now as explored.
+    fn explore(&mut self, board: Board) {
+        let players = self.players.keys().copied().collect::<Vec<_>>();
+        for player in players {
+            let visible = visibility::visible_cells(self, board, player);
+            self.explored.entry(player).or_default().extend(visible);
+        }
+    }
+    fn buildable_unit_types(&self, player: PlayerId) -> Vec<UnitType> {
+        self.players
+            .get(&player)
+            .map(|p| {
+                p.unit_types
+                    .iter()
+                    .filter(|u| p.can_build(u))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    fn own_unit(&self, id: UnitId) -> Result<&Unit, CommandError> {
+        let unit = self.units.get(&id).ok_or(CommandError::UnknownUnit(id))?;
+        if unit.controller != self.active_player {
+            return Err(CommandError::NotOwnUnit(id));
+        }
+        Ok(unit)
+    }
+    fn own_settlement(&mut self, id: SettlementId) -> Result<&mut Settlement, CommandError> {
+        let settlement = self
+            .settlements
+            .get_mut(&id)
+            .ok_or(CommandError::UnknownSettlement(id))?;
+        if settlement.controller != self.active_player {
+            return Err(CommandError::NotOwnSettlement(id));
+        }
+        Ok(settlement)
+    }
+    /// Makes `job` the settlement's construction, paying for it from the active player's
+    /// stockpile.
+    fn queue_construction(
+        &mut self,
+        id: SettlementId,
+        job: Construction,
+        events: &mut Vec<Event>,
+    ) -> Result<(), CommandError> {
+        let settlement = self.settlements.get(&id).unwrap();
+        // Materials paid for a construction that gets replaced are given back.
+        let refund = settlement
+            .construction
+            .as_ref()
+            .map_or(0.0, |c| c.materials);
+        let player = self.players.get_mut(&self.active_player).unwrap();
+        if player.materials + refund < job.materials {
+            return Err(CommandError::NotEnoughMaterials(job.materials));
+        }
+        player.materials += refund - job.materials;
+        events.push(Event::ConstructionStarted {
+            settlement: id,
+            kind: job.kind.clone(),
+        });
+        self.settlements.get_mut(&id).unwrap().construction = Some(job);
+        Ok(())
+    }
+    /// Moves a unit along its planned route for as far as it gets this turn.
+    fn advance_unit(&mut self, board: Board, id: UnitId, events: &mut Vec<Event>) {
+        let unit = self.units.get(&id).unwrap();
+        let Some(goal) = unit.goal else {
+            return;
+        };
+        let stale = unit.route.back().is_none_or(|w| w.cell != goal)
+            || unit.route.front().is_some_and(|w| w.turn < self.turn);
+        if stale {
+            let route = self.plan_route(board, id, goal);
+            let unit = self.units.get_mut(&id).unwrap();
+            let Some(route) = route else {
+                unit.goal = None;
+                unit.route.clear();
+                return;
+            };
+            unit.route = route.into();
+        }
+        let turn = self.turn;
+        let unit = self.units.get_mut(&id).unwrap();
+        let mut captured = None;
+        // Everything seen along the way is explored, not just what's in sight at the end.
+        let explored = self.explored.entry(unit.controller).or_default();
+        while let Some(next) = unit.route.front().copied()
+            && next.turn <= turn
+        {
+            let sight = sight_range(board.world_map, unit.cell, unit.sight);
+            explored.extend(board.cells_in_range(unit.cell, sight).into_keys());
+            let enemy = self
+                .settlements
+                .values()
+                .find(|s| s.cell == next.cell && s.controller != unit.controller);
+            // Units wait outside the walls until they're breached, and only military units take
+            // the settlement then.
+            if enemy.is_some_and(|s| !s.breached() || unit.can(Ability::FoundSettlement)) {
+                break;
+            }
+            let Some(cost) = board.step_cost(unit.cell, next.cell, &unit.movement) else {
+                break;
+            };
+            unit.used_speed += cost;
+            events.push(Event::UnitMoved {
+                unit: id,
+                from: unit.cell,
+                to: next.cell,
+            });
+            unit.cell = next.cell;
+            unit.route.pop_front();
+            if let Some(enemy) = enemy {
+                // Taking a settlement ends the move there.
+                captured = Some((enemy.id, enemy.controller));
+                break;
+            }
+        }
+        if unit.cell == goal || captured.is_some() {
+            unit.goal = None;
+            unit.route.clear();
+            events.push(Event::GoalReached { unit: id });
+        }
+        if let Some((settlement, from)) = captured {
+            let to = unit.controller;
+            let unit_types = self.buildable_unit_types(to);
+            self.settlements
+                .get_mut(&settlement)
+                .unwrap()
+                .capture(to, &unit_types);
+            events.push(Event::SettlementCaptured {
+                settlement,
+                from,
+                to,
+            });
+        }
+    }
+    fn damage(&mut self, id: UnitId, damage: f32, events: &mut Vec<Event>) {
+        let unit = self.units.get_mut(&id).unwrap();
+        unit.health -= damage;
+        events.push(Event::UnitDamaged {
+            unit: id,
+            damage,
+            health: unit.health,
+        });
+        if unit.health <= 0.0 {
+            self.units.remove(&id);
+            events.push(Event::UnitDestroyed { unit: id });
+        }
+    }
+    fn damage_settlement(&mut self, id: SettlementId, damage: f32, events: &mut Vec<Event>) {
+        let settlement = self.settlements.get_mut(&id).unwrap();
+        settlement.damage = (settlement.damage + damage).min(settlement.max_health());
+        events.push(Event::SettlementDamaged {
+            settlement: id,
+            damage,
+            health: settlement.health(),
+        });
+    }
+    fn start_turn(&mut self, board: Board, player: PlayerId, events: &mut Vec<Event>) {
+        if self.players.get(&player).unwrap().order == 0 {
+            self.turn += 1;
+        }
+        self.active_player = player;
+        events.push(Event::TurnStarted {
+            player,
+            turn: self.turn,
+        });
+        for unit in self.units.values_mut().filter(|u| u.controller == player) {
+            unit.used_speed = 0.0;
+        }
+        self.progress_research(player, events);
+        let settlements = self
+            .settlements
+            .values()
+            .filter(|s| s.controller == player)
+            .map(|s| s.id)
+            .collect::<Vec<_>>();
+        for id in settlements {
+            self.settlements.get_mut(&id).unwrap().repair();
+            let yields = self.settlement_yields(board, id);
+            self.players.get_mut(&player).unwrap().materials += yields.materials;
+            self.progress_construction(board, id, yields.production, events);
+            let settlement = self.settlements.get_mut(&id).unwrap();
+            settlement.food = (settlement.food + yields.food - settlement.food_upkeep()).max(0.0);
+            let growth_food = settlement.growth_food();
+            if settlement.food >= growth_food {
+                settlement.food -= growth_food;
+                self.grow(board, id, events);
+            }
+        }
+        let units = self
+            .units
+            .values()
+            .filter(|u| u.controller == player && u.goal.is_some())
+            .map(|u| u.id)
+            .collect::<Vec<_>>();
+        for unit in units {
+            self.advance_unit(board, unit, events);
+        }
+        let outcome = self.victories.iter().find_map(|victory| {
+            victory
+                .condition()
+                .winner(self, board)
+                .map(|winner| Outcome {
+                    winner,
+                    victory: *victory,
+                    turn: self.turn,
+                })
+        });
+        if let Some(outcome) = outcome {
+            self.outcome = Some(outcome);
+            events.push(Event::MatchWon { outcome });
+        }
+    }
+    fn progress_construction(
+        &mut self,
+        board: Board,
+        id: SettlementId,
+        production: f32,
+        events: &mut Vec<Event>,
+    ) {
+        let settlement = self.settlements.get_mut(&id).unwrap();
+        let Some(construction) = settlement.construction.as_mut() else {
+            return;
+        };
+        if !construction.add_progress(production) {
+            return;
+        }
+        let kind = construction.kind.clone();
+        let (controller, cell) = (settlement.controller, settlement.cell);
+        if let ConstructionKind::Unit(name) = &kind {
+            let unit_type = self
+                .players
+                .get(&controller)
+                .and_then(|p| p.unit_types.iter().find(|u| u.name == *name))
+                .cloned();
+            if let Some(unit_type) = unit_type {
+                let costs = unit_type.movement.costs();
+                let free_cell = board.world_map.get_neighbours(cell).into_iter().find(|n| {
+                    !self.units.values().any(|u| u.cell == *n)
+                        && board.step_cost(cell, *n, &costs).is_some()
+                });
+                let Some(free_cell) = free_cell else {
+                    events.push(Event::SpawnBlocked { settlement: id });
+                    return;
+                };
+                let unit = UnitId(self.next_id());
+                self.units
+                    .insert(unit, Unit::new(unit, &unit_type, controller, free_cell));
+                events.push(Event::UnitSpawned {
+                    unit,
+                    settlement: id,
+                });
+            }
+        }
+        let settlement = self.settlements.get_mut(&id).unwrap();
+        settlement.construction = None;
+        if let ConstructionKind::Improvement { name, cell } = &kind {
+            // Improvements don't make the next construction any dearer.
+            settlement.improvements.insert(*cell, name.clone());
+            events.push(Event::ImprovementBuilt {
+                settlement: id,
+                cell: *cell,
+                improvement: name.clone(),
+            });
+        } else {
+            for construction in settlement.available_constructions.iter_mut() {
+                construction.increase();
+            }
+        }
+        events.push(Event::ConstructionCompleted {
+            settlement: id,
+            kind,
+        });
+    }
+    fn progress_research(&mut self, player: PlayerId, events: &mut Vec<Event>) {
+        let settlements = self
+            .settlements
+            .values()
+            .filter(|s| s.controller == player)
+            .count();
+        let research = &mut self.players.get_mut(&player).unwrap().research;
+        let Some(tech) = research.current.clone() else {
+            return;
+        };
+        let Some(cost) = self.tech_tree.get(&tech).map(|t| t.cost) else {
+            research.current = None;
+            return;
+        };
+        research.progress += settlements as f32 * RESEARCH_PER_SETTLEMENT;
+        if research.progress < cost {
+            return;
+        }
+        research.progress -= cost;
+        research.current = None;
+        research.researched.insert(tech.clone());
+        events.push(Event::TechResearched { player, tech });
+        self.unlock_constructions(player);
+    }
+    /// Adds the unit types `player` can now build to its settlements, ahead of the sink.
+    fn unlock_constructions(&mut self, player: PlayerId) {
+        let owner = self.players.get(&player).unwrap();
+        let unit_types = owner
+            .unit_types
+            .iter()
+            .filter(|u| owner.can_build(u))
+            .collect::<Vec<_>>();
+        for settlement in self
+            .settlements
+            .values_mut()
+            .filter(|s| s.controller == player)
+        {
+            for unit_type in unit_types.iter() {
+                let kind = ConstructionKind::Unit(unit_type.name.clone());
+                if settlement
+                    .available_constructions
+                    .iter()
+                    .any(|c| c.kind == kind)
+                {
+                    continue;
+                }
+                let index = settlement
+                    .available_constructions
+                    .iter()
+                    .position(|c| c.kind == ConstructionKind::Sink)
+                    .unwrap_or(settlement.available_constructions.len());
+                settlement
+                    .available_constructions
+                    .insert(index, Construction::unit(unit_type));
+            }
+        }
+    }
+    /// Extends a settlement's borders to the nearest cell it doesn't control yet.
+    fn grow(&mut self, board: Board, id: SettlementId, events: &mut Vec<Event>) {
+        let settlement = self.settlements.get_mut(&id).unwrap();
+        let controlled_cells = &settlement.controlled_cells;
+        let mut un_controlled_cells = vec![];
+        for cell in controlled_cells.iter() {
+            un_controlled_cells.extend(
+                board
+                    .world_map
+                    .get_neighbours(*cell)
+                    .into_iter()
+                    .filter(|n| !controlled_cells.contains(n) && *n != settlement.cell),
+            );
+        }
+        let mut closest_distance = usize::MAX;
+        let mut closest_cell = None;
+        for cell in un_controlled_cells {
+            if let Some(distance) = board.distance(settlement.cell, cell)
+                && distance < closest_distance
+            {
+                closest_distance = distance;
+                closest_cell = Some(cell);
+            }
+        }
+        if let Some(cell) = closest_cell {
+            settlement.controlled_cells.push(cell);
+            events.push(Event::SettlementGrew {
+                settlement: id,
+                cell,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{player, test_board, unit_type};
+
+    #[test]
+    fn queued_unit_spawns_next_to_settlement() {
+        let test = test_board(7);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let mut game = test.new_match(vec![Player {
+            unit_types: vec![unit_type("Scout")],
+            ..player(0)
+        }]);
+        let cell = world_map
+            .get_valid_settlement_cells()
+            .first()
+            .copied()
+            .unwrap_or(CellId(0));
+        let settlement = game.found_settlement(
+            PlayerId(0),
+            "Home".to_string(),
+            cell,
+            world_map.get_neighbours(cell),
+        );
+        game.apply(
+            board,
+            Command::SetConstruction {
+                settlement,
+                construction: 0,
+            },
+        )
+        .unwrap();
+        assert!(game.turn_ready_to_end());
+        let events = game.apply(board, Command::EndTurn).unwrap();
+        assert_eq!(game.turn(), 2);
+        let spawned = events
+            .iter()
+            .find_map(|e| match e {
+                Event::UnitSpawned { unit, .. } => Some(*unit),
+                _ => None,
+            })
+            .unwrap();
+        let unit = game.unit(spawned).unwrap();
+        assert!(world_map.get_neighbours(cell).contains(&unit.cell));
+        assert!(game.settlement(settlement).unwrap().construction.is_none());
+    }
+}
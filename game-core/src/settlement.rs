@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use world_generation::CellId;
+
+use crate::{
+    Improvement, PlayerId, SettlementId, UnitType,
+    economy::{FOOD_UPKEEP, GROWTH_FOOD},
+};
+
+const SINK_COST: f32 = 5.0;
+/// Fewest steps allowed between the centres of two settlements.
+pub const MIN_SETTLEMENT_DISTANCE: usize = 4;
+/// Health of a settlement's walls before counting the cells it controls.
+pub const SETTLEMENT_HEALTH: f32 = 10.0;
+/// Extra wall health for each cell a settlement controls, the centre included.
+const HEALTH_PER_CELL: f32 = 2.0;
+/// Damage a settlement deals back to attackers next to it, before modifiers.
+pub const SETTLEMENT_DEFENCE: f32 = 2.0;
+/// Damage repaired at the start of each of the controller's turns.
+const REPAIR_PER_TURN: f32 = 2.0;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConstructionKind {
+    /// Spawns a unit of the named [`UnitType`] next to the settlement.
+    Unit(String),
+    /// Builds the named [`Improvement`] on one of the settlement's cells.
+    Improvement { name: String, cell: CellId },
+    /// Soaks up production without producing anything.
+    Sink,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Construction {
+    pub kind: ConstructionKind,
+    pub cost: f32,
+    /// Taken from the controller's stockpile when the construction is queued.
+    #[serde(default)]
+    pub materials: f32,
+    pub progress: f32,
+}
+impl Construction {
+    pub fn unit(unit_type: &UnitType) -> Self {
+        Self {
+            kind: ConstructionKind::Unit(unit_type.name.clone()),
+            cost: unit_type.default_cost,
+            materials: unit_type.materials,
+            progress: 0.0,
+        }
+    }
+    pub fn improvement(improvement: &Improvement, cell: CellId) -> Self {
+        Self {
+            kind: ConstructionKind::Improvement {
+                name: improvement.name.clone(),
+                cell,
+            },
+            cost: improvement.cost,
+            materials: improvement.materials,
+            progress: 0.0,
+        }
+    }
+    pub fn sink() -> Self {
+        Self {
+            kind: ConstructionKind::Sink,
+            cost: SINK_COST,
+            materials: 0.0,
+            progress: 0.0,
+        }
+    }
+    pub fn name(&self) -> &str {
+        match &self.kind {
+            ConstructionKind::Unit(name) => name,
+            ConstructionKind::Improvement { name, .. } => name,
+            ConstructionKind::Sink => "Sink",
+        }
+    }
+    /// Returns `true` once the construction is paid for.
+    pub(crate) fn add_progress(&mut self, progress: f32) -> bool {
+        self.progress += progress;
+        self.progress >= self.cost
+    }
+    /// Makes the next construction of this kind more expensive.
+    pub(crate) fn increase(&mut self) {
+        self.cost = self.cost.powf(1.5);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settlement {
+    pub id: SettlementId,
+    pub name: String,
+    pub controller: PlayerId,
+    pub cell: CellId,
+    /// Production of the settlement itself, before what its cells yield.
+    pub production: f32,
+    /// Stored towards the next growth.
+    #[serde(default)]
+    pub food: f32,
+    pub controlled_cells: Vec<CellId>,
+    /// The improvement built on each improved cell, by name.
+    #[serde(default)]
+    pub improvements: BTreeMap<CellId, String>,
+    /// Damage taken by the walls, once it reaches [`max_health`](Self::max_health) enemy units
+    /// can walk in.
+    #[serde(default)]
+    pub damage: f32,
+    pub construction: Option<Construction>,
+    pub available_constructions: Vec<Construction>,
+}
+impl Settlement {
+    pub fn new(
+        id: SettlementId,
+        name: String,
+        controller: PlayerId,
+        cell: CellId,
+        controlled_cells: Vec<CellId>,
+        unit_types: &[UnitType],
+    ) -> Self {
+        Self {
+            id,
+            name,
+            controller,
+            cell,
+            production: 1.0,
+            food: 0.0,
+            controlled_cells,
+            improvements: BTreeMap::new(),
+            damage: 0.0,
+            construction: None,
+            available_constructions: constructions(unit_types),
+        }
+    }
+    pub fn max_health(&self) -> f32 {
+        SETTLEMENT_HEALTH + HEALTH_PER_CELL * (self.controlled_cells.len() + 1) as f32
+    }
+    pub fn health(&self) -> f32 {
+        (self.max_health() - self.damage).max(0.0)
+    }
+    /// Whether the walls are down and enemy units can walk in.
+    pub fn breached(&self) -> bool {
+        self.health() <= 0.0
+    }
+    /// Hands the settlement to `controller`, who can build `unit_types` in it. Whatever was
+    /// being built is lost and the walls are patched up halfway.
+    pub(crate) fn capture(&mut self, controller: PlayerId, unit_types: &[UnitType]) {
+        self.controller = controller;
+        self.construction = None;
+        self.available_constructions = constructions(unit_types);
+        self.damage = self.max_health() / 2.0;
+    }
+    pub(crate) fn repair(&mut self) {
+        self.damage = (self.damage - REPAIR_PER_TURN).max(0.0);
+    }
+    /// Food eaten each turn.
+    pub fn food_upkeep(&self) -> f32 {
+        FOOD_UPKEEP * (self.controlled_cells.len() + 1) as f32
+    }
+    /// Stored food needed to grow.
+    pub fn growth_food(&self) -> f32 {
+        GROWTH_FOOD * (self.controlled_cells.len() + 1) as f32
+    }
+}
+
+/// A construction for each unit type, followed by the sink.
+fn constructions(unit_types: &[UnitType]) -> Vec<Construction> {
+    unit_types
+        .iter()
+        .map(Construction::unit)
+        .chain([Construction::sink()])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Ability, Command, CommandError, Event, Player, Unit, UnitId,
+        test_support::{player, test_board, unit_type},
+    };
+
+    #[test]
+    fn settlers_found_settlements_away_from_others() {
+        let test = test_board(17);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let settler = UnitType {
+            default_cost: 5.0,
+            speed: 5.0,
+            abilities: vec![Ability::FoundSettlement],
+            ..unit_type("Settler")
+        };
+        let mut game = test.new_match(vec![Player {
+            unit_types: vec![settler.clone()],
+            ..player(0)
+        }]);
+        let sites = world_map.get_valid_settlement_cells();
+        let home = sites[0];
+        game.found_settlement(
+            PlayerId(0),
+            "Home".to_string(),
+            home,
+            world_map.get_neighbours(home),
+        );
+        let far = sites
+            .iter()
+            .copied()
+            .find(|c| {
+                board
+                    .distance(home, *c)
+                    .is_some_and(|d| d >= MIN_SETTLEMENT_DISTANCE)
+            })
+            .unwrap();
+        let found = |cell| {
+            let mut game = game.clone();
+            let unit = UnitId(100);
+            game.units
+                .insert(unit, Unit::new(unit, &settler, PlayerId(0), cell));
+            let result = game.apply(
+                board,
+                Command::FoundSettlement {
+                    unit,
+                    name: "Outpost".to_string(),
+                },
+            );
+            (game, result)
+        };
+
+        let (_, result) = found(world_map.get_neighbours(home)[0]);
+        assert!(matches!(
+            result,
+            Err(CommandError::TooCloseToSettlement(_) | CommandError::InvalidSettlementSite(_))
+        ));
+        let (game, result) = found(far);
+        let events = result.unwrap();
+        let Some(Event::SettlementFounded { settlement, unit }) = events.first() else {
+            panic!("No settlement founded: {events:?}");
+        };
+        assert!(game.unit(*unit).is_none());
+        let settlement = game.settlement(*settlement).unwrap();
+        assert_eq!(settlement.cell, far);
+        assert_eq!(settlement.name, "Outpost");
+        assert_eq!(
+            settlement
+                .available_constructions
+                .iter()
+                .map(|c| c.name())
+                .collect::<Vec<_>>(),
+            ["Settler", "Sink"]
+        );
+    }
+
+    #[test]
+    fn sites_hemmed_in_by_borders_cant_be_settled() {
+        let test = test_board(17);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let settler = UnitType {
+            abilities: vec![Ability::FoundSettlement],
+            ..unit_type("Settler")
+        };
+        let mut game = test.new_match(vec![Player {
+            unit_types: vec![settler.clone()],
+            ..player(0)
+        }]);
+        let sites = world_map.get_valid_settlement_cells();
+        let home = sites[0];
+        let far = sites
+            .iter()
+            .copied()
+            .find(|c| {
+                board
+                    .distance(home, *c)
+                    .is_some_and(|d| d >= MIN_SETTLEMENT_DISTANCE)
+            })
+            .unwrap();
+        // Borders grown out far enough to take every cell around `far` but not `far` itself.
+        let borders = world_map
+            .get_neighbours(home)
+            .into_iter()
+            .chain(world_map.get_neighbours(far))
+            .filter(|c| *c != home)
+            .collect();
+        game.found_settlement(PlayerId(0), "Home".to_string(), home, borders);
+        assert_eq!(
+            game.check_settlement_site(board, far),
+            Err(CommandError::TooCloseToSettlement(far))
+        );
+        assert!(!game.settlement_sites(board).contains(&far));
+        let unit = UnitId(100);
+        game.units
+            .insert(unit, Unit::new(unit, &settler, PlayerId(0), far));
+        assert_eq!(
+            game.apply(
+                board,
+                Command::FoundSettlement {
+                    unit,
+                    name: "Outpost".to_string(),
+                },
+            ),
+            Err(CommandError::TooCloseToSettlement(far))
+        );
+        assert_eq!(game.settlements().count(), 1);
+    }
+}
@@ -0,0 +1,170 @@
+//! Technologies players research to unlock units, bonuses and buildings.
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Research points each settlement adds to its controller's research every turn.
+pub const RESEARCH_PER_SETTLEMENT: f32 = 1.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Unlock {
+    /// Added to the production of every settlement.
+    ProductionBonus(f32),
+    /// Allows building the named building on controlled cells.
+    Building(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tech {
+    /// What prerequisites, a unit type's `requires` and research commands refer to the tech by.
+    pub id: String,
+    pub name: String,
+    pub cost: f32,
+    #[serde(default)]
+    pub prerequisites: Vec<String>,
+    #[serde(default)]
+    pub unlocks: Vec<Unlock>,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Every tech in a match, as described in a `.tech.ron` file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TechTree {
+    pub techs: Vec<Tech>,
+}
+impl TechTree {
+    pub fn get(&self, id: &str) -> Option<&Tech> {
+        self.techs.iter().find(|t| t.id == id)
+    }
+    /// Techs that can be researched next, every prerequisite done and not researched yet.
+    pub fn available<'a>(&'a self, research: &'a Research) -> impl Iterator<Item = &'a Tech> {
+        self.techs
+            .iter()
+            .filter(|t| !research.has(&t.id) && t.prerequisites.iter().all(|p| research.has(p)))
+    }
+    /// The first prerequisite that isn't a tech in this tree.
+    pub fn unknown_prerequisite(&self) -> Option<(&Tech, &str)> {
+        self.techs.iter().find_map(|t| {
+            t.prerequisites
+                .iter()
+                .find(|p| self.get(p).is_none())
+                .map(|p| (t, p.as_str()))
+        })
+    }
+    pub fn production_bonus(&self, research: &Research) -> f32 {
+        self.unlocks(research)
+            .filter_map(|u| match u {
+                Unlock::ProductionBonus(bonus) => Some(*bonus),
+                _ => None,
+            })
+            .sum()
+    }
+    pub fn has_building(&self, research: &Research, building: &str) -> bool {
+        self.unlocks(research)
+            .any(|u| matches!(u, Unlock::Building(b) if b == building))
+    }
+    /// Whether a building can be built, either researched or not locked behind any tech.
+    pub fn building_available(&self, research: &Research, building: &str) -> bool {
+        let locked = self
+            .techs
+            .iter()
+            .flat_map(|t| t.unlocks.iter())
+            .any(|u| matches!(u, Unlock::Building(b) if b == building));
+        !locked || self.has_building(research, building)
+    }
+    fn unlocks<'a>(&'a self, research: &'a Research) -> impl Iterator<Item = &'a Unlock> {
+        self.techs
+            .iter()
+            .filter(|t| research.has(&t.id))
+            .flat_map(|t| t.unlocks.iter())
+    }
+}
+
+/// A player's progress through the [`TechTree`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Research {
+    /// The tech being researched.
+    pub current: Option<String>,
+    /// Points put into `current` so far.
+    pub progress: f32,
+    pub researched: BTreeSet<String>,
+}
+impl Research {
+    pub fn has(&self, tech: &str) -> bool {
+        self.researched.contains(tech)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use world_generation::CellId;
+
+    use super::*;
+    use crate::{
+        Command, ConstructionKind, Event, Match, Player, PlayerId, UnitType,
+        test_support::{player, test_board, unit_type},
+    };
+
+    #[test]
+    fn researching_a_tech_unlocks_its_units() {
+        let test = test_board(5);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let tech_tree = TechTree {
+            techs: vec![Tech {
+                id: "archery".to_string(),
+                name: "Archery".to_string(),
+                cost: 2.0,
+                prerequisites: vec![],
+                unlocks: vec![Unlock::ProductionBonus(0.5)],
+                description: String::new(),
+            }],
+        };
+        let archer = UnitType {
+            range: 2,
+            requires: Some("archery".to_string()),
+            ..unit_type("Archer")
+        };
+        let player = Player {
+            unit_types: vec![archer],
+            ..player(0)
+        };
+        let mut game = Match::new(vec![player], tech_tree, vec![], test.rng.clone());
+        let settlement = game.found_settlement(PlayerId(0), "Home".to_string(), CellId(0), vec![]);
+        let archers = |game: &Match| {
+            game.settlement(settlement)
+                .unwrap()
+                .available_constructions
+                .iter()
+                .filter(|c| c.kind == ConstructionKind::Unit("Archer".to_string()))
+                .count()
+        };
+        assert_eq!(archers(&game), 0);
+        game.apply(
+            board,
+            Command::SetResearch {
+                tech: "archery".to_string(),
+            },
+        )
+        .unwrap();
+        let events = [
+            game.apply(board, Command::EndTurn).unwrap(),
+            game.apply(board, Command::EndTurn).unwrap(),
+        ]
+        .concat();
+        assert!(events.contains(&Event::TechResearched {
+            player: PlayerId(0),
+            tech: "archery".to_string(),
+        }));
+        assert_eq!(archers(&game), 1);
+        assert_eq!(
+            game.apply(
+                board,
+                Command::SetResearch {
+                    tech: "archery".to_string(),
+                },
+            ),
+            Err(crate::CommandError::TechUnavailable("archery".to_string()))
+        );
+    }
+}
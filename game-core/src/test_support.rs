@@ -0,0 +1,74 @@
+//! Fixtures shared by the tests, a small generated map and unit types and players to override.
+use std::collections::HashMap;
+
+use petgraph::{Graph, graph::NodeIndex};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use world_generation::{CellId, WorldMap, WorldType, generate_world};
+
+use crate::{Board, DEFAULT_SIGHT, Match, Player, PlayerId, Research, TechTree, UnitType};
+
+/// A [`WorldType::Small`] map, its movement graph and the RNG as generating it left it.
+pub(crate) struct TestBoard {
+    pub(crate) world_map: WorldMap,
+    graph: Graph<CellId, f32>,
+    nodes: HashMap<CellId, NodeIndex>,
+    pub(crate) rng: ChaCha20Rng,
+}
+impl TestBoard {
+    pub(crate) fn board(&self) -> Board<'_> {
+        Board {
+            world_map: &self.world_map,
+            graph: &self.graph,
+            nodes: &self.nodes,
+        }
+    }
+    /// A match between `players` with no techs or improvements, rolling on a copy of the RNG.
+    pub(crate) fn new_match(&self, players: Vec<Player>) -> Match {
+        Match::new(players, TechTree::default(), vec![], self.rng.clone())
+    }
+}
+
+pub(crate) fn test_board(seed: u64) -> TestBoard {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let world_map = generate_world(WorldType::Small.get_params(), &mut rng).unwrap();
+    let (graph, nodes) = pathfinding::get_graph(&world_map);
+    TestBoard {
+        world_map,
+        graph,
+        nodes,
+        rng,
+    }
+}
+
+/// A cheap infantry unit with 3 health, range 1 and speed 10.
+pub(crate) fn unit_type(name: &str) -> UnitType {
+    UnitType {
+        name: name.to_string(),
+        default_cost: 1.0,
+        materials: 0.0,
+        health: 3.0,
+        range: 1,
+        speed: 10.0,
+        movement: Default::default(),
+        combat: Default::default(),
+        requires: None,
+        abilities: vec![],
+        sight: DEFAULT_SIGHT,
+        mesh_path: String::new(),
+        icon_path: String::new(),
+        seed_barks: vec![],
+        description: String::new(),
+    }
+}
+
+/// A player with no unit types, research or materials, `id` doubling as the turn order.
+pub(crate) fn player(id: usize) -> Player {
+    Player {
+        id: PlayerId(id),
+        order: id,
+        unit_types: vec![],
+        research: Research::default(),
+        materials: 0.0,
+    }
+}
@@ -0,0 +1,158 @@
+//! Taking back the active player's last moves and construction orders within a turn.
+//!
+//! Only commands that gave nothing away can be taken back. A move that explored new cells, brought
+//! an enemy unit into sight or captured a settlement clears the history instead, as does anything
+//! that rolls dice or ends the turn.
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Board, Command, CommandError, Construction, Event, Match, SettlementId, Unit, UnitId};
+
+/// What to put back to take one command back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum UndoStep {
+    /// The unit as it was before it moved.
+    Move(Unit),
+    /// The settlement's construction and the active player's stockpile before the new order.
+    Construction {
+        settlement: SettlementId,
+        construction: Option<Construction>,
+        materials: f32,
+    },
+}
+
+/// What the active player knows of the map, a command that grows it can't be taken back.
+pub(crate) struct Sighting {
+    explored: usize,
+    enemies: BTreeSet<UnitId>,
+}
+impl Sighting {
+    pub(crate) fn new(game: &Match, board: Board) -> Self {
+        let player = game.active_player;
+        let visibility = game.visibility(board, player);
+        let enemies = game
+            .units
+            .values()
+            .filter(|u| u.controller != player && visibility.visible.contains(&u.cell))
+            .map(|u| u.id)
+            .collect();
+        Self {
+            explored: visibility.explored.len(),
+            enemies,
+        }
+    }
+    /// Whether `after` shows nothing that wasn't known before.
+    pub(crate) fn reveals_nothing(&self, after: &Sighting) -> bool {
+        self.explored == after.explored && after.enemies.is_subset(&self.enemies)
+    }
+}
+
+/// The step to take `command` back, taken before it's applied. `None` if it can't be undone.
+pub(crate) fn step(game: &Match, command: &Command) -> Option<UndoStep> {
+    match command {
+        Command::MoveUnit { unit, .. } => game.units.get(unit).cloned().map(UndoStep::Move),
+        Command::SetConstruction { settlement, .. }
+        | Command::BuildImprovement { settlement, .. } => {
+            let construction = game.settlements.get(settlement)?.construction.clone();
+            Some(UndoStep::Construction {
+                settlement: *settlement,
+                construction,
+                materials: game.players.get(&game.active_player)?.materials,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Takes back the last undoable command of the turn.
+pub(crate) fn undo(game: &mut Match, events: &mut Vec<Event>) -> Result<(), CommandError> {
+    match game.undo.pop().ok_or(CommandError::NothingToUndo)? {
+        UndoStep::Move(unit) => {
+            events.push(Event::MoveUndone {
+                unit: unit.id,
+                cell: unit.cell,
+            });
+            game.units.insert(unit.id, unit);
+        }
+        UndoStep::Construction {
+            settlement,
+            construction,
+            materials,
+        } => {
+            game.settlements.get_mut(&settlement).unwrap().construction = construction;
+            game.players.get_mut(&game.active_player).unwrap().materials = materials;
+            events.push(Event::ConstructionUndone { settlement });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Player, PlayerId, UnitType,
+        test_support::{player, test_board, unit_type},
+    };
+
+    #[test]
+    fn undoing_a_move_puts_the_unit_back() {
+        let test = test_board(20);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let warrior = UnitType {
+            speed: 3.0,
+            sight: 0,
+            ..unit_type("Warrior")
+        };
+        let mut game = test.new_match(vec![Player {
+            unit_types: vec![warrior.clone()],
+            ..player(0)
+        }]);
+        let start = world_map.get_valid_settlement_cells()[0];
+        let unit = UnitId(100);
+        game.units
+            .insert(unit, Unit::new(unit, &warrior, PlayerId(0), start));
+        // Everything is explored up front so the move reveals nothing.
+        game.explored
+            .insert(PlayerId(0), board.nodes.keys().copied().collect());
+        assert_eq!(
+            game.apply(board, Command::Undo).unwrap_err(),
+            CommandError::NothingToUndo
+        );
+        let goal = game
+            .reachable_cells(board, unit)
+            .into_keys()
+            .filter(|c| *c != start)
+            .min()
+            .unwrap();
+        game.apply(
+            board,
+            Command::MoveUnit {
+                unit,
+                goal: Some(goal),
+            },
+        )
+        .unwrap();
+        assert_ne!(game.units[&unit].cell, start);
+        assert!(game.can_undo());
+        let events = game.apply(board, Command::Undo).unwrap();
+        assert_eq!(events, vec![Event::MoveUndone { unit, cell: start }]);
+        assert_eq!(game.units[&unit].cell, start);
+        assert_eq!(game.units[&unit].used_speed, 0.0);
+        assert!(!game.can_undo());
+        game.apply(
+            board,
+            Command::MoveUnit {
+                unit,
+                goal: Some(goal),
+            },
+        )
+        .unwrap();
+        game.apply(board, Command::EndTurn).unwrap();
+        assert!(
+            !game.can_undo(),
+            "the history is cleared once the turn ends"
+        );
+    }
+}
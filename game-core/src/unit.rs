@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+
+use pathfinding::{CostProfile, MovementProfile, Waypoint};
+use serde::{Deserialize, Serialize};
+use world_generation::CellId;
+
+use crate::{CombatStats, DEFAULT_SIGHT, PlayerId, UnitId};
+
+/// Something a unit can do besides moving and fighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ability {
+    /// Founds a settlement on the unit's cell, using the unit up.
+    FoundSettlement,
+}
+
+/// A kind of unit a civilisation can build, as described in its `.civ.ron` file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitType {
+    pub name: String,
+    pub default_cost: f32,
+    /// Materials needed to queue the unit, on top of its production cost.
+    #[serde(default)]
+    pub materials: f32,
+    pub health: f32,
+    pub range: usize,
+    pub speed: f32,
+    /// How the terrain slows the unit down, [`MovementProfile::Infantry`] if left out.
+    #[serde(default)]
+    pub movement: MovementProfile,
+    #[serde(default)]
+    pub combat: CombatStats,
+    /// Id of the tech that has to be researched before the unit can be built.
+    #[serde(default)]
+    pub requires: Option<String>,
+    #[serde(default)]
+    pub abilities: Vec<Ability>,
+    /// Steps the unit sees on flat ground, see [`sight_range`](crate::sight_range).
+    #[serde(default = "default_sight")]
+    pub sight: usize,
+    pub mesh_path: String,
+    pub icon_path: String,
+    pub seed_barks: Vec<String>,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unit {
+    pub id: UnitId,
+    pub unit_type: String,
+    pub controller: PlayerId,
+    pub max_health: f32,
+    pub health: f32,
+    pub range: usize,
+    pub speed: f32,
+    /// Movement already spent this turn.
+    pub used_speed: f32,
+    #[serde(default)]
+    pub movement: CostProfile,
+    #[serde(default)]
+    pub combat: CombatStats,
+    #[serde(default)]
+    pub abilities: Vec<Ability>,
+    #[serde(default = "default_sight")]
+    pub sight: usize,
+    pub cell: CellId,
+    /// Where the unit is heading, it keeps moving towards it at the start of each of its turns.
+    pub goal: Option<CellId>,
+    /// The rest of the way to `goal`, planned when the goal was set.
+    #[serde(default)]
+    pub route: VecDeque<Waypoint>,
+}
+fn default_sight() -> usize {
+    DEFAULT_SIGHT
+}
+
+impl Unit {
+    pub fn new(id: UnitId, unit_type: &UnitType, controller: PlayerId, cell: CellId) -> Self {
+        Self {
+            id,
+            unit_type: unit_type.name.clone(),
+            controller,
+            max_health: unit_type.health,
+            health: unit_type.health,
+            range: unit_type.range,
+            speed: unit_type.speed,
+            used_speed: 0.0,
+            movement: unit_type.movement.costs(),
+            combat: unit_type.combat.clone(),
+            abilities: unit_type.abilities.clone(),
+            sight: unit_type.sight,
+            cell,
+            goal: None,
+            route: VecDeque::new(),
+        }
+    }
+    pub fn can(&self, ability: Ability) -> bool {
+        self.abilities.contains(&ability)
+    }
+}
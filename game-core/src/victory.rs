@@ -0,0 +1,224 @@
+//! How a match is won.
+//!
+//! Every enabled [`Victory`] is checked at the start of each turn, the first to name a winner ends
+//! the match with [`Event::MatchWon`](crate::Event::MatchWon).
+use pathfinding::WATER_LEVEL;
+use serde::{Deserialize, Serialize};
+use world_generation::CellId;
+
+use crate::{Board, Match, PlayerId};
+
+/// Share of the land a player needs to control for a [`Territorial`] victory by default.
+pub const DEFAULT_TERRITORY_SHARE: f32 = 0.5;
+/// Rounds played before a [`TurnLimit`] victory by default.
+pub const DEFAULT_TURN_LIMIT: u32 = 150;
+
+pub trait VictoryCondition {
+    /// Shown on the game-over screen and in the new-game menu.
+    fn name(&self) -> &'static str;
+    /// The player who has won, if anyone has yet.
+    fn winner(&self, game: &Match, board: Board) -> Option<PlayerId>;
+}
+
+/// Won by the last player left holding settlements.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Conquest;
+impl VictoryCondition for Conquest {
+    fn name(&self) -> &'static str {
+        "Conquest"
+    }
+    fn winner(&self, game: &Match, _board: Board) -> Option<PlayerId> {
+        let mut holders = game
+            .players()
+            .map(|p| p.id)
+            .filter(|p| game.settlements().any(|s| s.controller == *p));
+        let winner = holders.next()?;
+        // A match for one player can't be won by conquest.
+        (holders.next().is_none() && game.players().count() > 1).then_some(winner)
+    }
+}
+
+/// Won by controlling `share` of the land, settlement centres included.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Territorial {
+    pub share: f32,
+}
+impl VictoryCondition for Territorial {
+    fn name(&self) -> &'static str {
+        "Territorial"
+    }
+    fn winner(&self, game: &Match, board: Board) -> Option<PlayerId> {
+        let is_land = |cell: &CellId| board.world_map.get_raw_height(cell) >= WATER_LEVEL;
+        let land = board
+            .world_map
+            .iter_cells()
+            .filter(|c| is_land(&CellId(c.site())))
+            .count();
+        game.players().map(|p| p.id).find(|player| {
+            let controlled = game
+                .settlements()
+                .filter(|s| s.controller == *player)
+                .flat_map(|s| s.controlled_cells.iter().chain([&s.cell]))
+                .filter(|c| is_land(c))
+                .count();
+            land > 0 && controlled as f32 >= self.share * land as f32
+        })
+    }
+}
+
+/// Won by the highest [`score`](PlayerStats::score) once `turns` rounds have been played.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TurnLimit {
+    pub turns: u32,
+}
+impl VictoryCondition for TurnLimit {
+    fn name(&self) -> &'static str {
+        "Score"
+    }
+    fn winner(&self, game: &Match, _board: Board) -> Option<PlayerId> {
+        if game.turn() <= self.turns {
+            return None;
+        }
+        // Ties go to whoever plays first.
+        game.players()
+            .map(|p| (game.stats(p.id).score(), p.order, p.id))
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+            .map(|(_, _, id)| id)
+    }
+}
+
+/// One of the built-in victory conditions, as chosen in the new-game menu.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Victory {
+    Conquest(Conquest),
+    Territorial(Territorial),
+    TurnLimit(TurnLimit),
+}
+impl Victory {
+    /// Every built-in condition with its default settings.
+    pub const ALL: [Victory; 3] = [
+        Victory::Conquest(Conquest),
+        Victory::Territorial(Territorial {
+            share: DEFAULT_TERRITORY_SHARE,
+        }),
+        Victory::TurnLimit(TurnLimit {
+            turns: DEFAULT_TURN_LIMIT,
+        }),
+    ];
+    pub fn condition(&self) -> &dyn VictoryCondition {
+        match self {
+            Victory::Conquest(c) => c,
+            Victory::Territorial(c) => c,
+            Victory::TurnLimit(c) => c,
+        }
+    }
+}
+
+/// How a finished match ended.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Outcome {
+    pub winner: PlayerId,
+    pub victory: Victory,
+    pub turn: u32,
+}
+
+/// What a player has to show for the match so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlayerStats {
+    pub settlements: usize,
+    /// Cells inside the player's borders, settlement centres included.
+    pub cells: usize,
+    pub units: usize,
+    pub techs: usize,
+}
+impl PlayerStats {
+    pub fn score(&self) -> usize {
+        self.settlements * 10 + self.cells + self.units * 2 + self.techs * 5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Archetype, Command, Event, Player, Unit, UnitId, UnitType,
+        test_support::{player, test_board, unit_type},
+    };
+
+    #[test]
+    fn taking_the_last_rival_settlement_wins_by_conquest() {
+        let test = test_board(15);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let mut siege_engine = UnitType {
+            health: 20.0,
+            ..unit_type("Siege Engine")
+        };
+        siege_engine.combat.archetype = Archetype::Melee;
+        siege_engine.combat.attack = 100.0;
+        let mut game = test.new_match(vec![
+            Player {
+                unit_types: vec![siege_engine.clone()],
+                ..player(0)
+            },
+            player(1),
+        ]);
+        game.set_victories(vec![Victory::Conquest(Conquest)]);
+        let sites = world_map.get_valid_settlement_cells();
+        let home = game.found_settlement(PlayerId(0), "Home".to_string(), sites[0], vec![]);
+        let costs = siege_engine.movement.costs();
+        let (site, outside) = sites[1..]
+            .iter()
+            .find_map(|site| {
+                world_map
+                    .get_neighbours(*site)
+                    .into_iter()
+                    .find(|n| *n != sites[0] && board.step_cost(*n, *site, &costs).is_some())
+                    .map(|n| (*site, n))
+            })
+            .unwrap();
+        let rival = game.found_settlement(PlayerId(1), "Rival".to_string(), site, vec![]);
+        let attacker = UnitId(100);
+        game.units.insert(
+            attacker,
+            Unit::new(attacker, &siege_engine, PlayerId(0), outside),
+        );
+        assert_eq!(Conquest.winner(&game, board), None);
+        game.apply(
+            board,
+            Command::AttackSettlement {
+                attacker,
+                settlement: rival,
+            },
+        )
+        .unwrap();
+        let events = game
+            .apply(
+                board,
+                Command::MoveUnit {
+                    unit: attacker,
+                    goal: Some(site),
+                },
+            )
+            .unwrap();
+        assert!(events.contains(&Event::SettlementCaptured {
+            settlement: rival,
+            from: PlayerId(1),
+            to: PlayerId(0),
+        }));
+        let events = game.apply(board, Command::EndTurn).unwrap();
+        assert!(events.contains(&Event::MatchWon {
+            outcome: Outcome {
+                winner: PlayerId(0),
+                victory: Victory::Conquest(Conquest),
+                turn: game.turn(),
+            }
+        }));
+        assert_eq!(game.stats(PlayerId(0)).settlements, 2);
+        assert_eq!(game.outcome().map(|o| o.winner), Some(PlayerId(0)));
+        assert!(game.settlement(home).is_some());
+        assert_eq!(
+            game.apply(board, Command::EndTurn),
+            Err(crate::CommandError::MatchOver)
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! What each player can see of the map.
+//!
+//! Units see [`sight_range`] steps around them and settlements see one step past their borders.
+//! Cells a player has seen at some point stay explored, but only the cells seen right now show
+//! what's on them.
+use std::collections::{BTreeSet, HashSet};
+
+use pathfinding::WATER_LEVEL;
+use world_generation::{CellId, WorldMap};
+
+use crate::{Board, Match, PlayerId};
+
+/// Steps a unit sees on flat ground unless its type says otherwise.
+pub const DEFAULT_SIGHT: usize = 2;
+/// Raw height above the water level each extra step of sight needs.
+const HEIGHT_PER_SIGHT: f32 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellVisibility {
+    /// Never seen, nothing about it is shown.
+    Unexplored,
+    /// Seen before, the terrain and settlements are known but not what's there now.
+    Explored,
+    Visible,
+}
+
+/// A player's view of the map at one moment.
+#[derive(Debug, Clone, Default)]
+pub struct Visibility {
+    pub visible: HashSet<CellId>,
+    /// Every cell seen so far, the visible ones included.
+    pub explored: BTreeSet<CellId>,
+}
+impl Visibility {
+    pub fn get(&self, cell: CellId) -> CellVisibility {
+        if self.visible.contains(&cell) {
+            CellVisibility::Visible
+        } else if self.explored.contains(&cell) {
+            CellVisibility::Explored
+        } else {
+            CellVisibility::Unexplored
+        }
+    }
+}
+
+/// How many steps can be seen from `cell` by something with the given `sight`, hills see further.
+pub fn sight_range(world_map: &WorldMap, cell: CellId, sight: usize) -> usize {
+    let height = (world_map.get_raw_height(&cell) - WATER_LEVEL).max(0.0);
+    sight + (height / HEIGHT_PER_SIGHT) as usize
+}
+
+/// Every cell `player` can see right now.
+pub(crate) fn visible_cells(game: &Match, board: Board, player: PlayerId) -> HashSet<CellId> {
+    let units = game
+        .units()
+        .filter(|u| u.controller == player)
+        .map(|u| (u.cell, sight_range(board.world_map, u.cell, u.sight)));
+    let borders = game
+        .settlements()
+        .filter(|s| s.controller == player)
+        .flat_map(|s| s.controlled_cells.iter().chain([&s.cell]))
+        .map(|c| (*c, 1));
+    units
+        .chain(borders)
+        .flat_map(|(cell, range)| board.cells_in_range(cell, range).into_keys())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Command, Player, Unit, UnitId, UnitType,
+        test_support::{player, test_board, unit_type},
+    };
+
+    #[test]
+    fn explored_cells_stay_explored_once_out_of_sight() {
+        let test = test_board(16);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let scout = UnitType {
+            speed: 50.0,
+            ..unit_type("Scout")
+        };
+        let mut game = test.new_match(vec![Player {
+            unit_types: vec![scout.clone()],
+            ..player(0)
+        }]);
+        let start = world_map.get_valid_settlement_cells()[0];
+        let unit = UnitId(100);
+        game.units
+            .insert(unit, Unit::new(unit, &scout, PlayerId(0), start));
+        let before = game.visibility(board, PlayerId(0));
+        assert_eq!(before.get(start), CellVisibility::Visible);
+        assert!(
+            before.visible.len() >= board.cells_in_range(start, DEFAULT_SIGHT).len(),
+            "high ground never sees less"
+        );
+        let goal = game
+            .reachable_cells(board, unit)
+            .into_keys()
+            .filter(|c| {
+                let range = sight_range(world_map, *c, DEFAULT_SIGHT);
+                board
+                    .distance(start, *c)
+                    .is_some_and(|d| d > 2 * DEFAULT_SIGHT + 2)
+                    && !board.cells_in_range(*c, range).contains_key(&start)
+            })
+            .min()
+            .unwrap();
+        game.apply(
+            board,
+            Command::MoveUnit {
+                unit,
+                goal: Some(goal),
+            },
+        )
+        .unwrap();
+        let after = game.visibility(board, PlayerId(0));
+        assert_eq!(game.units[&unit].cell, goal);
+        assert_eq!(after.get(goal), CellVisibility::Visible);
+        assert_eq!(after.get(start), CellVisibility::Explored);
+        let unseen = world_map
+            .iter_cells()
+            .map(|c| CellId(c.site()))
+            .find(|c| !after.explored.contains(c))
+            .unwrap();
+        assert_eq!(after.get(unseen), CellVisibility::Unexplored);
+    }
+
+    #[test]
+    fn enemy_units_are_seen_only_within_sight() {
+        let test = test_board(16);
+        let (board, world_map) = (test.board(), &test.world_map);
+        let scout = unit_type("Scout");
+        let mut game = test.new_match(vec![player(0), player(1)]);
+        let start = world_map.get_valid_settlement_cells()[0];
+        let range = sight_range(world_map, start, DEFAULT_SIGHT);
+        // The cells just inside and just outside the scout's sight.
+        let rings = board.cells_in_range(start, range + 1);
+        let ring = |steps: usize| {
+            rings
+                .iter()
+                .find(|(_, s)| **s as usize == steps)
+                .map(|(c, _)| *c)
+                .unwrap()
+        };
+        let (near, far) = (ring(range), ring(range + 1));
+        for (id, controller, cell) in [
+            (100, PlayerId(0), start),
+            (101, PlayerId(1), near),
+            (102, PlayerId(1), far),
+        ] {
+            let unit = UnitId(id);
+            game.units
+                .insert(unit, Unit::new(unit, &scout, controller, cell));
+        }
+        let visibility = game.visibility(board, PlayerId(0));
+        assert_eq!(visibility.get(near), CellVisibility::Visible);
+        assert_eq!(visibility.get(far), CellVisibility::Unexplored);
+    }
+}
@@ -6,6 +6,8 @@ use std::fmt::Display;
 pub enum MainMenuAction {
     None,
     NewGame,
+    LoadGame,
+    LanGame,
     Settings,
     Exit,
 }
@@ -35,6 +37,18 @@ pub fn main_menu(ctx: &mut egui::Context, offset_x: f32, width: f32) -> MainMenu
 
                         ui.add_space(4.0);
 
+                        if ui.button("Load Game").clicked() {
+                            action = MainMenuAction::LoadGame;
+                        }
+
+                        ui.add_space(4.0);
+
+                        if ui.button("LAN Game").clicked() {
+                            action = MainMenuAction::LanGame;
+                        }
+
+                        ui.add_space(4.0);
+
                         if ui.button("Settings").clicked() {
                             action = MainMenuAction::Settings;
                         }
@@ -156,22 +170,196 @@ pub fn settings_menu(
         });
     action
 }
+pub struct SaveSummary {
+    pub name: String,
+    pub description: String,
+}
+pub enum LoadGameMenuAction {
+    None,
+    Load(usize),
+    Return,
+}
+pub fn load_game_menu(
+    ctx: &mut egui::Context,
+    offset_x: f32,
+    width: f32,
+    saves: &[SaveSummary],
+) -> LoadGameMenuAction {
+    let mut action = LoadGameMenuAction::None;
+    egui::CentralPanel::default().show(ctx, |_ui| {});
+    egui::Area::new("main_menu".into())
+        .anchor(Align2::CENTER_CENTER, egui::vec2(offset_x, 0.0))
+        .show(ctx, |ui| {
+            egui::Frame::default()
+                .inner_margin(Margin::same(12))
+                .show(ui, |ui| {
+                    ui.set_width(width);
+                    ui.vertical_centered(|ui| {
+                        ui.heading("Load Game");
+                        ui.add_space(12.0);
+                        if saves.is_empty() {
+                            ui.label("No saved games");
+                        }
+                        egui::ScrollArea::vertical()
+                            .max_height(300.0)
+                            .show(ui, |ui| {
+                                for (i, save) in saves.iter().enumerate() {
+                                    if ui.button(&save.name).clicked() {
+                                        action = LoadGameMenuAction::Load(i);
+                                    }
+                                    ui.small(&save.description);
+                                    ui.add_space(4.0);
+                                }
+                            });
+                        ui.add_space(4.0);
+                        if ui.button("Return").clicked() {
+                            action = LoadGameMenuAction::Return;
+                        }
+                    });
+                });
+        });
+    action
+}
+fn world_type_combo(ui: &mut egui::Ui, world_type: &mut world_generation::WorldType) {
+    egui::ComboBox::from_label("World Type")
+        .selected_text(format!("{:?}", world_type))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(world_type, world_generation::WorldType::Default, "Default");
+            ui.selectable_value(world_type, world_generation::WorldType::Small, "Small");
+            ui.selectable_value(world_type, world_generation::WorldType::Large, "Large");
+            ui.selectable_value(world_type, world_generation::WorldType::Flat, "Flat");
+        });
+}
+/// World generation params saved from the new game menu under a name.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WorldPreset {
+    pub name: String,
+    pub params: world_generation::WorldGenerationParams,
+}
 pub struct NewWorldSettings<Civ> {
-    pub world_type: world_generation::WorldType,
+    /// Starts as one of the [`WorldType`](world_generation::WorldType) presets, changed under
+    /// "Advanced".
+    pub params: world_generation::WorldGenerationParams,
+    /// Base 36, the same seed and settings give the same world and match.
+    pub seed: String,
+    /// What to save the params as.
+    pub preset_name: String,
+    /// Name of the [`TerrainProfile`](world_generation::TerrainProfile) to generate with.
+    pub profile: String,
     pub player_count: usize,
     pub selected_civs: Vec<Option<Civ>>,
+    /// `None` for a human player.
+    pub player_ais: Vec<Option<game_core::ai::Difficulty>>,
+    /// Asked for at the start of each of a human player's turns in hot-seat, empty for none.
+    pub player_pins: Vec<String>,
+    /// Each built-in victory condition and whether it's enabled.
+    pub victories: Vec<(game_core::Victory, bool)>,
 }
 pub enum NewGameMenuAction {
     None,
     Start,
+    RandomizeSeed,
+    /// Save `params` as a preset called `preset_name`, replacing any preset with that name.
+    SavePreset,
+    DeletePreset(usize),
     Return,
 }
+fn seed_is_valid(seed: &str) -> bool {
+    !seed.is_empty() && seed.chars().all(|c| c.is_digit(36))
+}
+/// Every world generation param, the seed and the saved presets.
+fn advanced_world_settings<Civ>(
+    ui: &mut egui::Ui,
+    settings: &mut NewWorldSettings<Civ>,
+    presets: &[WorldPreset],
+    action: &mut NewGameMenuAction,
+) {
+    use world_generation::WorldGenerationParams as Params;
+    let params = &mut settings.params;
+    ui.add(egui::Slider::new(&mut params.width, Params::SIZE_RANGE).text("Width"));
+    ui.add(egui::Slider::new(&mut params.height, Params::SIZE_RANGE).text("Height"));
+    ui.add(egui::Slider::new(&mut params.plate_count, Params::PLATE_COUNT_RANGE).text("Plates"));
+    ui.add(
+        egui::Slider::new(&mut params.plate_size, Params::REGION_SIZE_RANGE)
+            .logarithmic(true)
+            .text("Plate Size"),
+    );
+    ui.add(
+        egui::Slider::new(&mut params.continent_count, Params::CONTINENT_COUNT_RANGE)
+            .text("Continents"),
+    );
+    ui.add(
+        egui::Slider::new(&mut params.continent_size, Params::REGION_SIZE_RANGE)
+            .logarithmic(true)
+            .text("Continent Size"),
+    );
+    ui.add(egui::Slider::new(&mut params.ocean_count, Params::OCEAN_COUNT_RANGE).text("Oceans"));
+    ui.add(
+        egui::Slider::new(&mut params.ocean_size, Params::REGION_SIZE_RANGE)
+            .logarithmic(true)
+            .text("Ocean Size"),
+    );
+    ui.add(egui::Slider::new(&mut params.scale, Params::SCALE_RANGE).text("Scale"));
+    ui.label(format!("{} cells", params.cell_count()));
+    let valid = match params.validate() {
+        Ok(()) => true,
+        Err(e) => {
+            ui.colored_label(ui.visuals().error_fg_color, e.to_string());
+            false
+        }
+    };
+    ui.add_space(4.0);
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut settings.seed)
+                .hint_text("Seed")
+                .desired_width(140.0),
+        );
+        if ui.button("Randomize").clicked() {
+            *action = NewGameMenuAction::RandomizeSeed;
+        }
+    });
+    if !seed_is_valid(&settings.seed) {
+        ui.colored_label(
+            ui.visuals().error_fg_color,
+            "The seed can only be letters and digits",
+        );
+    }
+    ui.add_space(4.0);
+    for (i, preset) in presets.iter().enumerate() {
+        ui.horizontal(|ui| {
+            if ui.button(&preset.name).clicked() {
+                settings.params = preset.params;
+                settings.preset_name = preset.name.clone();
+            }
+            if ui.small_button("Delete").clicked() {
+                *action = NewGameMenuAction::DeletePreset(i);
+            }
+        });
+    }
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut settings.preset_name)
+                .hint_text("Preset name")
+                .desired_width(140.0),
+        );
+        let can_save = valid && !settings.preset_name.trim().is_empty();
+        if ui
+            .add_enabled(can_save, egui::Button::new("Save Preset"))
+            .clicked()
+        {
+            *action = NewGameMenuAction::SavePreset;
+        }
+    });
+}
 pub fn new_game_menu<CivId: PartialEq + Eq + std::hash::Hash + Copy, Civ: Display + Ord>(
     ctx: &mut egui::Context,
     offset_x: f32,
     width: f32,
     settings: &mut NewWorldSettings<CivId>,
     civs: &std::collections::HashMap<CivId, Civ>,
+    presets: &[WorldPreset],
+    profiles: &[String],
 ) -> NewGameMenuAction {
     let mut action = NewGameMenuAction::None;
     let mut civ_list = civs.iter().collect::<Vec<_>>();
@@ -185,30 +373,21 @@ pub fn new_game_menu<CivId: PartialEq + Eq + std::hash::Hash + Copy, Civ: Displa
                 ui.heading("New Game");
                 ui.add_space(12.0);
                 //ui.radio_value(temp_params.world_type, alternative, atoms);
-                egui::ComboBox::from_label("World Type")
-                    .selected_text(format!("{:?}", settings.world_type))
+                let mut world_type = settings.params.world_type;
+                world_type_combo(ui, &mut world_type);
+                if world_type != settings.params.world_type {
+                    settings.params = world_type.get_params();
+                }
+                egui::ComboBox::from_label("Terrain")
+                    .selected_text(&settings.profile)
                     .show_ui(ui, |ui| {
-                        ui.selectable_value(
-                            &mut settings.world_type,
-                            world_generation::WorldType::Default,
-                            "Default",
-                        );
-                        ui.selectable_value(
-                            &mut settings.world_type,
-                            world_generation::WorldType::Small,
-                            "Small",
-                        );
-                        ui.selectable_value(
-                            &mut settings.world_type,
-                            world_generation::WorldType::Large,
-                            "Large",
-                        );
-                        ui.selectable_value(
-                            &mut settings.world_type,
-                            world_generation::WorldType::Flat,
-                            "Flat",
-                        );
+                        for profile in profiles {
+                            ui.selectable_value(&mut settings.profile, profile.clone(), profile);
+                        }
                     });
+                egui::CollapsingHeader::new("Advanced").show(ui, |ui| {
+                    advanced_world_settings(ui, settings, presets, &mut action);
+                });
                 ui.add_space(4.0);
                 let slider = egui::Slider::new(
                     &mut settings.player_count, // or your actual field name
@@ -235,12 +414,61 @@ pub fn new_game_menu<CivId: PartialEq + Eq + std::hash::Hash + Copy, Civ: Displa
                                 );
                             }
                         });
+                    let control = settings.player_ais.get_mut(i).unwrap();
+                    egui::ComboBox::from_label(format!("Player {} Control", i + 1))
+                        .selected_text(match control {
+                            Some(difficulty) => format!("AI ({difficulty:?})"),
+                            None => "Human".to_string(),
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(control, None, "Human");
+                            for difficulty in game_core::ai::Difficulty::ALL {
+                                ui.selectable_value(
+                                    control,
+                                    Some(difficulty),
+                                    format!("AI ({difficulty:?})"),
+                                );
+                            }
+                        });
+                    if control.is_none() {
+                        let pin = egui::TextEdit::singleline(&mut settings.player_pins[i])
+                            .password(true)
+                            .hint_text(format!("Player {} PIN (optional)", i + 1));
+                        ui.add(pin);
+                    }
                 }
+                ui.add_space(4.0);
+                ui.label("Victory Conditions");
+                for (victory, enabled) in settings.victories.iter_mut() {
+                    ui.checkbox(enabled, victory.condition().name());
+                    match victory {
+                        game_core::Victory::Conquest(_) => {}
+                        game_core::Victory::Territorial(territorial) => {
+                            let mut percent = territorial.share * 100.0;
+                            let slider = egui::Slider::new(&mut percent, 10.0..=100.0)
+                                .clamping(egui::SliderClamping::Always)
+                                .suffix("%")
+                                .text("Land Controlled");
+                            if ui.add_enabled(*enabled, slider).changed() {
+                                territorial.share = percent / 100.0;
+                            }
+                        }
+                        game_core::Victory::TurnLimit(turn_limit) => {
+                            let slider = egui::Slider::new(&mut turn_limit.turns, 10..=500)
+                                .clamping(egui::SliderClamping::Always)
+                                .text("Turn Limit");
+                            ui.add_enabled(*enabled, slider);
+                        }
+                    }
+                }
+                ui.add_space(4.0);
                 let start_button_enabled = settings
                     .selected_civs
                     .iter()
                     .take(settings.player_count)
-                    .all(|civ| civ.is_some());
+                    .all(|civ| civ.is_some())
+                    && settings.params.validate().is_ok()
+                    && seed_is_valid(&settings.seed);
                 let start_button = egui::Button::new("Start");
                 if ui.add_enabled(start_button_enabled, start_button).clicked() {
                     action = NewGameMenuAction::Start;
@@ -253,3 +481,108 @@ pub fn new_game_menu<CivId: PartialEq + Eq + std::hash::Hash + Copy, Civ: Displa
         });
     action
 }
+pub struct LanSettings<Civ> {
+    /// Where to listen when hosting.
+    pub host_address: String,
+    /// The host to connect to when joining.
+    pub join_address: String,
+    pub civ: Option<Civ>,
+    /// Only used by the host.
+    pub world_type: world_generation::WorldType,
+    /// What the lobby is doing, shown under the buttons.
+    pub status: String,
+    /// Set once hosting or joining, the addresses and civ can't change after that.
+    pub connected: bool,
+    /// Set on the host once a player has joined.
+    pub can_start: bool,
+}
+impl<Civ> LanSettings<Civ> {
+    /// Back to picking the addresses and civ, with `status` saying why.
+    pub fn disconnect(&mut self, status: String) {
+        self.status = status;
+        self.connected = false;
+        self.can_start = false;
+    }
+}
+pub enum LanMenuAction {
+    None,
+    Host,
+    Join,
+    Start,
+    Return,
+}
+pub fn lan_menu<CivId: PartialEq + Eq + std::hash::Hash + Copy, Civ: Display + Ord>(
+    ctx: &mut egui::Context,
+    offset_x: f32,
+    width: f32,
+    settings: &mut LanSettings<CivId>,
+    civs: &std::collections::HashMap<CivId, Civ>,
+) -> LanMenuAction {
+    let mut action = LanMenuAction::None;
+    let mut civ_list = civs.iter().collect::<Vec<_>>();
+    civ_list.sort_by_key(|e| e.1);
+    egui::CentralPanel::default().show(ctx, |_ui| {});
+    egui::Area::new("main_menu".into())
+        .anchor(Align2::CENTER_CENTER, egui::vec2(offset_x, 0.0))
+        .show(ctx, |ui| {
+            ui.set_width(width);
+            ui.vertical_centered(|ui| {
+                ui.heading("LAN Game");
+                ui.add_space(12.0);
+                ui.add_enabled_ui(!settings.connected, |ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut settings.host_address)
+                            .hint_text("Listen on"),
+                    );
+                    ui.add(
+                        egui::TextEdit::singleline(&mut settings.join_address)
+                            .hint_text("Host address"),
+                    );
+                    egui::ComboBox::from_label("Civ")
+                        .selected_text(match &settings.civ {
+                            Some(civ) => format!("{}", civs.get(civ).unwrap()),
+                            None => "Select Civ".to_string(),
+                        })
+                        .show_ui(ui, |ui| {
+                            for (civ_id, civ) in civ_list.iter() {
+                                ui.selectable_value(
+                                    &mut settings.civ,
+                                    Some(**civ_id),
+                                    format!("{}", civ),
+                                );
+                            }
+                        });
+                });
+                world_type_combo(ui, &mut settings.world_type);
+                ui.add_space(4.0);
+                let can_connect = !settings.connected && settings.civ.is_some();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(can_connect, egui::Button::new("Host"))
+                        .clicked()
+                    {
+                        action = LanMenuAction::Host;
+                    }
+                    if ui
+                        .add_enabled(can_connect, egui::Button::new("Join"))
+                        .clicked()
+                    {
+                        action = LanMenuAction::Join;
+                    }
+                    if ui
+                        .add_enabled(settings.can_start, egui::Button::new("Start"))
+                        .clicked()
+                    {
+                        action = LanMenuAction::Start;
+                    }
+                });
+                ui.add_space(4.0);
+                ui.label(&settings.status);
+                ui.add_space(4.0);
+                if ui.button("Return").clicked() {
+                    action = LanMenuAction::Return;
+                }
+            });
+        });
+    action
+}
@@ -0,0 +1,164 @@
+//! Compares [`pathfinding::a_star`] with the `Vec` based implementation it replaced, on a
+//! `WorldType::Large` map.
+use std::{collections::HashMap, hint::black_box};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use glam::{Vec2, Vec3Swizzles};
+use pathfinding::{Distance, MovementCostModel};
+use petgraph::{Graph, graph::NodeIndex};
+use rand::{SeedableRng, rngs::StdRng};
+use world_generation::{CellId, WorldMap, WorldType, generate_world};
+
+fn bench_a_star(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(1);
+    let world_map = generate_world(WorldType::Large.get_params(), &mut rng).unwrap();
+    let (graph, nodes) = pathfinding::get_graph(&world_map);
+    // The old version only knew about distance and slope, so it searches the edges `Distance`
+    // allows, weighted with the same costs.
+    let walkable = graph.filter_map(
+        |_, cell| Some(*cell),
+        |edge, _| {
+            let (a, b) = graph.edge_endpoints(edge).unwrap();
+            Distance.step_cost(&world_map, graph[a], graph[b])
+        },
+    );
+    // The two cells furthest apart that are still connected, the worst case for both versions.
+    let start = CellId(0);
+    let costs = petgraph::algo::dijkstra(&walkable, nodes[&start], None, |e| *e.weight());
+    let goal = graph[*costs
+        .iter()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(n, _)| n)
+        .unwrap()];
+
+    let mut group = c.benchmark_group("a_star_large_map");
+    group.sample_size(10);
+    group.bench_function("binary_heap", |b| {
+        b.iter(|| {
+            pathfinding::a_star(
+                black_box(start),
+                black_box(goal),
+                &graph,
+                &nodes,
+                &world_map,
+                &Distance,
+            )
+        })
+    });
+    group.bench_function("linear_scan", |b| {
+        b.iter(|| {
+            legacy::a_star(
+                black_box(start),
+                black_box(goal),
+                &walkable,
+                &nodes,
+                &world_map,
+            )
+        })
+    });
+    group.finish();
+}
+
+/// The original implementation, kept as a baseline.
+mod legacy {
+    use super::*;
+
+    #[derive(Clone)]
+    struct AStarNode {
+        cell_id: CellId,
+        g: f32,
+        h: f32,
+        parent: Option<Box<AStarNode>>,
+    }
+    impl AStarNode {
+        fn new(cell_id: CellId, g: f32, h: f32, parent: Option<Box<AStarNode>>) -> Self {
+            Self {
+                cell_id,
+                g,
+                h,
+                parent,
+            }
+        }
+        fn f(&self) -> f32 {
+            self.g + self.h
+        }
+    }
+
+    pub fn a_star(
+        start: CellId,
+        goal: CellId,
+        graph: &Graph<CellId, f32>,
+        nodes: &HashMap<CellId, NodeIndex>,
+        world_map: &WorldMap,
+    ) -> Option<Vec<CellId>> {
+        let mut open_list = vec![AStarNode::new(
+            start,
+            0.0,
+            heuristic(
+                world_map.get_position_for_cell(start).xz(),
+                world_map.get_position_for_cell(goal).xz(),
+            ),
+            None,
+        )];
+        let mut closed_list = Vec::<AStarNode>::new();
+        while !open_list.is_empty() {
+            let current = open_list
+                .iter()
+                .min_by(|a, b| a.f().total_cmp(&b.f()))
+                .unwrap()
+                .clone();
+            if current.cell_id == goal {
+                return Some(reconstruct_path(current));
+            }
+            open_list.retain(|n| n.cell_id != current.cell_id);
+            closed_list.push(current.clone());
+            for n_cell_id in world_map.get_neighbours(current.cell_id) {
+                if closed_list.iter().any(|n| n.cell_id == n_cell_id) {
+                    continue;
+                }
+                let (Some(current_node), Some(next_node)) =
+                    (nodes.get(&current.cell_id), nodes.get(&n_cell_id))
+                else {
+                    continue;
+                };
+                let edges = graph
+                    .edges_connecting(*current_node, *next_node)
+                    .collect::<Vec<_>>();
+                if let Some(distance) = edges.first() {
+                    let tent_g = current.g + distance.weight();
+                    if let Some(neighbor) = open_list.iter().find(|n| n.cell_id == n_cell_id) {
+                        if tent_g >= neighbor.g {
+                            continue;
+                        }
+                    } else {
+                        open_list.push(AStarNode::new(
+                            n_cell_id,
+                            tent_g,
+                            heuristic(
+                                world_map.get_position_for_cell(n_cell_id).xz(),
+                                world_map.get_position_for_cell(goal).xz(),
+                            ),
+                            Some(Box::new(current.clone())),
+                        ));
+                    }
+                }
+            }
+        }
+        None
+    }
+    fn reconstruct_path(current: AStarNode) -> Vec<CellId> {
+        let mut path = Vec::new();
+        let mut current = Some(Box::new(current));
+        while let Some(node) = current {
+            path.push(node.cell_id);
+            current = node.parent;
+        }
+        path
+    }
+    fn heuristic(start: Vec2, goal: Vec2) -> f32 {
+        start.distance(goal)
+    }
+}
+
+criterion_group!(benches, bench_a_star);
+criterion_main!(benches);
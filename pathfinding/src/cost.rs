@@ -0,0 +1,148 @@
+//! How much it costs a unit to step from one cell onto a neighbour.
+use std::collections::BTreeMap;
+
+use glam::{Vec3, Vec3Swizzles};
+use serde::{Deserialize, Serialize};
+use world_generation::{CellId, WorldMap};
+
+/// Raw cell height below which a cell is water.
+pub const WATER_LEVEL: f32 = 0.5;
+
+/// Prices the steps [`a_star`](crate::a_star) takes between neighbouring cells.
+///
+/// A step should never cost less than the horizontal distance between the two cells, otherwise
+/// the search may miss the cheapest path.
+pub trait MovementCostModel {
+    /// Cost of stepping from `from` onto its neighbour `to`, `None` if the step isn't allowed.
+    fn step_cost(&self, world_map: &WorldMap, from: CellId, to: CellId) -> Option<f32>;
+}
+
+/// Straight-line distance, blocked only by steep slopes. For rules where the terrain shouldn't
+/// matter, like how far apart two units are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Distance;
+impl MovementCostModel for Distance {
+    fn step_cost(&self, world_map: &WorldMap, from: CellId, to: CellId) -> Option<f32> {
+        let from = world_map.get_position_for_cell(from);
+        let to = world_map.get_position_for_cell(to);
+        let length = from.distance(to);
+        ((from.y - to.y).abs() / length < 0.3).then_some(length)
+    }
+}
+
+/// Counts the steps [`Distance`] allows, each costing 1. Meant for
+/// [`reachable_within`](crate::reachable_within), as it breaks the [`a_star`](crate::a_star)
+/// heuristic on maps where neighbours are more than 1 apart.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Steps;
+impl MovementCostModel for Steps {
+    fn step_cost(&self, world_map: &WorldMap, from: CellId, to: CellId) -> Option<f32> {
+        Distance.step_cost(world_map, from, to).map(|_| 1.0)
+    }
+}
+
+/// Movement costs for one kind of unit, read from the `.civ.ron` files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostProfile {
+    /// Steepest slope, height change over distance, the unit can move along.
+    pub max_slope: f32,
+    /// Extra cost for each unit of height climbed, going downhill costs nothing extra.
+    pub climb: f32,
+    /// Cost multiplier for entering water, `None` if the unit can't cross it.
+    pub water: Option<f32>,
+    /// Extra cost multiplier per unit of a resource in the entered cell, so `"Forest": 1.0`
+    /// doubles the cost of the densest forest.
+    pub resources: BTreeMap<String, f32>,
+    /// Extra cost multiplier for wading into a river, moving along one costs nothing extra.
+    ///
+    /// Profiles saved before rivers mattered leave it out and keep crossing them for free.
+    #[serde(default)]
+    pub river_crossing: f32,
+}
+impl Default for CostProfile {
+    fn default() -> Self {
+        Self {
+            max_slope: 0.3,
+            climb: 1.0,
+            water: None,
+            resources: BTreeMap::from([("Forest".to_string(), 1.0)]),
+            river_crossing: 0.5,
+        }
+    }
+}
+impl MovementCostModel for CostProfile {
+    fn step_cost(&self, world_map: &WorldMap, from: CellId, to: CellId) -> Option<f32> {
+        let is_water = world_map.get_raw_height(&to) < WATER_LEVEL;
+        if is_water && self.water.is_none() {
+            return None;
+        }
+        let crosses_river = world_map.has_river(to) && !world_map.has_river_between(from, to);
+        let from = surface_position(world_map, from);
+        let to_pos = surface_position(world_map, to);
+        let length = from.distance(to_pos);
+        let rise = to_pos.y - from.y;
+        if rise.abs() / length >= self.max_slope {
+            return None;
+        }
+        let mut multiplier = if is_water {
+            self.water.unwrap_or(1.0).max(1.0)
+        } else {
+            1.0
+        };
+        if crosses_river {
+            multiplier += self.river_crossing.max(0.0);
+        }
+        if let Some(resources) = world_map.get_resources_for_cell(to) {
+            multiplier += self
+                .resources
+                .iter()
+                .filter_map(|(name, factor)| resources.get(name).map(|amount| factor * amount))
+                .map(|extra| extra.max(0.0))
+                .sum::<f32>();
+        }
+        Some((length + self.climb.max(0.0) * rise.max(0.0)) * multiplier)
+    }
+}
+
+/// Which [`CostProfile`] a unit type moves with.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum MovementProfile {
+    /// Walks over any land that isn't too steep, slowed a little by forest.
+    #[default]
+    Infantry,
+    /// Fine on open ground, but struggles with hills and forest.
+    Cavalry,
+    /// Floats over steep ground and can cross water, slowly.
+    Mage,
+    Custom(CostProfile),
+}
+impl MovementProfile {
+    pub fn costs(&self) -> CostProfile {
+        match self {
+            MovementProfile::Infantry => CostProfile::default(),
+            MovementProfile::Cavalry => CostProfile {
+                max_slope: 0.2,
+                climb: 3.0,
+                water: None,
+                resources: BTreeMap::from([("Forest".to_string(), 2.5)]),
+                river_crossing: 1.0,
+            },
+            MovementProfile::Mage => CostProfile {
+                max_slope: 0.6,
+                climb: 0.5,
+                water: Some(3.0),
+                resources: BTreeMap::from([("Forest".to_string(), 0.5)]),
+                river_crossing: 0.0,
+            },
+            MovementProfile::Custom(costs) => costs.clone(),
+        }
+    }
+}
+
+/// Position of a cell with water cells raised to the surface.
+fn surface_position(world_map: &WorldMap, cell: CellId) -> Vec3 {
+    let position = world_map.get_position_for_cell(cell);
+    let surface = world_map.get_raw_height(&cell).max(WATER_LEVEL) * world_map.height_scale;
+    position.xz().extend(surface).xzy()
+}
@@ -3,10 +3,18 @@ use glam::Vec2;
 use glam::Vec3Swizzles;
 use petgraph::Graph;
 use petgraph::prelude::*;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
 use world_generation::CellId;
 use world_generation::WorldMap;
 
+mod cost;
+mod plan;
+pub use cost::{CostProfile, Distance, MovementCostModel, MovementProfile, Steps, WATER_LEVEL};
+pub use plan::{Waypoint, split_into_turns};
+
+/// Links every cell to its neighbours, weighted by the distance between them. Which steps a unit
+/// can actually take, and what they cost, is up to the [`MovementCostModel`] given to [`a_star`].
 pub fn get_graph(world_map: &WorldMap) -> (Graph<CellId, f32>, HashMap<CellId, NodeIndex>) {
     let mut graph = Graph::<CellId, f32>::new();
     let mut nodes = HashMap::new();
@@ -19,122 +27,264 @@ pub fn get_graph(world_map: &WorldMap) -> (Graph<CellId, f32>, HashMap<CellId, N
         let c_pos = world_map.get_position_for_cell(*cell_id);
         for n_cell_id in world_map.get_neighbours(*cell_id) {
             let n_pos = world_map.get_position_for_cell(n_cell_id);
-            let length = c_pos.distance(n_pos);
-            let height = (c_pos.y - n_pos.y).abs();
-            let slope = height / length;
-            if slope < 0.3 {
-                graph.add_edge(
-                    *node,
-                    *nodes.get(&n_cell_id).unwrap(),
-                    c_pos.distance(n_pos),
-                );
-            }
+            graph.add_edge(
+                *node,
+                *nodes.get(&n_cell_id).unwrap(),
+                c_pos.distance(n_pos),
+            );
         }
     }
     (graph, nodes)
 }
-#[derive(Clone)]
-struct AStarNode {
-    cell_id: CellId,
-    g: f32,
-    h: f32,
-    parent: Option<Box<AStarNode>>,
+/// Open list entry, ordered so the [`BinaryHeap`] pops the lowest `f` first.
+#[derive(Clone, Copy, PartialEq)]
+struct OpenNode {
+    f: f32,
+    node: NodeIndex,
 }
-
-impl AStarNode {
-    fn new(cell_id: CellId, g: f32, h: f32, parent: Option<Box<AStarNode>>) -> Self {
-        Self {
-            cell_id,
-            g,
-            h,
-            parent,
-        }
+impl Eq for OpenNode {}
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .f
+            .total_cmp(&self.f)
+            .then_with(|| other.node.cmp(&self.node))
     }
-    fn f(&self) -> f32 {
-        self.g + self.h
+}
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
+/// Cheapest path from `start` to `goal` under `costs`, returned goal first.
 pub fn a_star(
     start: CellId,
     goal: CellId,
     graph: &Graph<CellId, f32>,
     nodes: &HashMap<CellId, NodeIndex>,
     world_map: &WorldMap,
+    costs: &impl MovementCostModel,
 ) -> Option<Vec<CellId>> {
-    let mut open_list = vec![AStarNode::new(
-        start,
-        0.0,
-        heuristic(
-            world_map.get_position_for_cell(start).xz(),
-            world_map.get_position_for_cell(goal).xz(),
-        ),
-        None,
-    )];
-    let mut closed_list = Vec::<AStarNode>::new();
-    while !open_list.is_empty() {
-        let current = open_list
-            .iter()
-            .min_by(|a, b| a.f().total_cmp(&b.f()))
-            .unwrap()
-            .clone();
-        if current.cell_id == goal {
-            return Some(reconstruct_path(current));
+    let start_node = *nodes.get(&start)?;
+    let goal_node = *nodes.get(&goal)?;
+    let goal_pos = world_map.get_position_for_cell(goal).xz();
+    let h = |cell: CellId| heuristic(world_map.get_position_for_cell(cell).xz(), goal_pos);
+
+    let node_count = graph.node_count();
+    let mut g = vec![f32::INFINITY; node_count];
+    let mut parents = vec![None::<NodeIndex>; node_count];
+    let mut closed = vec![false; node_count];
+    let mut open = BinaryHeap::new();
+    g[start_node.index()] = 0.0;
+    open.push(OpenNode {
+        f: h(start),
+        node: start_node,
+    });
+    while let Some(OpenNode { node: current, .. }) = open.pop() {
+        if current == goal_node {
+            return Some(reconstruct_path(graph, &parents, current));
         }
-        open_list.retain(|n| n.cell_id != current.cell_id);
-        closed_list.push(current.clone());
-        //let v_cell = voronoi.cell(current.cell_id.0);
-        for n_cell_id in world_map.get_neighbours(current.cell_id) {
-            if closed_list.iter().any(|n| n.cell_id == n_cell_id) {
+        // Stale entries are left in the heap when a node is re-pushed with a better `g`.
+        if std::mem::replace(&mut closed[current.index()], true) {
+            continue;
+        }
+        let current_g = g[current.index()];
+        for edge in graph.edges(current) {
+            let next = edge.target();
+            if closed[next.index()] {
                 continue;
             }
-            let current_node = nodes.get(&current.cell_id);
-            if current_node.is_none() {
+            let Some(cost) = costs.step_cost(world_map, graph[current], graph[next]) else {
                 continue;
-            }
-            let next_node = nodes.get(&n_cell_id);
-            if next_node.is_none() {
+            };
+            let tent_g = current_g + cost;
+            if tent_g >= g[next.index()] {
                 continue;
             }
-            let edges = graph
-                .edges_connecting(
-                    *current_node.expect("Current cell not found in node index map"),
-                    *next_node.expect("Neightbour cell not found in node index map"),
-                )
-                .collect::<Vec<_>>();
-            if let Some(distance) = edges.first() {
-                let tent_g = current.g + distance.weight();
-                if let Some(neighbor) = open_list.iter().find(|n| n.cell_id == n_cell_id) {
-                    if tent_g >= neighbor.g {
-                        continue;
-                    }
-                } else {
-                    open_list.push(AStarNode::new(
-                        n_cell_id,
-                        tent_g,
-                        heuristic(
-                            world_map.get_position_for_cell(n_cell_id).xz(),
-                            world_map.get_position_for_cell(goal).xz(),
-                        ),
-                        Some(Box::new(current.clone())),
-                    ));
-                }
-            }
+            g[next.index()] = tent_g;
+            parents[next.index()] = Some(current);
+            open.push(OpenNode {
+                f: tent_g + h(graph[next]),
+                node: next,
+            });
         }
     }
     None
 }
-fn reconstruct_path(current: AStarNode) -> Vec<CellId> {
-    let mut path = Vec::new();
-    let mut current = Some(Box::new(current));
-    while let Some(node) = current {
-        path.push(node.cell_id);
-        current = node.parent;
+/// Every cell that can be reached from `start` for at most `budget`, along with the cheapest
+/// cost of getting there. `start` is included at no cost.
+pub fn reachable_within(
+    start: CellId,
+    budget: f32,
+    graph: &Graph<CellId, f32>,
+    nodes: &HashMap<CellId, NodeIndex>,
+    world_map: &WorldMap,
+    costs: &impl MovementCostModel,
+) -> HashMap<CellId, f32> {
+    let mut reached = HashMap::new();
+    let Some(&start_node) = nodes.get(&start) else {
+        return reached;
+    };
+    let mut best = vec![f32::INFINITY; graph.node_count()];
+    let mut open = BinaryHeap::new();
+    best[start_node.index()] = 0.0;
+    open.push(OpenNode {
+        f: 0.0,
+        node: start_node,
+    });
+    while let Some(OpenNode {
+        f: cost,
+        node: current,
+    }) = open.pop()
+    {
+        if cost > best[current.index()] {
+            continue;
+        }
+        reached.insert(graph[current], cost);
+        for edge in graph.edges(current) {
+            let next = edge.target();
+            let Some(step) = costs.step_cost(world_map, graph[current], graph[next]) else {
+                continue;
+            };
+            let total = cost + step;
+            if total > budget || total >= best[next.index()] {
+                continue;
+            }
+            best[next.index()] = total;
+            open.push(OpenNode {
+                f: total,
+                node: next,
+            });
+        }
+    }
+    reached
+}
+fn reconstruct_path(
+    graph: &Graph<CellId, f32>,
+    parents: &[Option<NodeIndex>],
+    goal: NodeIndex,
+) -> Vec<CellId> {
+    let mut path = vec![graph[goal]];
+    let mut current = goal;
+    while let Some(parent) = parents[current.index()] {
+        path.push(graph[parent]);
+        current = parent;
     }
-    //path.reverse();
     path
 }
 
 fn heuristic(start: Vec2, goal: Vec2) -> f32 {
     start.distance(goal)
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+    use world_generation::{WorldType, generate_world};
+
+    use super::*;
+
+    #[test]
+    fn a_star_matches_dijkstra() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let world_map = generate_world(WorldType::Small.get_params(), &mut rng).unwrap();
+        let (graph, nodes) = get_graph(&world_map);
+        let profile = MovementProfile::Cavalry.costs();
+        // Only the steps the profile allows, priced by it.
+        let priced = graph.filter_map(
+            |_, cell| Some(*cell),
+            |edge, _| {
+                let (a, b) = graph.edge_endpoints(edge).unwrap();
+                profile.step_cost(&world_map, graph[a], graph[b])
+            },
+        );
+        let start = world_map
+            .iter_cells()
+            .map(|c| CellId(c.site()))
+            .find(|c| world_map.get_raw_height(c) >= WATER_LEVEL)
+            .unwrap();
+        let costs = petgraph::algo::dijkstra(&priced, nodes[&start], None, |e| *e.weight());
+        for (node, cost) in costs.iter().step_by(17) {
+            let path = a_star(start, graph[*node], &graph, &nodes, &world_map, &profile).unwrap();
+            assert_eq!(*path.first().unwrap(), graph[*node]);
+            assert_eq!(*path.last().unwrap(), start);
+            let length = path
+                .windows(2)
+                .map(|w| {
+                    let edge = priced.find_edge(nodes[&w[1]], nodes[&w[0]]).unwrap();
+                    priced[edge]
+                })
+                .sum::<f32>();
+            assert!(
+                (length - cost).abs() <= 1e-3 * cost.max(1.0),
+                "{length} != {cost}"
+            );
+        }
+    }
+
+    #[test]
+    fn each_turn_stays_within_speed() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let world_map = generate_world(WorldType::Small.get_params(), &mut rng).unwrap();
+        let (graph, nodes) = get_graph(&world_map);
+        let start = CellId(0);
+        let goal = (0..graph.node_count())
+            .rev()
+            .map(|i| graph[NodeIndex::new(i)])
+            .find(|goal| {
+                *goal != start
+                    && a_star(start, *goal, &graph, &nodes, &world_map, &Distance).is_some()
+            })
+            .unwrap();
+        let mut path = a_star(start, goal, &graph, &nodes, &world_map, &Distance).unwrap();
+        path.reverse();
+        let speed = 4.0;
+        let waypoints = split_into_turns(&path, &world_map, &Distance, speed, 0.0, 1).unwrap();
+        assert_eq!(waypoints.len(), path.len() - 1);
+        let mut spent = HashMap::<u32, (f32, usize)>::new();
+        for (step, waypoint) in path.windows(2).zip(waypoints.iter()) {
+            assert_eq!(step[1], waypoint.cell);
+            let cost = Distance.step_cost(&world_map, step[0], step[1]).unwrap();
+            let turn = spent.entry(waypoint.turn).or_default();
+            turn.0 += cost;
+            turn.1 += 1;
+        }
+        for turn in 1..=waypoints.last().map_or(1, |w| w.turn) {
+            let (cost, steps) = spent[&turn];
+            assert!(cost <= speed || steps == 1, "turn {turn} spent {cost}");
+        }
+    }
+
+    #[test]
+    fn reachable_within_matches_dijkstra() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let world_map = generate_world(WorldType::Small.get_params(), &mut rng).unwrap();
+        let (graph, nodes) = get_graph(&world_map);
+        let profile = MovementProfile::Infantry.costs();
+        let priced = graph.filter_map(
+            |_, cell| Some(*cell),
+            |edge, _| {
+                let (a, b) = graph.edge_endpoints(edge).unwrap();
+                profile.step_cost(&world_map, graph[a], graph[b])
+            },
+        );
+        let start = world_map
+            .iter_cells()
+            .map(|c| CellId(c.site()))
+            .find(|c| world_map.get_raw_height(c) >= WATER_LEVEL)
+            .unwrap();
+        let budget = 12.0;
+        let reached = reachable_within(start, budget, &graph, &nodes, &world_map, &profile);
+        let costs = petgraph::algo::dijkstra(&priced, nodes[&start], None, |e| *e.weight());
+        let expected = costs
+            .iter()
+            .filter(|(_, cost)| **cost <= budget)
+            .map(|(node, cost)| (graph[*node], *cost))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(reached.len(), expected.len());
+        for (cell, cost) in expected {
+            assert!((reached[&cell] - cost).abs() <= 1e-3 * cost.max(1.0));
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Splitting a path into the stretches a unit covers each turn.
+use serde::{Deserialize, Serialize};
+use world_generation::{CellId, WorldMap};
+
+use crate::MovementCostModel;
+
+/// A cell along a planned route and the turn the unit gets there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Waypoint {
+    pub cell: CellId,
+    pub turn: u32,
+}
+
+/// Walks `path`, start first, spending up to `speed` each turn, beginning on `turn` with `used`
+/// already spent. A unit that hasn't moved yet this turn can always take one step, so costly
+/// terrain slows it down rather than stopping it.
+///
+/// Returns a [`Waypoint`] for every cell after the start, `None` if `costs` forbids a step.
+pub fn split_into_turns(
+    path: &[CellId],
+    world_map: &WorldMap,
+    costs: &impl MovementCostModel,
+    speed: f32,
+    mut used: f32,
+    mut turn: u32,
+) -> Option<Vec<Waypoint>> {
+    let mut waypoints = Vec::with_capacity(path.len().saturating_sub(1));
+    for step in path.windows(2) {
+        let cost = costs.step_cost(world_map, step[0], step[1])?;
+        if used > 0.0 && used + cost > speed {
+            turn += 1;
+            used = 0.0;
+        }
+        used += cost;
+        waypoints.push(Waypoint {
+            cell: step[1],
+            turn,
+        });
+    }
+    Some(waypoints)
+}
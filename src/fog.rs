@@ -0,0 +1,89 @@
+//! Fog of war, hides whatever the viewing player can't see.
+use bevy::{camera::visibility::RenderLayers, prelude::*};
+use game_core::{CellVisibility, PlayerId};
+
+use crate::{
+    ControlledArea, GameEvent, GameState, ImprovementMarker, SettlementCenter, Unit,
+    generate::WorldMap,
+    minimap::{MinimapCell, MinimapControlledArea},
+    render_layers,
+};
+
+/// Takes enemy units out of the `WORLD` layer while they're out of sight, and settlements,
+/// borders and minimap cells out of their layers until they've been explored.
+pub fn update_fog(
+    mut events: MessageReader<GameEvent>,
+    mut viewer: Local<Option<PlayerId>>,
+    game_state: Res<GameState>,
+    world_map: Res<WorldMap>,
+    pathfinding: Res<crate::pathfinding::PathFinding>,
+    mut commands: Commands,
+    units: Query<(Entity, &Unit)>,
+    settlements: Query<(Entity, &SettlementCenter)>,
+    controlled_areas: Query<(Entity, &ControlledArea)>,
+    minimap_controlled_areas: Query<(Entity, &MinimapControlledArea)>,
+    improvements: Query<(Entity, &ImprovementMarker)>,
+    minimap_cells: Query<(Entity, &MinimapCell)>,
+) {
+    let game_changed = events.read().count() > 0;
+    let viewing_player = game_state.viewing_player();
+    if !game_changed && *viewer == Some(viewing_player) {
+        return;
+    }
+    *viewer = Some(viewing_player);
+    let visibility = game_state
+        .game
+        .visibility(pathfinding.board(&world_map), viewing_player);
+    let layer = |shown: bool, layer: usize| {
+        if shown {
+            RenderLayers::layer(layer)
+        } else {
+            RenderLayers::none()
+        }
+    };
+    for (entity, unit) in units.iter() {
+        let shown = game_state.game.unit(unit.id).is_some_and(|u| {
+            u.controller == viewing_player || visibility.get(u.cell) == CellVisibility::Visible
+        });
+        commands
+            .entity(entity)
+            .insert(layer(shown, render_layers::WORLD));
+    }
+    // Settlements don't move, so once found they stay on the map.
+    let mut shown_settlements = vec![];
+    for (entity, center) in settlements.iter() {
+        let shown = game_state.game.settlement(center.id).is_some_and(|s| {
+            s.controller == viewing_player || visibility.get(s.cell) != CellVisibility::Unexplored
+        });
+        if shown {
+            shown_settlements.push(entity);
+        }
+        commands
+            .entity(entity)
+            .insert(layer(shown, render_layers::WORLD));
+    }
+    for (entity, area) in controlled_areas.iter() {
+        let shown = shown_settlements.contains(&area.0);
+        commands
+            .entity(entity)
+            .insert(layer(shown, render_layers::WORLD));
+    }
+    for (entity, area) in minimap_controlled_areas.iter() {
+        let shown = shown_settlements.contains(&area.0);
+        commands
+            .entity(entity)
+            .insert(layer(shown, render_layers::MINIMAP));
+    }
+    for (entity, marker) in improvements.iter() {
+        let shown = visibility.get(marker.cell) != CellVisibility::Unexplored;
+        commands
+            .entity(entity)
+            .insert(layer(shown, render_layers::WORLD));
+    }
+    for (entity, cell) in minimap_cells.iter() {
+        let shown = visibility.get(cell.0) != CellVisibility::Unexplored;
+        commands
+            .entity(entity)
+            .insert(layer(shown, render_layers::MINIMAP));
+    }
+}
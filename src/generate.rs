@@ -1,5 +1,5 @@
 use bevy::{
-    camera::visibility::RenderLayers,
+    camera::visibility::{NoFrustumCulling, RenderLayers},
     color::palettes::css::BLACK,
     ecs::system::SystemState,
     light::{NotShadowCaster, light_consts::lux},
@@ -19,11 +19,12 @@ use colorgrad::Gradient;
 use llm_api::{settlement_names::SettlementNameCtx, unit_spawn_barks::UnitSpawnBarkCtx};
 use rand::Rng;
 use std::ops::Deref;
+use world_generation::Biome;
 pub use world_generation::CellId;
 
 use crate::{
-    AppState, CURRENT_OS, Cell, CellHighlight, GameState, LLMProvider, LLMSettings, Random,
-    Selection, Unit, llm, render_layers,
+    AppState, CURRENT_OS, Cell, CellHighlight, GameCommand, GameState, HighlightKind, LLMProvider,
+    LLMSettings, Random, Selection, Unit, llm, minimap, render_layers,
 };
 #[derive(Resource, Default)]
 pub struct WorldMap(pub Option<world_generation::WorldMap>);
@@ -84,11 +85,19 @@ impl WorldType {
 
 #[derive(Resource)]
 pub struct WorldGenerationParams(pub Option<world_generation::WorldGenerationParams>);
+/// Shapes the terrain of newly generated worlds, saved worlds carry their own.
+#[derive(Resource, Default)]
+pub struct WorldProfile(pub world_generation::TerrainProfile);
+
+/// RNG state the current world was generated from, kept so a saved match can rebuild the same map.
+#[derive(Resource, Clone)]
+pub struct WorldGenerationRng(pub crate::RandomRng);
 
 pub struct WorldPlugin;
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<WorldMap>();
+        app.init_resource::<WorldProfile>();
         app.add_computed_state::<GenerationPhase>();
         app.add_sub_state::<GenerationState>();
         app.add_systems(
@@ -290,6 +299,20 @@ fn generate_settlement_name(
         });
     }
 }
+fn biome_color(biome: Biome) -> Color {
+    let hex = match biome {
+        Biome::Ocean => "#003a6b",
+        Biome::Coast => "#0f7a8a",
+        Biome::Tundra => "#cdd2d8",
+        Biome::Desert => "#e8d7a1",
+        Biome::Grassland => "#a7c88a",
+        Biome::Forest => "#5b7f3a",
+        Biome::Jungle => "#2f6b2a",
+        Biome::Marsh => "#6b7f5a",
+        Biome::Mountain => "#8c8f93",
+    };
+    Srgba::hex(hex).unwrap().into()
+}
 fn spawn_world(
     world_map: Res<WorldMap>,
     mut commands: Commands,
@@ -354,7 +377,6 @@ fn spawn_world(
         let height = world_map.get_raw_height(&CellId(v_cell.site()));
         let color = g.at(height);
         assert!(color.to_css_hex() != "#000000");
-        let color = bevy::color::Color::srgba(color.r, color.g, color.b, 1.0); //.lighter(0.2);
         //let height_key = (height * 100.0).round() as u8;
         assert!((0.0..=1.0).contains(&height));
         // let material = if let Some(mat) = height_material_cache.get(&height_key) {
@@ -372,8 +394,10 @@ fn spawn_world(
         //     mat.clone()
         // };
 
+        // The minimap shows the biomes, the height already shows in the 3D terrain.
+        let biome_color = biome_color(world_map.get_biome(CellId(v_cell.site())));
         let cell_shape = ShapeBuilder::with(&polygon)
-            .fill(color.with_saturation(color.saturation() / 2.0))
+            .fill(biome_color.with_saturation(biome_color.saturation() / 2.0))
             .stroke((BLACK, 0.1))
             .build();
         commands.spawn((
@@ -384,6 +408,8 @@ fn spawn_world(
                 0.0,
             ),
             RenderLayers::from_layers(&[render_layers::MINIMAP]),
+            minimap::MinimapCell(CellId(v_cell.site())),
+            DespawnOnExit(AppState::InGame),
         ));
         let height_vertices = &vertices
             .iter()
@@ -457,6 +483,7 @@ fn spawn_world(
             },
             Ground,
             RenderLayers::from_layers(&[render_layers::WORLD]),
+            DespawnOnExit(AppState::InGame),
             children![(
                 Mesh3d(outline_mesh),
                 MeshMaterial3d(outline_material.clone()),
@@ -467,6 +494,33 @@ fn spawn_world(
         ));
         cell.observe(click_cell).observe(over_cell);
     }
+    let river_material = materials.add(StandardMaterial {
+        base_color: Srgba::hex("#1b2a4a").unwrap().into(),
+        unlit: true,
+        ..default()
+    });
+    for river in world_map.rivers() {
+        // Just above the ground, or the sea surface where a river runs out into it.
+        let points = river
+            .cells
+            .iter()
+            .map(|cell| {
+                let position = world_map.get_position_for_cell(*cell);
+                let surface = world_map.get_raw_height(cell).max(0.5) * world_map.height_scale;
+                position.with_y(surface + 0.02)
+            })
+            .collect::<Vec<_>>();
+        // Brush strokes thicken as more water gathers.
+        let half_width = (0.03 * river.flow.sqrt()).clamp(0.05, 0.25);
+        commands.spawn((
+            Mesh3d(meshes.add(crate::polyline_ribbon_mesh_3d(&points, half_width, Vec3::Y))),
+            MeshMaterial3d(river_material.clone()),
+            NoFrustumCulling,
+            NotShadowCaster,
+            RenderLayers::from_layers(&[render_layers::WORLD]),
+            DespawnOnExit(AppState::InGame),
+        ));
+    }
     // commands.spawn((
     //     Mesh3d(outline_mesh),
     //     MeshMaterial3d(outline_material.clone()),
@@ -498,6 +552,7 @@ fn spawn_world(
         Ground,
         NotShadowCaster,
         RenderLayers::from_layers(&[render_layers::WORLD]),
+        DespawnOnExit(AppState::InGame),
     ));
     commands.spawn((
         DirectionalLight {
@@ -506,6 +561,7 @@ fn spawn_world(
             ..default()
         },
         RenderLayers::from_layers(&[render_layers::WORLD]),
+        DespawnOnExit(AppState::InGame),
         //cascade_shadow_config,
         Transform::from_xyz(0.0, 200.0, -200.0).looking_at(vec3(0.0, 0.0, 0.0), Vec3::Y),
     ));
@@ -515,26 +571,32 @@ fn spawn_world(
 fn click_cell(
     mut event: On<Pointer<Click>>,
     cells: Query<&Cell>,
-    mut units: Query<&mut Unit>,
+    units: Query<&Unit>,
     mut selected_unit: ResMut<Selection>,
     mut game_state: ResMut<GameState>,
     world_map: Res<WorldMap>,
+    mut game_commands: MessageWriter<GameCommand>,
 ) {
     if event.button == PointerButton::Primary {
         match *selected_unit {
             Selection::None => {
                 if let Ok(cell) = cells.get(event.entity) {
-                    let active_player = game_state.active_player;
+                    let active_player = game_state.active_player();
                     let player = game_state.players.get_mut(&active_player).unwrap();
-                    if let Some(resources) = world_map.get_resources_for_cell(cell.cell_id) {
-                        player.add_notification(format!("Cell resources: {:?}", resources));
-                    }
+                    let yields = game_core::cell_yields(&world_map, cell.cell_id);
+                    player.add_notification(format!(
+                        "Cell yields: {:.1} food, {:.1} production, {:.1} materials",
+                        yields.food, yields.production, yields.materials
+                    ));
                 }
             }
             Selection::Unit(unit) => {
                 if let Ok(cell) = cells.get(event.entity) {
-                    let mut unit = units.get_mut(unit).unwrap();
-                    unit.goal = Some(cell.cell_id);
+                    let unit = units.get(unit).unwrap().id;
+                    game_commands.write(GameCommand(game_core::Command::MoveUnit {
+                        unit,
+                        goal: Some(cell.cell_id),
+                    }));
                     info!("Set unit's goal");
                 }
             }
@@ -549,6 +611,7 @@ fn over_cell(
     mut event: On<Pointer<Over>>,
     cells: Query<(&Cell, Entity)>,
     units: Query<&Unit>,
+    game_state: Res<GameState>,
     highlights: Query<(Entity, &CellHighlight)>,
     selected: Res<Selection>,
     world_map: Res<WorldMap>,
@@ -557,26 +620,36 @@ fn over_cell(
     pathfinding: Res<crate::pathfinding::PathFinding>,
 ) {
     if let Selection::Unit(unit_entity) = *selected {
-        let crate::pathfinding::PathFinding { graph, nodes } = pathfinding.as_ref();
-        for (e, _highlight) in highlights.iter() {
-            let mut e = commands.entity(e);
-            e.despawn();
+        for (e, highlight) in highlights.iter() {
+            if highlight.kind == HighlightKind::Path {
+                commands.entity(e).despawn();
+            }
         }
         let unit = units.get(unit_entity).unwrap();
-        let start = unit.current_cell;
         let goal = cells.get(event.entity).unwrap().0.cell_id;
-        let result = pathfinding::a_star(start, goal, graph, nodes, &world_map);
-        if let Some(result) = result {
-            for cell_id in result {
-                let cell = cells.iter().find(|e| e.0.cell_id == cell_id);
+        let board = pathfinding.board(&world_map);
+        let route = game_state.game.plan_route(board, unit.id, goal);
+        if let Some(route) = route {
+            for (i, waypoint) in route.iter().enumerate() {
+                // The last cell reached each turn gets a marker with the number of turns.
+                let turn_end = route.get(i + 1).is_none_or(|n| n.turn != waypoint.turn);
+                let turn = turn_end.then(|| waypoint.turn + 1 - game_state.game.turn());
+                let cell = cells.iter().find(|e| e.0.cell_id == waypoint.cell);
                 if let Some((cell, entity)) = cell {
+                    let color = if turn.is_some() {
+                        Color::srgb(1.0, 0.85, 0.3)
+                    } else {
+                        Color::WHITE
+                    };
                     let mut e = commands.entity(entity);
                     e.with_child((
                         Mesh3d(cell.outline.clone()),
-                        MeshMaterial3d(materials.add(Color::WHITE)),
+                        MeshMaterial3d(materials.add(color)),
                         Transform::from_xyz(0.0, 2.0, 0.0),
                         CellHighlight {
                             _unit: Some(unit_entity),
+                            kind: HighlightKind::Path,
+                            turn,
                         },
                     ));
                 }
@@ -610,28 +683,69 @@ enum GenerationState {
     Finshed,
 }
 fn gen_world(
+    mut commands: Commands,
     args: Res<WorldGenerationParams>,
+    profile: Res<WorldProfile>,
     rng: ResMut<crate::Random<crate::RandomRng>>,
+    world_rng: Option<Res<WorldGenerationRng>>,
+    pending: Option<Res<crate::save::PendingLoad>>,
     runtime: ResMut<TokioTasksRuntime>,
 ) {
     info!("Generating world...");
+    let stored = pending.map(|p| p.0.world.clone());
     let a = *args.0.as_ref().unwrap();
-    let rng = rng.0.as_ref().unwrap().clone();
+    let profile = profile.0.clone();
+    let rng = match world_rng {
+        Some(world_rng) => world_rng.0.clone(),
+        None => {
+            let rng = rng.0.as_ref().unwrap().clone();
+            commands.insert_resource(WorldGenerationRng(rng.clone()));
+            rng
+        }
+    };
     runtime.spawn_background_task(move |mut ctx| async move {
         let mut rng = rng;
-        let generated_world = world_generation::generate_world(a, &mut rng).unwrap();
+        let generated_world = match stored {
+            Some(stored) => stored.restore(&mut rng),
+            None => {
+                Ok(world_generation::generate_world_with_profile(a, &profile, &mut rng).unwrap())
+            }
+        };
+        // The terrain never changes during a match, so the movement graph is built once here.
+        let graph = generated_world.as_ref().ok().map(pathfinding::get_graph);
         ctx.run_on_main_thread(move |ctx| {
             let world = ctx.world;
-            let (mut world_map, mut next_state) = {
+            let generated_world = match generated_world {
+                Ok(generated_world) => generated_world,
+                Err(e) => {
+                    error!("Failed to restore saved world: {e}");
+                    world.remove_resource::<crate::save::PendingLoad>();
+                    world
+                        .resource_mut::<NextState<crate::AppState>>()
+                        .set(crate::AppState::Menu);
+                    return;
+                }
+            };
+            // Saved matches already carry settlement names and barks, so skip the LLM phases.
+            let loading = world.contains_resource::<crate::save::PendingLoad>();
+            let (mut world_map, mut pathfinding, mut next_state) = {
                 let mut system_state = SystemState::<(
                     ResMut<WorldMap>,
+                    ResMut<crate::pathfinding::PathFinding>,
                     ResMut<NextState<GenerationState>>,
                 )>::new(world);
                 system_state.get_mut(world)
             };
             world_map.0 = Some(generated_world);
+            if let Some((graph, nodes)) = graph {
+                *pathfinding = crate::pathfinding::PathFinding { graph, nodes };
+            }
             info!("World generated.");
-            next_state.set(GenerationState::Settlements);
+            if loading {
+                next_state.set(GenerationState::Spawn);
+            } else {
+                next_state.set(GenerationState::Settlements);
+            }
         })
         .await;
     });
@@ -0,0 +1,171 @@
+//! Hot-seat handover, hides the world between human turns until the next player takes over.
+use bevy::prelude::*;
+use bevy_egui::{EguiContext, EguiContexts, EguiPrimaryContextPass, egui};
+use bevy_rts_camera::RtsCameraControls;
+use game_core::PlayerId;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, GameState};
+
+pub struct HandoverPlugin;
+impl Plugin for HandoverPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Handover>();
+        app.add_systems(
+            EguiPrimaryContextPass,
+            handover_screen.run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+/// A hot-seat PIN, kept as a salted hash so it can't be read back out of a save.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Pin {
+    salt: [u8; 16],
+    hash: [u8; 32],
+}
+impl Pin {
+    pub fn new(pin: &str) -> Self {
+        // Salted from the OS rather than the match RNG, so seeded matches play out the same.
+        let salt = rand::rng().random::<[u8; 16]>();
+        Self {
+            salt,
+            hash: Self::hash(&salt, pin),
+        }
+    }
+    pub fn matches(&self, pin: &str) -> bool {
+        Self::hash(&self.salt, pin) == self.hash
+    }
+    fn hash(salt: &[u8; 16], pin: &str) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(salt);
+        hasher.update(pin.as_bytes());
+        *hasher.finalize().as_bytes()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Handover {
+    /// The human player the screen is waiting on to take over.
+    pub waiting_for: Option<PlayerId>,
+    /// The human player whose view was last on screen.
+    viewer: Option<PlayerId>,
+    pin: String,
+    wrong_pin: bool,
+    /// Notifications that arrived while the player was away, shown once they've taken over.
+    summary: Vec<String>,
+}
+impl Handover {
+    /// Called when a human player's turn starts, returns whether the screen is now waiting on
+    /// them because someone else was playing.
+    pub fn turn_started(&mut self, game_state: &GameState, player: PlayerId) -> bool {
+        let humans = game_state
+            .players
+            .values()
+            .filter(|p| p.is_local_human())
+            .count();
+        let handing_over = humans > 1 && self.viewer != Some(player);
+        self.viewer = Some(player);
+        if handing_over {
+            *self = Handover {
+                waiting_for: Some(player),
+                viewer: Some(player),
+                ..default()
+            };
+        }
+        handing_over
+    }
+}
+
+/// Run condition for whatever shouldn't show or react while the handover screen is up.
+pub fn not_handing_over(handover: Res<Handover>) -> bool {
+    handover.waiting_for.is_none()
+}
+
+/// Shows the view through `camera`, turning every other player camera off.
+pub fn show_view(
+    cameras: &mut Query<(&mut Camera, Entity, &mut RtsCameraControls), Without<EguiContext>>,
+    camera: Option<Entity>,
+) {
+    for (mut player_camera, entity, mut controls) in cameras.iter_mut() {
+        let active = camera == Some(entity);
+        player_camera.is_active = active;
+        controls.enabled = active;
+    }
+}
+
+/// Covers the screen until the next player confirms, checking their PIN if they set one, then
+/// sums up what happened since their last turn.
+fn handover_screen(
+    mut contexts: EguiContexts,
+    mut handover: ResMut<Handover>,
+    mut game_state: ResMut<GameState>,
+    mut cameras: Query<(&mut Camera, Entity, &mut RtsCameraControls), Without<EguiContext>>,
+) -> Result {
+    let ctx = contexts.ctx_mut()?;
+    let Some(waiting_for) = handover.waiting_for else {
+        if handover.summary.is_empty() {
+            return Ok(());
+        }
+        let mut close = false;
+        egui::Window::new("Since Your Last Turn")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                for message in handover.summary.iter() {
+                    ui.label(message);
+                }
+                ui.separator();
+                close = ui.button("Continue").clicked();
+            });
+        if close {
+            handover.summary.clear();
+        }
+        return Ok(());
+    };
+    let player = &game_state.players[&waiting_for];
+    let mut confirmed = false;
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.vertical_centered(|ui| {
+            ui.add_space(ui.available_height() / 3.0);
+            ui.heading(format!("Pass to Player {}", player.order + 1));
+            ui.label(&player.civ.name);
+            ui.add_space(12.0);
+            if player.pin.is_some() {
+                let pin = egui::TextEdit::singleline(&mut handover.pin)
+                    .password(true)
+                    .hint_text("PIN")
+                    .desired_width(120.0);
+                let response = ui.add(pin);
+                confirmed = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if handover.wrong_pin {
+                    ui.colored_label(ui.visuals().error_fg_color, "Wrong PIN");
+                }
+                ui.add_space(4.0);
+            }
+            confirmed |= ui.button("Ready").clicked();
+        });
+    });
+    if !confirmed {
+        return Ok(());
+    }
+    if player
+        .pin
+        .as_ref()
+        .is_some_and(|pin| !pin.matches(&handover.pin))
+    {
+        handover.wrong_pin = true;
+        handover.pin.clear();
+        return Ok(());
+    }
+    let player = game_state.players.get_mut(&waiting_for).unwrap();
+    *handover = Handover {
+        viewer: Some(waiting_for),
+        summary: player.notifications.drain(..).map(|n| n.message).collect(),
+        ..default()
+    };
+    show_view(&mut cameras, player.camera_entity);
+    Ok(())
+}
@@ -19,7 +19,6 @@ use bevy::{
         Exposure,
         visibility::{NoFrustumCulling, RenderLayers},
     },
-    ecs::system::SystemState,
     input_focus::InputFocus,
     light::AtmosphereEnvironmentMapLight,
     log::LogPlugin,
@@ -31,10 +30,7 @@ use bevy::{
     window::PrimaryWindow,
 };
 use bevy_easings::{Ease, EasingsPlugin};
-use bevy_egui::{
-    EguiContext, EguiContexts, EguiTextureHandle,
-    egui::{self, Response, Ui},
-};
+use bevy_egui::{EguiContext, EguiContexts, EguiTextureHandle, egui};
 use bevy_kira_audio::prelude::*;
 use bevy_persistent::{Persistent, StorageFormat};
 use bevy_prototype_lyon::{
@@ -46,22 +42,31 @@ use bevy_steamworks::SteamworksPlugin;
 use bevy_tokio_tasks::TokioTasksRuntime;
 use clap::Parser;
 use colorgrad::Gradient;
+use game_core::{PlayerId, UnitType};
 use geo::{CoordsIter, unary_union};
 use num::Num;
 use rand::{Rng, SeedableRng, distr::Uniform};
 use rand_chacha::ChaCha20Rng;
 use serde::Deserialize;
 use thiserror::Error;
+mod fog;
 mod generate;
+mod handover;
 mod llm;
 mod minimap;
+mod net;
 mod pathfinding;
+mod replay;
+mod save;
 #[derive(Parser, Debug)]
 struct Args {
     #[arg(long)]
     seed: Option<String>,
     #[arg(long)]
     llm_mode: Option<Option<String>>,
+    /// Re-simulates the match in a replay file.
+    #[arg(long)]
+    replay: Option<std::path::PathBuf>,
 }
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub(crate) enum AppState {
@@ -112,9 +117,20 @@ fn main() -> anyhow::Result<()> {
         .add_plugins(crate::ui::UIPlugin)
         .add_plugins(crate::generate::WorldPlugin)
         .add_plugins(crate::menu::MenuPlugin)
-        .add_message::<TurnStart>()
+        .add_plugins(crate::save::SavePlugin)
+        .add_plugins(crate::handover::HandoverPlugin)
+        .add_plugins(crate::net::NetPlugin)
+        .add_plugins(crate::replay::ReplayPlugin)
+        .add_message::<GameCommand>()
+        .add_message::<GameEvent>()
         .init_asset::<Civilisation>()
         .init_asset_loader::<CivilisationAssetLoader>()
+        .init_asset::<TechTreeAsset>()
+        .init_asset_loader::<TechTreeAssetLoader>()
+        .init_asset::<ImprovementAsset>()
+        .init_asset_loader::<ImprovementAssetLoader>()
+        .init_asset::<TerrainProfileAsset>()
+        .init_asset_loader::<TerrainProfileAssetLoader>()
         .init_asset::<LLMProvider>()
         .init_asset_loader::<LLMProviderAssetLoader>()
         .init_state::<AppState>()
@@ -123,6 +139,7 @@ fn main() -> anyhow::Result<()> {
         .init_resource::<LoadedFolders>()
         .insert_resource(Seed(args.seed.clone()))
         .insert_resource(LlmModeOverride(args.llm_mode))
+        .insert_resource(replay::ReplayPath(args.replay))
         //.insert_resource(GameState::new(2))
         .insert_resource::<Random<RandomRng>>(Random(None))
         .insert_resource(Selection::None)
@@ -144,30 +161,43 @@ fn main() -> anyhow::Result<()> {
         )
         .add_systems(Update, loaded.run_if(in_state(AppState::Loading)))
         .add_systems(OnExit(AppState::Loading), remove_startup_screen)
+        .add_systems(OnExit(AppState::InGame), leave_match)
         .add_systems(
             OnEnter(AppState::InGame),
-            (startup, minimap::spawn_minimap_camera),
+            (
+                startup.run_if(not(resource_exists::<save::PendingLoad>)),
+                minimap::spawn_minimap_camera,
+            ),
         )
         .add_systems(
             Update,
             (
+                (
+                    ai_turn,
+                    net::receive_commands,
+                    replay::play_replay,
+                    run_commands,
+                    (
+                        turn_start,
+                        spawn_units,
+                        update_units,
+                        settlements_founded,
+                        settlements_change_hands,
+                        settlement_grows,
+                        improvements_built,
+                        tech_researched,
+                    ),
+                    fog::update_fog,
+                )
+                    .chain(),
                 move_unit,
-                turn_start_async,
-                deselect,
-                reset_turn_ready_to_end,
+                (deselect, remember_selection, highlight_selection).chain(),
                 move_sun,
                 debug_notification,
+                undo_shortcut.run_if(handover::not_handing_over),
             )
                 .run_if(in_state(AppState::InGame)),
         )
-        .add_systems(
-            FixedUpdate,
-            set_unit_next_cell.run_if(in_state(AppState::InGame)),
-        )
-        .add_systems(
-            FixedPostUpdate,
-            check_if_turn_ready_to_end.run_if(in_state(AppState::InGame)),
-        )
         .run();
     Ok(())
 }
@@ -175,10 +205,16 @@ fn main() -> anyhow::Result<()> {
 struct LoadedFolders {
     civs: Option<Handle<LoadedFolder>>,
     llm_providers: Option<Handle<LoadedFolder>>,
+    tech_tree: Option<Handle<TechTreeAsset>>,
+    improvements: Option<Handle<LoadedFolder>>,
+    world_profiles: Option<Handle<LoadedFolder>>,
 }
 fn load_civs(asset_server: Res<AssetServer>, mut folders: ResMut<LoadedFolders>) {
     folders.civs = Some(asset_server.load_folder("civilisations"));
-    folders.llm_providers = Some(asset_server.load_folder("llm-providers"))
+    folders.llm_providers = Some(asset_server.load_folder("llm-providers"));
+    folders.tech_tree = Some(asset_server.load("tech/tech-tree.tech.ron"));
+    folders.improvements = Some(asset_server.load_folder("improvements"));
+    folders.world_profiles = Some(asset_server.load_folder("world-profiles"));
 }
 fn load_settings(
     mut commands: Commands,
@@ -228,6 +264,15 @@ fn load_settings(
             .expect("Failed to override llm mode from command line");
     }
     commands.insert_resource(llm_settings);
+    commands.insert_resource(
+        Persistent::<WorldPresets>::builder()
+            .name("world presets")
+            .format(StorageFormat::Toml)
+            .path(config_dir.join("world_presets.toml"))
+            .default(WorldPresets::default())
+            .build()
+            .expect("Failed to init world presets"),
+    );
 }
 fn loaded(
     mut next_state: ResMut<NextState<AppState>>,
@@ -261,6 +306,11 @@ struct LLMSettings {
 struct VideoSettings {
     window_mode: ::menu::FullscreenMode,
 }
+/// Custom world generation params saved from the new game menu.
+#[derive(Resource, serde::Serialize, serde::Deserialize, Clone, Default)]
+struct WorldPresets {
+    presets: Vec<::menu::WorldPreset>,
+}
 impl Default for AudioSettings {
     fn default() -> Self {
         AudioSettings { music_volume: 1.0 }
@@ -275,23 +325,26 @@ impl Default for VideoSettings {
 }
 fn setup_rng(mut random: ResMut<Random<ChaCha20Rng>>, seed: Res<Seed>) {
     let rng = match seed.0.as_ref() {
-        Some(s) => {
-            let num = num::BigUint::from_str_radix(s, 36).unwrap();
-            let seed_bytes = num.to_bytes_le();
-            let mut seed_arr = [0u8; 32];
-            for (i, b) in seed_bytes.iter().enumerate().take(32) {
-                seed_arr[i] = *b;
-            }
-            ChaCha20Rng::from_seed(seed_arr)
-        }
+        Some(s) => rng_from_seed(s).expect("Seeds are base 36"),
         None => ChaCha20Rng::from_os_rng(),
     };
-    let seed = rng.get_seed();
-    let num = num::BigUint::from_bytes_le(&seed);
-    let seed = num.to_str_radix(36);
-    info!("Seed: {}", seed);
+    info!("Seed: {}", seed_of(&rng));
     random.0 = Some(rng);
 }
+/// Parses a base 36 seed as given with `--seed` or in the new game menu.
+fn rng_from_seed(seed: &str) -> Option<ChaCha20Rng> {
+    let num = num::BigUint::from_str_radix(seed, 36).ok()?;
+    let seed_bytes = num.to_bytes_le();
+    let mut seed_arr = [0u8; 32];
+    for (i, b) in seed_bytes.iter().enumerate().take(32) {
+        seed_arr[i] = *b;
+    }
+    Some(ChaCha20Rng::from_seed(seed_arr))
+}
+/// The base 36 seed `rng` was created from.
+fn seed_of(rng: &ChaCha20Rng) -> String {
+    num::BigUint::from_bytes_le(&rng.get_seed()).to_str_radix(36)
+}
 fn startup_screens(mut commands: Commands) {
     commands.spawn((
         StartupScreen,
@@ -384,6 +437,26 @@ fn deselect(
         }
     }
 }
+/// Ctrl+Z takes back the active player's last move or construction order.
+fn undo_shortcut(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    game_state: Res<GameState>,
+    mut game_commands: MessageWriter<GameCommand>,
+) {
+    let ctrl = keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if ctrl
+        && keyboard.just_pressed(KeyCode::KeyZ)
+        && game_state.active_player_is_human()
+        && game_state.game.can_undo()
+    {
+        game_commands.write(GameCommand(game_core::Command::Undo));
+    }
+}
+/// Clears what the despawned match entities leave behind, ready for the next one.
+fn leave_match(mut selected: ResMut<Selection>, mut handover: ResMut<handover::Handover>) {
+    *selected = Selection::None;
+    *handover = handover::Handover::default();
+}
 fn archive_old_logs(runtime: ResMut<TokioTasksRuntime>) {
     runtime.spawn_background_task(move |_| async move {
         logs::archive_old_logs(Path::new("logs")).unwrap();
@@ -468,15 +541,11 @@ fn startup(
     mut meshes: ResMut<Assets<Mesh>>,
     mut random: ResMut<Random<ChaCha20Rng>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: ResMut<AssetServer>,
-    mut contexts: EguiContexts,
     mut scattering_mediums: ResMut<Assets<ScatteringMedium>>,
 ) {
-    let scale = world_map.scale;
-
-    let map_box = world_map.bounds();
-
-    for player in game_state.players.values_mut() {
+    let viewing_player = game_state.viewing_player();
+    let GameState { players, game } = game_state.as_mut();
+    for player in players.values_mut() {
         let mut valid_settlment_cells = world_map.get_valid_settlement_cells();
         if valid_settlment_cells.is_empty() {
             valid_settlment_cells = world_map.iter_cells().map(|c| CellId(c.site())).collect();
@@ -491,234 +560,245 @@ fn startup(
         //     .0
         //     .sample(Uniform::<Vec2>::new(map_box.0, map_box.1).unwrap());
         // let cell_id = world_map.get_cell_for_position(pos);
+        player.material = materials.add(player.color);
         if let Some(cell_id) = cell_id {
             //let cell_vertices = world_map.get_vertices_for_cell(cell_id);
             let pos = world_map.get_position_for_cell(cell_id);
-            let player_mat = materials.add(player.color);
-            let settlement_mesh = meshes.add(Cuboid::from_length(world_map.entity_scale));
-            let name = player
-                .settlement_names
-                .pop()
-                .unwrap_or(format!("Settlement: {}", player.id.0));
-            let settlment = SettlementCenter {
-                name,
-                controller: player.id,
-                production: 1.0,
-                construction: None,
-                cell: cell_id,
-                turns_till_growth: 1,
-                controlled_cells: world_map.get_neighbours(cell_id),
-                available_constructions: player
-                    .civ
-                    .units
-                    .iter()
-                    .map(|u| {
-                        ConstructionJob::Unit(u.to_construction(
-                            player.id,
-                            cell_id,
-                            asset_server.as_ref(),
-                            &mut contexts,
-                            MeshMaterial3d(player_mat.clone()),
-                        ))
-                    })
-                    .chain(vec![ConstructionJob::Sink(SinkConstuction {
-                        cost: 5.0,
-                        progress: 0.0,
-                    })])
-                    .collect::<Vec<_>>(),
-            };
-            let camera_entity = commands
-                .spawn((
-                    Camera3d { ..default() },
-                    // PanOrbitCamera {
-                    //     enabled: player.order == 0,
-                    //     ..default()
-                    // },
-                    Atmosphere::earthlike(scattering_mediums.add(ScatteringMedium::default())),
-                    // Can be adjusted to change the scene scale and rendering quality
-                    AtmosphereSettings::default(),
-                    // AtmosphereSettings {
-                    //     aerial_view_lut_max_distance: 3.2e5,
-                    //     scene_units_to_m: 1e+4,
-                    //     ..Default::default()
-                    // },
-                    Bloom::NATURAL,
-                    Exposure::SUNLIGHT,
-                    AtmosphereEnvironmentMapLight::default(),
-                    RtsCamera {
-                        height_max: scale * 10.0,
-                        target_zoom: 0.8,
-                        target_focus: Transform::from_translation(pos),
-                        bounds: Aabb2d {
-                            max: map_box.1,
-                            min: map_box.0,
-                        },
-                        min_angle: 0.0f32.to_radians(),
-                        ..default()
-                    },
-                    RtsCameraControls {
-                        key_up: KeyCode::KeyW,
-                        key_right: KeyCode::KeyD,
-                        key_down: KeyCode::KeyS,
-                        key_left: KeyCode::KeyA,
-                        zoom_sensitivity: 0.25,
-                        edge_pan_restrict_to_viewport: true,
-                        enabled: player.order == 0,
-                        pan_speed: 30.0,
-                        ..default()
-                    },
-                    Camera {
-                        is_active: player.order == 0,
-
-                        ..default()
-                    },
-                    RenderLayers::from_layers(&[render_layers::WORLD]), // PanCam {
-                                                                        //     enabled: player.order == 0,
-                                                                        //     //max_scale: 1.0,
-                                                                        //     ..default()
-                                                                        // },
-                ))
-                .id();
-            player.camera_entity = Some(camera_entity);
-            let mut controlled_polys = Vec::new();
-            for neighbour in settlment.controlled_cells.iter() {
-                controlled_polys.push(world_map.get_cell_shape(*neighbour));
-            }
-            let controlled_vertices = get_hull(controlled_polys, pos.xz(), scale);
-            let polygon = bevy_prototype_lyon::prelude::shapes::Polygon {
-                points: controlled_vertices.clone(),
-                closed: true,
-            };
-
-            let mut settlement = commands.spawn((
-                Mesh3d(settlement_mesh),
-                MeshMaterial3d(player_mat.clone()),
-                Transform::from_translation(pos),
-                settlment,
-                RenderLayers::from_layers(&[render_layers::WORLD]),
-            ));
-            settlement.observe(settlement_grows);
-            settlement.observe(click_settlement);
-            let settlement_entity = settlement.id();
-            commands.spawn((
-                minimap::MinimapControlledArea(settlement_entity),
-                ShapeBuilder::with(&polygon).fill(player.color).build(),
-                Transform::from_translation(pos.xzy().with_z(4.0)),
-                RenderLayers::from_layers(&[render_layers::MINIMAP]),
-            ));
-            let mut ribbons_vertices = controlled_vertices
-                .iter()
-                .map(|v| {
-                    v.extend(
-                        world_map
-                            .get_height_at_vertex((*v + pos.xz()) / world_map.scale)
-                            .max(0.5)
-                            * world_map.height_scale,
-                    )
-                    .xzy()
-                })
-                .collect::<Vec<_>>();
-            ribbons_vertices.push(*ribbons_vertices.first().unwrap());
-            let ribbon_mesh = polyline_ribbon_mesh_3d(&ribbons_vertices, 0.1, Vec3::Y);
-            commands.spawn((
-                ControlledArea(settlement_entity),
-                Mesh3d(meshes.add(ribbon_mesh)),
-                MeshMaterial3d(player_mat.clone()),
-                NoFrustumCulling,
-                RenderLayers::from_layers(&[render_layers::WORLD]),
-                Transform::from_translation(pos.with_y(0.01)),
+            let name = player.next_settlement_name();
+            let settlement =
+                game.found_settlement(player.id, name, cell_id, world_map.get_neighbours(cell_id));
+            player.camera_entity = Some(spawn_player_camera(
+                &mut commands,
+                &mut scattering_mediums,
+                &world_map,
+                player,
+                pos,
+                DEFAULT_ZOOM,
+                player.id == viewing_player,
             ));
+            spawn_settlement(
+                &mut commands,
+                &mut meshes,
+                &world_map,
+                game.settlement(settlement).unwrap(),
+                player,
+            );
         }
     }
 
     // Egui camera.
 }
-fn get_hull(polys: Vec<geo::Polygon>, offset: Vec2, scale: f32) -> Vec<Vec2> {
-    let multi_polygon = unary_union(polys.iter());
-    multi_polygon
-        .exterior_coords_iter()
-        .map(|c| (vec2(c.x as f32, c.y as f32) * scale) - offset)
-        .collect::<Vec<_>>()
-}
-#[derive(EntityEvent)]
-struct SettlementGrows {
-    #[event_target]
-    target_entity: Entity,
-}
-#[derive(Component)]
-pub struct ControlledArea(pub Entity);
-fn settlement_grows(
-    event: On<SettlementGrows>,
-    mut settlements: Query<&mut SettlementCenter>,
-    minimap_controlled_areas: Query<(Entity, &minimap::MinimapControlledArea)>,
-    controlled_areas: Query<(Entity, &ControlledArea)>,
-    world_map: Res<WorldMap>,
-    mut commands: Commands,
-    pathfinding: Res<crate::pathfinding::PathFinding>,
-    game_state: Res<GameState>,
-    mut meshes: ResMut<Assets<Mesh>>,
-) {
+/// How far a new player camera is zoomed in.
+const DEFAULT_ZOOM: f32 = 0.8;
+fn spawn_player_camera(
+    commands: &mut Commands,
+    scattering_mediums: &mut Assets<ScatteringMedium>,
+    world_map: &WorldMap,
+    player: &Player,
+    focus: Vec3,
+    zoom: f32,
+    active: bool,
+) -> Entity {
     let scale = world_map.scale;
-    let crate::pathfinding::PathFinding { graph, nodes } = pathfinding.as_ref();
-    let entity = event.target_entity;
-    let mut settlement = settlements.get_mut(entity).unwrap();
-
-    if settlement.controller != game_state.active_player {
-        return;
-    }
-    let player = game_state.players.get(&settlement.controller).unwrap();
+    let map_box = world_map.bounds();
+    commands
+        .spawn((
+            Camera3d { ..default() },
+            DespawnOnExit(AppState::InGame),
+            // PanOrbitCamera {
+            //     enabled: player.order == 0,
+            //     ..default()
+            // },
+            Atmosphere::earthlike(scattering_mediums.add(ScatteringMedium::default())),
+            // Can be adjusted to change the scene scale and rendering quality
+            AtmosphereSettings::default(),
+            // AtmosphereSettings {
+            //     aerial_view_lut_max_distance: 3.2e5,
+            //     scene_units_to_m: 1e+4,
+            //     ..Default::default()
+            // },
+            Bloom::NATURAL,
+            Exposure::SUNLIGHT,
+            AtmosphereEnvironmentMapLight::default(),
+            RtsCamera {
+                height_max: scale * 10.0,
+                target_zoom: zoom,
+                target_focus: Transform::from_translation(focus),
+                bounds: Aabb2d {
+                    max: map_box.1,
+                    min: map_box.0,
+                },
+                min_angle: 0.0f32.to_radians(),
+                ..default()
+            },
+            RtsCameraControls {
+                key_up: KeyCode::KeyW,
+                key_right: KeyCode::KeyD,
+                key_down: KeyCode::KeyS,
+                key_left: KeyCode::KeyA,
+                zoom_sensitivity: 0.25,
+                edge_pan_restrict_to_viewport: true,
+                enabled: active,
+                pan_speed: 30.0,
+                ..default()
+            },
+            Camera {
+                is_active: active,
+                ..default()
+            },
+            RenderLayers::from_layers(&[render_layers::WORLD]),
+        ))
+        .id()
+}
+fn spawn_settlement(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    world_map: &WorldMap,
+    settlement: &game_core::Settlement,
+    player: &Player,
+) -> Entity {
     let pos = world_map.get_position_for_cell(settlement.cell);
-    let controlled_cells = &settlement.controlled_cells;
-    let mut un_controlled_cells = vec![];
-    for cell in controlled_cells.iter() {
-        let neighbours = world_map.get_neighbours(*cell);
-        let un_controlled_neighbours = neighbours
-            .into_iter()
-            .filter(|n| !controlled_cells.contains(n) && *n != settlement.cell)
-            .collect::<Vec<_>>();
-        if un_controlled_neighbours.is_empty() {
-            continue;
-        }
-        un_controlled_cells.extend(un_controlled_neighbours);
-    }
-    let mut closest_distance = u8::MAX;
-    let mut closest_cell = None;
-    for cell in un_controlled_cells.iter() {
-        let path = crate::pathfinding::a_star(settlement.cell, *cell, graph, nodes, &world_map);
-        if path.is_none() {
-            continue;
-        }
-        let path = path.unwrap();
-        let distance = path.len() as u8;
-        if distance < closest_distance {
-            closest_distance = distance;
-            closest_cell = Some(*cell);
-        }
-    }
-    if closest_cell.is_none() {
-        return;
-    }
-    let closest_cell = closest_cell.unwrap();
-    settlement.controlled_cells.push(closest_cell);
-    let minimap_controlled_area_entity = minimap_controlled_areas
-        .iter()
-        .find(|(_, area)| area.0 == entity)
-        .map(|(e, _)| e)
-        .unwrap();
-    let controlled_area_entity = controlled_areas
-        .iter()
-        .find(|(_, area)| area.0 == entity)
-        .map(|(e, _)| e)
-        .unwrap();
+    let (polygon, ribbon_mesh) =
+        controlled_area_shapes(world_map, settlement.cell, &settlement.controlled_cells);
+    let mut settlement = commands.spawn((
+        Mesh3d(meshes.add(Cuboid::from_length(world_map.entity_scale))),
+        MeshMaterial3d(player.material.clone()),
+        Transform::from_translation(pos),
+        SettlementCenter {
+            id: settlement.id,
+            controller: settlement.controller,
+        },
+        RenderLayers::from_layers(&[render_layers::WORLD]),
+        DespawnOnExit(AppState::InGame),
+    ));
+    settlement.observe(click_settlement);
+    let settlement_entity = settlement.id();
+    commands.spawn((
+        minimap::MinimapControlledArea(settlement_entity),
+        ShapeBuilder::with(&polygon).fill(player.color).build(),
+        Transform::from_translation(pos.xzy().with_z(4.0)),
+        RenderLayers::from_layers(&[render_layers::MINIMAP]),
+        DespawnOnExit(AppState::InGame),
+    ));
+    commands.spawn((
+        ControlledArea(settlement_entity),
+        Mesh3d(meshes.add(ribbon_mesh)),
+        MeshMaterial3d(player.material.clone()),
+        NoFrustumCulling,
+        RenderLayers::from_layers(&[render_layers::WORLD]),
+        Transform::from_translation(pos.with_y(0.01)),
+        DespawnOnExit(AppState::InGame),
+    ));
+    settlement_entity
+}
+/// Marks the improvement a settlement built on one of its cells.
+#[derive(Component)]
+struct ImprovementMarker {
+    settlement: game_core::SettlementId,
+    cell: CellId,
+}
+fn spawn_improvement(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    asset_server: &AssetServer,
+    world_map: &WorldMap,
+    marker: ImprovementMarker,
+    improvement: Option<&game_core::Improvement>,
+    player: &Player,
+) -> Entity {
+    let mesh = match improvement.and_then(|i| i.mesh_path.clone()) {
+        Some(path) => asset_server.load(
+            GltfAssetLabel::Primitive {
+                mesh: 0,
+                primitive: 0,
+            }
+            .from_asset(path),
+        ),
+        None => meshes.add(Cylinder::new(
+            world_map.entity_scale * 0.4,
+            world_map.entity_scale * 0.5,
+        )),
+    };
+    let position = world_map.get_position_for_cell(marker.cell);
+    commands
+        .spawn((
+            marker,
+            Mesh3d(mesh),
+            MeshMaterial3d(player.material.clone()),
+            Transform::from_translation(position),
+            RenderLayers::from_layers(&[render_layers::WORLD]),
+            DespawnOnExit(AppState::InGame),
+        ))
+        .id()
+}
+fn spawn_unit(
+    commands: &mut Commands,
+    world_map: &WorldMap,
+    unit: &game_core::Unit,
+    player: &Player,
+    asset_server: &AssetServer,
+    contexts: &mut EguiContexts,
+) -> Entity {
+    let unit_type = player.unit_type(&unit.unit_type);
+    let mut entity = commands.spawn((
+        Unit {
+            id: unit.id,
+            current_cell: unit.cell,
+            moves: VecDeque::new(),
+            move_timer: None,
+            icon: unit_icon(player, &unit.unit_type, asset_server, contexts),
+        },
+        Mesh3d(
+            asset_server.load(
+                GltfAssetLabel::Primitive {
+                    mesh: 0,
+                    primitive: 0,
+                }
+                .from_asset(unit_type.mesh_path.clone()),
+            ),
+        ),
+        MeshMaterial3d(player.material.clone()),
+        //MeshMaterial2d(materials.add(player.color)),
+        Transform::from_translation(world_map.get_position_for_cell(unit.cell)),
+        RenderLayers::from_layers(&[render_layers::WORLD]),
+        DespawnOnExit(AppState::InGame),
+    ));
+    entity.observe(click_unit);
+    entity.id()
+}
+fn unit_icon(
+    player: &Player,
+    unit_type: &str,
+    asset_server: &AssetServer,
+    contexts: &mut EguiContexts,
+) -> egui::TextureId {
+    contexts.add_image(EguiTextureHandle::Strong(
+        asset_server.load(player.unit_type(unit_type).icon_path.clone()),
+    ))
+}
+/// Builds the minimap polygon and the in-world border ribbon for the cells a settlement controls.
+fn controlled_area_shapes(
+    world_map: &WorldMap,
+    center: CellId,
+    controlled_cells: &[CellId],
+) -> (bevy_prototype_lyon::prelude::shapes::Polygon, Mesh) {
+    let pos = world_map.get_position_for_cell(center);
     let mut controlled_polys = Vec::new();
-    for neighbour in settlement.controlled_cells.iter() {
+    for neighbour in controlled_cells.iter() {
         controlled_polys.push(world_map.get_cell_shape(*neighbour));
     }
-    let controlled_vertices = get_hull(controlled_polys, pos.xz(), scale);
+    let controlled_vertices = get_hull(controlled_polys, pos.xz(), world_map.scale);
     let polygon = bevy_prototype_lyon::prelude::shapes::Polygon {
         points: controlled_vertices.clone(),
         closed: true,
     };
+    // Nothing to outline for a settlement hemmed in with no borders of its own.
+    if controlled_vertices.len() < 2 {
+        return (
+            polygon,
+            Mesh::new(PrimitiveTopology::TriangleList, default()),
+        );
+    }
     let mut ribbons_vertices = controlled_vertices
         .iter()
         .map(|v| {
@@ -731,14 +811,256 @@ fn settlement_grows(
             .xzy()
         })
         .collect::<Vec<_>>();
-    ribbons_vertices.push(*ribbons_vertices.first().unwrap());
-    let outline_mesh = polyline_ribbon_mesh_3d(&ribbons_vertices, 0.1, Vec3::Y);
-    commands
-        .entity(controlled_area_entity)
-        .insert(Mesh3d(meshes.add(outline_mesh)));
-    commands
-        .entity(minimap_controlled_area_entity)
-        .insert(ShapeBuilder::with(&polygon).fill(player.color).build());
+    ribbons_vertices.push(ribbons_vertices[0]);
+    let ribbon_mesh = polyline_ribbon_mesh_3d(&ribbons_vertices, 0.1, Vec3::Y);
+    (polygon, ribbon_mesh)
+}
+fn get_hull(polys: Vec<geo::Polygon>, offset: Vec2, scale: f32) -> Vec<Vec2> {
+    let multi_polygon = unary_union(polys.iter());
+    multi_polygon
+        .exterior_coords_iter()
+        .map(|c| (vec2(c.x as f32, c.y as f32) * scale) - offset)
+        .collect::<Vec<_>>()
+}
+#[derive(Component)]
+pub struct ControlledArea(pub Entity);
+fn tech_researched(mut events: MessageReader<GameEvent>, mut game_state: ResMut<GameState>) {
+    for event in events.read() {
+        let game_core::Event::TechResearched { player, tech } = &**event else {
+            continue;
+        };
+        let name = game_state
+            .game
+            .tech_tree()
+            .get(tech)
+            .map_or(tech.clone(), |t| t.name.clone());
+        if let Some(player) = game_state.players.get_mut(player) {
+            player.add_notification(format!("{name} researched"));
+        }
+    }
+}
+fn improvements_built(
+    mut events: MessageReader<GameEvent>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    asset_server: Res<AssetServer>,
+    world_map: Res<WorldMap>,
+    game_state: Res<GameState>,
+) {
+    for event in events.read() {
+        let game_core::Event::ImprovementBuilt {
+            settlement,
+            cell,
+            improvement,
+        } = &**event
+        else {
+            continue;
+        };
+        let Some(settlement) = game_state.game.settlement(*settlement) else {
+            continue;
+        };
+        let Some(player) = game_state.players.get(&settlement.controller) else {
+            continue;
+        };
+        spawn_improvement(
+            &mut commands,
+            &mut meshes,
+            &asset_server,
+            &world_map,
+            ImprovementMarker {
+                settlement: settlement.id,
+                cell: *cell,
+            },
+            game_state.game.improvement(improvement),
+            player,
+        );
+    }
+}
+fn settlements_founded(
+    mut events: MessageReader<GameEvent>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    world_map: Res<WorldMap>,
+    mut game_state: ResMut<GameState>,
+) {
+    let GameState { players, game } = game_state.as_mut();
+    for event in events.read() {
+        let game_core::Event::SettlementFounded { settlement, .. } = **event else {
+            continue;
+        };
+        let Some(settlement) = game.settlement(settlement) else {
+            continue;
+        };
+        let Some(player) = players.get_mut(&settlement.controller) else {
+            continue;
+        };
+        spawn_settlement(&mut commands, &mut meshes, &world_map, settlement, player);
+        player.add_notification(format!("{} founded", settlement.name));
+    }
+}
+/// Recolours captured settlements, removes razed ones and lets both sides know.
+fn settlements_change_hands(
+    mut events: MessageReader<GameEvent>,
+    mut commands: Commands,
+    mut settlements: Query<(Entity, &mut SettlementCenter)>,
+    minimap_controlled_areas: Query<(Entity, &minimap::MinimapControlledArea)>,
+    controlled_areas: Query<(Entity, &ControlledArea)>,
+    improvements: Query<(Entity, &ImprovementMarker)>,
+    mut selection: ResMut<Selection>,
+    world_map: Res<WorldMap>,
+    mut game_state: ResMut<GameState>,
+) {
+    let GameState { players, game } = game_state.as_mut();
+    for event in events.read() {
+        let (settlement, from, to) = match &**event {
+            game_core::Event::SettlementDamaged {
+                settlement, health, ..
+            } => {
+                if *health <= 0.0
+                    && let Some(settlement) = game.settlement(*settlement)
+                    && let Some(player) = players.get_mut(&settlement.controller)
+                {
+                    player.add_notification(format!("The walls of {} are down", settlement.name));
+                }
+                continue;
+            }
+            game_core::Event::SettlementCaptured {
+                settlement,
+                from,
+                to,
+            } => (*settlement, *from, *to),
+            game_core::Event::SettlementRazed {
+                settlement,
+                name,
+                from,
+                by,
+            } => {
+                if let (Some(victim), Some(razer)) = (players.get(from), players.get(by)) {
+                    let (victim, razer) = (victim.civ.name.clone(), razer.civ.name.clone());
+                    if let Some(player) = players.get_mut(from) {
+                        player.add_notification(format!("{name} was razed by the {razer}"));
+                    }
+                    if let Some(player) = players.get_mut(by) {
+                        player.add_notification(format!("{name} of the {victim} razed"));
+                    }
+                }
+                let Some((entity, _)) = settlements.iter().find(|(_, s)| s.id == *settlement)
+                else {
+                    continue;
+                };
+                if matches!(*selection, Selection::Settlement(selected) if selected == entity) {
+                    *selection = Selection::None;
+                }
+                let areas = minimap_controlled_areas
+                    .iter()
+                    .filter(|(_, area)| area.0 == entity)
+                    .map(|(e, _)| e)
+                    .chain(
+                        controlled_areas
+                            .iter()
+                            .filter(|(_, area)| area.0 == entity)
+                            .map(|(e, _)| e),
+                    );
+                let markers = improvements
+                    .iter()
+                    .filter(|(_, m)| m.settlement == *settlement)
+                    .map(|(e, _)| e);
+                for entity in [entity].into_iter().chain(areas).chain(markers) {
+                    commands.entity(entity).despawn();
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        let Some((entity, mut center)) = settlements.iter_mut().find(|(_, s)| s.id == settlement)
+        else {
+            continue;
+        };
+        // Razed in the same batch, the razing event cleans up after it.
+        let Some(settlement) = game.settlement(settlement) else {
+            continue;
+        };
+        let (Some(player), Some(previous)) = (players.get(&to), players.get(&from)) else {
+            continue;
+        };
+        let (winner, loser) = (player.civ.name.clone(), previous.civ.name.clone());
+        center.controller = to;
+        if matches!(*selection, Selection::Settlement(selected) if selected == entity) {
+            *selection = Selection::None;
+        }
+        let areas = controlled_areas
+            .iter()
+            .filter(|(_, area)| area.0 == entity)
+            .map(|(e, _)| e);
+        let markers = improvements
+            .iter()
+            .filter(|(_, m)| m.settlement == settlement.id)
+            .map(|(e, _)| e);
+        for entity in [entity].into_iter().chain(areas).chain(markers) {
+            commands
+                .entity(entity)
+                .insert(MeshMaterial3d(player.material.clone()));
+        }
+        let (polygon, _) =
+            controlled_area_shapes(&world_map, settlement.cell, &settlement.controlled_cells);
+        for (entity, _) in minimap_controlled_areas
+            .iter()
+            .filter(|(_, area)| area.0 == entity)
+        {
+            commands
+                .entity(entity)
+                .insert(ShapeBuilder::with(&polygon).fill(player.color).build());
+        }
+        if let Some(player) = players.get_mut(&from) {
+            player.add_notification(format!("{} was captured by the {winner}", settlement.name));
+        }
+        if let Some(player) = players.get_mut(&to) {
+            player.add_notification(format!("{} captured from the {loser}", settlement.name));
+        }
+    }
+}
+fn settlement_grows(
+    mut events: MessageReader<GameEvent>,
+    settlements: Query<(Entity, &SettlementCenter)>,
+    minimap_controlled_areas: Query<(Entity, &minimap::MinimapControlledArea)>,
+    controlled_areas: Query<(Entity, &ControlledArea)>,
+    world_map: Res<WorldMap>,
+    mut commands: Commands,
+    game_state: Res<GameState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for event in events.read() {
+        let game_core::Event::SettlementGrew { settlement, .. } = **event else {
+            continue;
+        };
+        let Some((entity, _)) = settlements.iter().find(|(_, s)| s.id == settlement) else {
+            continue;
+        };
+        let Some(settlement) = game_state.game.settlement(settlement) else {
+            continue;
+        };
+        let Some(player) = game_state.players.get(&settlement.controller) else {
+            continue;
+        };
+        let minimap_controlled_area_entity = minimap_controlled_areas
+            .iter()
+            .find(|(_, area)| area.0 == entity)
+            .map(|(e, _)| e)
+            .unwrap();
+        let controlled_area_entity = controlled_areas
+            .iter()
+            .find(|(_, area)| area.0 == entity)
+            .map(|(e, _)| e)
+            .unwrap();
+        let (polygon, outline_mesh) =
+            controlled_area_shapes(&world_map, settlement.cell, &settlement.controlled_cells);
+        commands
+            .entity(controlled_area_entity)
+            .insert(Mesh3d(meshes.add(outline_mesh)));
+        commands
+            .entity(minimap_controlled_area_entity)
+            .insert(ShapeBuilder::with(&polygon).fill(player.color).build());
+    }
 }
 
 pub fn polyline_ribbon_mesh_3d(points: &[Vec3], half_width: f32, up: Vec3) -> Mesh {
@@ -839,39 +1161,77 @@ pub fn polyline_ribbon_mesh_3d(points: &[Vec3], half_width: f32, up: Vec3) -> Me
     mesh.insert_indices(Indices::U32(indices));
     mesh
 }
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct PlayerId(usize);
+/// Asks the match to carry out a command on behalf of the active player.
 #[derive(Message)]
-struct TurnStart {
-    player: PlayerId,
-}
-fn check_if_turn_ready_to_end(
-    units: Query<&Unit>,
-    settlements: Query<&SettlementCenter>,
+struct GameCommand(game_core::Command);
+/// Something that changed in the match, for the presentation systems to catch up on.
+#[derive(Message, Deref)]
+struct GameEvent(game_core::Event);
+fn run_commands(
+    mut requests: MessageReader<GameCommand>,
+    mut remote_requests: MessageReader<net::RemoteCommand>,
+    mut events: MessageWriter<GameEvent>,
     mut game_state: ResMut<GameState>,
+    mut session: Option<ResMut<net::Session>>,
+    mut log: ResMut<replay::CommandLog>,
+    world_map: Res<WorldMap>,
+    pathfinding: Res<crate::pathfinding::PathFinding>,
 ) {
-    let player_units_used = units
-        .iter()
-        .filter(|u| u.controller == game_state.active_player)
-        .all(|u| (u.goal.is_some() && u.next_cell.is_none()) || u.used_speed > 0.0);
-    let player_settlements_busy = settlements
-        .iter()
-        .filter(|u| u.controller == game_state.active_player)
-        .all(|s| s.construction.is_some());
-    if player_units_used && player_settlements_busy && !game_state.turn_ready_to_end {
-        game_state.turn_ready_to_end = true;
-        info!(
-            "Turn ready to end for player: {:?}",
-            game_state.active_player
-        )
+    let remote = remote_requests.read().map(|c| (&c.0, false));
+    for (command, local) in remote.chain(requests.read().map(|c| (&c.0, true))) {
+        if local && game_state.players[&game_state.active_player()].remote {
+            warn!(
+                "Ignored {:?}, the active player is on another machine",
+                command
+            );
+            continue;
+        }
+        match game_state
+            .game
+            .apply(pathfinding.board(&world_map), command.clone())
+        {
+            Ok(new_events) => {
+                events.write_batch(new_events.into_iter().map(GameEvent));
+            }
+            Err(e) => {
+                warn!("Rejected {:?}: {e}", command);
+                continue;
+            }
+        }
+        if let Err(e) = log.record(command, &game_state.game) {
+            error!("Could not hash the match for the replay: {e}");
+        }
+        if let Some(session) = session.as_mut()
+            && let Err(e) = session.applied(command, local, &game_state.game)
+        {
+            net::report(&mut game_state, &e);
+        }
     }
 }
-fn reset_turn_ready_to_end(
-    mut turn_start: MessageReader<TurnStart>,
+
+/// Lets a computer-controlled active player issue its next command.
+fn ai_turn(
     mut game_state: ResMut<GameState>,
+    world_map: Res<WorldMap>,
+    pathfinding: Res<crate::pathfinding::PathFinding>,
+    mut game_commands: MessageWriter<GameCommand>,
 ) {
-    for _turn in turn_start.read() {
-        game_state.turn_ready_to_end = false;
+    let GameState { players, game } = game_state.as_mut();
+    let Some(player) = players.get_mut(&game.active_player()) else {
+        return;
+    };
+    // The host plays the computer players in a LAN match.
+    if player.remote {
+        return;
+    }
+    let Some(ai) = player.ai.as_mut() else {
+        return;
+    };
+    if let Some(mut command) = ai.next_command(game, pathfinding.board(&world_map)) {
+        if let game_core::Command::FoundSettlement { name, .. } = &mut command {
+            *name = player.next_settlement_name();
+        }
+        game_commands.write(GameCommand(command));
     }
 }
 
@@ -881,119 +1241,141 @@ fn debug_notification(
     time: Res<Time>,
 ) {
     if input.just_pressed(KeyCode::KeyN) {
-        let active_player = game_state.active_player;
+        let active_player = game_state.active_player();
         let player = game_state.players.get_mut(&active_player).unwrap();
         player.add_notification(format!("This is a test notification! {:?}", time.elapsed()));
     }
 }
-fn turn_start_async(
-    runtime: ResMut<TokioTasksRuntime>,
-    world_map: Res<WorldMap>,
-    mut turn_start: MessageReader<TurnStart>,
+fn turn_start(
+    mut events: MessageReader<GameEvent>,
     mut commands: Commands,
     mut cameras: Query<(&mut Camera, Entity, &mut RtsCameraControls), Without<EguiContext>>,
-    mut units: Query<&mut Unit>,
-    mut settlements: Query<(Entity, &mut SettlementCenter)>,
     mut selected: ResMut<Selection>,
+    mut handover: ResMut<handover::Handover>,
     highlights: Query<Entity, With<CellHighlight>>,
-    mut game_state: ResMut<GameState>,
+    units: Query<(Entity, &Unit)>,
+    settlements: Query<(Entity, &SettlementCenter)>,
+    game_state: Res<GameState>,
 ) {
-    for turn in turn_start.read() {
-        let pathfinding_map = world_map.clone();
-        let turn_player = turn.player;
-        runtime.spawn_background_task(move |mut ctx| async move {
-            let _ = info_span!("turn_start_pathfinding").entered();
-            let (graph, nodes) = pathfinding::get_graph(&pathfinding_map);
-            ctx.run_on_main_thread(move |ctx| {
-                let world = ctx.world;
-                let mut system_state =
-                    SystemState::<(ResMut<crate::pathfinding::PathFinding>,)>::new(world);
-                let (mut pathfinding,) = { system_state.get_mut(world) };
-                *pathfinding = crate::pathfinding::PathFinding { graph, nodes };
-                system_state.apply(world);
-            })
-            .await;
-        });
-        let player = game_state.players.get_mut(&turn_player).unwrap();
-        for (mut camera, entity, mut controls) in cameras.iter_mut() {
-            if let Some(player_camera_entity) = player.camera_entity
-                && player_camera_entity == entity
-            {
-                camera.is_active = true;
-                controls.enabled = true;
-            } else {
-                camera.is_active = false;
-                controls.enabled = false;
-            }
-        }
-        info!("Turn started for player: {:?}", turn_player);
+    for event in events.read() {
+        let game_core::Event::TurnStarted { player, .. } = **event else {
+            continue;
+        };
+        let player = game_state.players.get(&player).unwrap();
+        info!("Turn started for player: {:?}", player.id);
         *selected = Selection::None;
         for entity in highlights.iter() {
             let mut highlight = commands.entity(entity);
             highlight.despawn();
         }
-        for mut unit in units.iter_mut().filter(|u| u.controller == turn_player) {
-            unit.used_speed = 0.0;
+        if player.ai.is_some() || player.remote {
+            // Keep showing the last human player's view while the computer or another machine
+            // plays.
+            continue;
         }
-        for (entity, mut settlement) in settlements
-            .iter_mut()
-            .filter(|(_, s)| s.controller == turn_player)
-        {
-            let production = settlement.production;
-            let cell = settlement.cell;
-            let mut completed_construction = false;
-            if let Some(ref mut construction) = settlement.construction {
-                match construction {
-                    ConstructionJob::Unit(unit_constuction) => {
-                        if unit_constuction.add_progress(production) {
-                            let neighbours = world_map.get_neighbours(cell);
-                            let neighbour = neighbours
-                                .iter()
-                                .find(|n| !units.iter().any(|u| u.current_cell == **n));
-                            if let Some(cell) = neighbour {
-                                let pos = world_map.get_position_for_cell(*cell);
-                                let template = unit_constuction.get_template(*cell);
-                                let unit_icon = template.unit.icon;
-                                let mut unit = commands.spawn((
-                                    template,
-                                    //MeshMaterial2d(materials.add(player.color)),
-                                    Transform::from_translation(pos),
-                                ));
-                                unit.observe(click_unit);
-                                let bark = player
-                                    .unit_spawn_barks
-                                    .get_mut(&unit_constuction.name)
-                                    .and_then(|barks| barks.pop())
-                                    .unwrap_or("Unit spawned!".to_string());
-                                player.add_notification_with_icon(bark, unit_icon);
-                                completed_construction = true;
-
-                                settlement.construction = None;
-                            } else {
-                                info!("No space for unit!");
-                            }
-                        }
-                    }
-                    ConstructionJob::Sink(sink_constuction) => {
-                        if sink_constuction.add_progress(production) {
-                            completed_construction = true;
-                            settlement.construction = None;
-                        }
-                    }
+        *selected = Selection::from_id(player.selection, player.id, &units, &settlements);
+        if handover.turn_started(&game_state, player.id) {
+            // Nothing is shown until the next player has taken over.
+            handover::show_view(&mut cameras, None);
+        } else {
+            handover::show_view(&mut cameras, player.camera_entity);
+        }
+    }
+}
+/// Keeps the active human player's selection, so it's back when their next turn starts.
+fn remember_selection(
+    selected: Res<Selection>,
+    mut game_state: ResMut<GameState>,
+    units: Query<(Entity, &Unit)>,
+    settlements: Query<(Entity, &SettlementCenter)>,
+) {
+    if !selected.is_changed() || !game_state.active_player_is_human() {
+        return;
+    }
+    let id = selected.id(&units, &settlements);
+    let active_player = game_state.active_player();
+    game_state
+        .players
+        .get_mut(&active_player)
+        .unwrap()
+        .selection = id;
+}
+fn spawn_units(
+    mut events: MessageReader<GameEvent>,
+    mut commands: Commands,
+    mut game_state: ResMut<GameState>,
+    world_map: Res<WorldMap>,
+    asset_server: Res<AssetServer>,
+    mut contexts: EguiContexts,
+) {
+    let GameState { players, game } = game_state.as_mut();
+    for event in events.read() {
+        match **event {
+            game_core::Event::UnitSpawned { unit, .. } => {
+                let Some(unit) = game.unit(unit) else {
+                    continue;
+                };
+                let Some(player) = players.get_mut(&unit.controller) else {
+                    continue;
+                };
+                spawn_unit(
+                    &mut commands,
+                    &world_map,
+                    unit,
+                    player,
+                    asset_server.as_ref(),
+                    &mut contexts,
+                );
+                let icon = unit_icon(
+                    player,
+                    &unit.unit_type,
+                    asset_server.as_ref(),
+                    &mut contexts,
+                );
+                let bark = player
+                    .unit_spawn_barks
+                    .get_mut(&unit.unit_type)
+                    .and_then(|barks| barks.pop())
+                    .unwrap_or("Unit spawned!".to_string());
+                player.add_notification_with_icon(bark, icon);
+            }
+            game_core::Event::SpawnBlocked { .. } => info!("No space for unit!"),
+            _ => {}
+        }
+    }
+}
+/// Queues unit moves for animation, puts undone moves straight back and removes units that died
+/// or settled down.
+fn update_units(
+    mut events: MessageReader<GameEvent>,
+    mut commands: Commands,
+    mut units: Query<(Entity, &mut Unit, &mut Transform)>,
+    world_map: Res<WorldMap>,
+) {
+    for event in events.read() {
+        match **event {
+            game_core::Event::UnitMoved { unit, to, .. } => {
+                if let Some((_, mut unit, _)) = units.iter_mut().find(|(_, u, _)| u.id == unit) {
+                    unit.moves.push_back(to);
                 }
             }
-            if completed_construction {
-                for con in settlement.available_constructions.iter_mut() {
-                    con.increase();
+            game_core::Event::MoveUndone { unit, cell } => {
+                if let Some((_, mut unit, mut transform)) =
+                    units.iter_mut().find(|(_, u, _)| u.id == unit)
+                {
+                    unit.current_cell = cell;
+                    unit.moves.clear();
+                    unit.move_timer = None;
+                    *transform = Transform::from_translation(world_map.get_position_for_cell(cell));
                 }
             }
-            settlement.turns_till_growth -= 1;
-            if settlement.turns_till_growth == 0 {
-                settlement.turns_till_growth = 1;
-                commands
-                    .entity(entity)
-                    .trigger(|e| SettlementGrows { target_entity: e });
+            game_core::Event::UnitDestroyed { unit }
+            | game_core::Event::SettlementFounded { unit, .. } => {
+                if let Some((entity, ..)) = units.iter().find(|(_, u, _)| u.id == unit) {
+                    commands.entity(entity).despawn();
+                }
             }
+            _ => {}
         }
     }
 }
@@ -1001,15 +1383,69 @@ struct Player {
     id: PlayerId,
     order: usize,
     color: Color,
-    _local: bool,
+    /// Set for computer-controlled players.
+    ai: Option<game_core::ai::Ai>,
     camera_entity: Option<Entity>,
+    material: Handle<StandardMaterial>,
     settlement_names: Vec<String>,
     settlement_context: SettlementNameCtx,
     unit_spawn_barks: HashMap<String, Vec<String>>,
     notifications: VecDeque<Notification>,
     civ: Civilisation,
+    /// Asked for before the player's turn is shown in hot-seat matches.
+    pin: Option<handover::Pin>,
+    /// What the player had selected when they last handed over.
+    selection: Option<SelectedId>,
+    /// Played on another machine in a LAN match, or from the file while a replay plays.
+    remote: bool,
 }
 impl Player {
+    fn new(order: usize, player_count: usize, civ: Civilisation) -> Self {
+        let t = order as f32 / (player_count + 1) as f32;
+        let color = Color::hsl(360.0 * t, 0.95, 0.7);
+        Player {
+            order,
+            id: PlayerId(order),
+            ai: None,
+            settlement_names: vec![],
+            settlement_context: SettlementNameCtx {
+                civilisation_name: civ.name.to_string(),
+                description: civ.description.to_string(),
+                seed_names: civ.settlement_name_seeds.clone(),
+            },
+            civ,
+            camera_entity: None,
+            material: Handle::default(),
+            color,
+            unit_spawn_barks: HashMap::new(),
+            notifications: VecDeque::new(),
+            pin: None,
+            selection: None,
+            remote: false,
+        }
+    }
+    fn is_local_human(&self) -> bool {
+        self.ai.is_none() && !self.remote
+    }
+    fn unit_type(&self, name: &str) -> &UnitType {
+        self.civ.units.iter().find(|u| u.name == name).unwrap()
+    }
+    /// The rules-side view of this player.
+    fn to_core(&self) -> game_core::Player {
+        game_core::Player {
+            id: self.id,
+            order: self.order,
+            unit_types: self.civ.units.clone(),
+            research: game_core::Research::default(),
+            materials: 0.0,
+        }
+    }
+    /// A name for the player's next settlement, from the generated names while they last.
+    fn next_settlement_name(&mut self) -> String {
+        self.settlement_names
+            .pop()
+            .unwrap_or(format!("Settlement: {}", self.id.0))
+    }
     fn add_notification(&mut self, message: String) {
         self.notifications.push_back(Notification {
             message,
@@ -1074,6 +1510,129 @@ impl AssetLoader for CivilisationAssetLoader {
     }
 }
 
+#[derive(TypePath, Debug, Clone, Asset, Deref)]
+struct TechTreeAsset(game_core::TechTree);
+#[derive(Default, TypePath)]
+struct TechTreeAssetLoader;
+
+/// Possible errors that can be produced by [`TechTreeAssetLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+enum TechTreeAssetLoaderError {
+    /// An [IO](std::io) Error
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON](ron) Error
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+    /// A tech needs a tech that isn't in the tree
+    #[error("Tech {tech} needs unknown tech {prerequisite}")]
+    UnknownPrerequisite { tech: String, prerequisite: String },
+}
+
+impl AssetLoader for TechTreeAssetLoader {
+    type Asset = TechTreeAsset;
+    type Settings = ();
+    type Error = TechTreeAssetLoaderError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let tech_tree = ron::de::from_bytes::<game_core::TechTree>(&bytes)?;
+        if let Some((tech, prerequisite)) = tech_tree.unknown_prerequisite() {
+            return Err(TechTreeAssetLoaderError::UnknownPrerequisite {
+                tech: tech.id.clone(),
+                prerequisite: prerequisite.to_string(),
+            });
+        }
+        Ok(TechTreeAsset(tech_tree))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tech.ron"]
+    }
+}
+
+#[derive(TypePath, Debug, Clone, Asset, Deref)]
+struct ImprovementAsset(game_core::Improvement);
+#[derive(Default, TypePath)]
+struct ImprovementAssetLoader;
+
+/// Possible errors that can be produced by [`ImprovementAssetLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+enum ImprovementAssetLoaderError {
+    /// An [IO](std::io) Error
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON](ron) Error
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for ImprovementAssetLoader {
+    type Asset = ImprovementAsset;
+    type Settings = ();
+    type Error = ImprovementAssetLoaderError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let improvement = ron::de::from_bytes::<game_core::Improvement>(&bytes)?;
+        Ok(ImprovementAsset(improvement))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["improvement.ron"]
+    }
+}
+
+#[derive(TypePath, Debug, Clone, Asset, Deref)]
+struct TerrainProfileAsset(world_generation::TerrainProfile);
+#[derive(Default, TypePath)]
+struct TerrainProfileAssetLoader;
+
+/// Possible errors that can be produced by [`TerrainProfileAssetLoader`]
+#[non_exhaustive]
+#[derive(Debug, Error)]
+enum TerrainProfileAssetLoaderError {
+    /// An [IO](std::io) Error
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON](ron) Error
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for TerrainProfileAssetLoader {
+    type Asset = TerrainProfileAsset;
+    type Settings = ();
+    type Error = TerrainProfileAssetLoaderError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let profile = ron::de::from_bytes::<world_generation::TerrainProfile>(&bytes)?;
+        Ok(TerrainProfileAsset(profile))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["profile.ron"]
+    }
+}
+
 #[derive(TypePath, Debug, Deserialize, Clone, Asset)]
 struct LLMProvider {
     pub name: String,
@@ -1135,74 +1694,20 @@ pub const CURRENT_OS: LibOperatingSystem = {
     }
 };
 
-#[derive(Debug, Deserialize, Clone)]
-struct UnitType {
-    pub name: String,
-    pub default_cost: f32,
-    pub health: f32,
-    pub range: usize,
-    pub speed: f32,
-    pub mesh_path: String,
-    pub icon_path: String,
-    pub seed_barks: Vec<String>,
-    pub description: String,
-}
-
-impl UnitType {
-    fn to_construction(
-        &self,
-        controller: PlayerId,
-        cell: CellId,
-        asset_server: &AssetServer,
-        contexts: &mut EguiContexts,
-        material: MeshMaterial3d<StandardMaterial>,
-    ) -> UnitConstuction {
-        UnitConstuction {
-            cost: self.default_cost,
-            progress: 0.0,
-            template: Box::new(UnitTemplate {
-                unit: Unit {
-                    name: self.name.clone(),
-                    max_health: self.health,
-                    health: self.health,
-                    range: self.range,
-                    controller,
-                    speed: self.speed,
-                    used_speed: 0.0,
-                    current_cell: cell,
-                    next_cell: None,
-                    goal: None,
-                    move_timer: None,
-                    icon: contexts.add_image(EguiTextureHandle::Strong(
-                        asset_server.load(self.icon_path.clone()),
-                    )),
-                },
-                mesh: Mesh3d(
-                    asset_server.load(
-                        GltfAssetLabel::Primitive {
-                            mesh: 0,
-                            primitive: 0,
-                        }
-                        .from_asset(self.mesh_path.clone()),
-                    ),
-                ),
-                material,
-            }),
-            name: self.name.clone(),
-        }
-    }
-}
 #[derive(Resource)]
 struct GameState {
     players: HashMap<PlayerId, Player>,
-    active_player: PlayerId,
-    turn_ready_to_end: bool,
+    game: game_core::Match,
 }
 impl GameState {
     fn new(
         player_count: usize,
         selected_civs: &mut [Option<AssetId<Civilisation>>],
+        player_ais: &[Option<game_core::ai::Difficulty>],
         civs: &Assets<Civilisation>,
+        tech_tree: game_core::TechTree,
+        improvements: Vec<game_core::Improvement>,
+        rng: &mut RandomRng,
     ) -> Self {
         let mut players = HashMap::with_capacity(player_count);
         for i in 0..player_count {
@@ -1214,157 +1719,98 @@ impl GameState {
                 let civ_i = rng.sample(Uniform::new(0, civs_vec.len()).unwrap());
                 civs_vec.get(civ_i).unwrap().clone()
             };
-            let t = i as f32 / (player_count + 1) as f32;
-            let color = Color::hsl(360.0 * t, 0.95, 0.7);
-            let player = Player {
-                order: i,
-                id: PlayerId(i),
-                _local: true,
-                settlement_names: vec![],
-                settlement_context: SettlementNameCtx {
-                    civilisation_name: civ.name.to_string(),
-                    description: civ.description.to_string(),
-                    seed_names: civ.settlement_name_seeds.clone(),
-                },
-                civ: civ.clone(),
-                camera_entity: None,
-                color,
-                unit_spawn_barks: HashMap::new(),
-                notifications: VecDeque::new(),
-            };
+            let mut player = Player::new(i, player_count, civ);
+            if let Some(difficulty) = player_ais.get(i).copied().flatten() {
+                player.ai = Some(game_core::ai::Ai::new(
+                    player.id,
+                    difficulty,
+                    RandomRng::from_rng(rng),
+                ));
+            }
             players.insert(player.id, player);
         }
-        let active_player = players.values().find(|p| p.order == 0).unwrap().id;
-        Self {
-            players,
-            active_player,
-            turn_ready_to_end: false,
-        }
+        let game = game_core::Match::new(
+            players.values().map(Player::to_core).collect(),
+            tech_tree,
+            improvements,
+            RandomRng::from_rng(rng),
+        );
+        Self { players, game }
     }
-}
-fn set_unit_next_cell(
-    mut units: Query<&mut Unit>,
-    world_map: Res<WorldMap>,
-    pathfinding: Res<crate::pathfinding::PathFinding>,
-) {
-    for mut unit in units.iter_mut() {
-        if let Some(goal) = unit.goal {
-            if unit.current_cell == goal {
-                unit.goal = None;
-                info!("Unit reached goal");
-
-                continue;
-            }
-            if unit.next_cell.is_none() {
-                let crate::pathfinding::PathFinding { graph, nodes } = pathfinding.as_ref();
-                let result = pathfinding::a_star(unit.current_cell, goal, graph, nodes, &world_map);
-                match result {
-                    Some(mut result) => {
-                        let _ = result.pop();
-                        let nex_cell = result.pop();
-                        if let Some(nex_cell) = nex_cell {
-                            let current_cell_pos =
-                                world_map.get_position_for_cell(unit.current_cell);
-                            let next_cell_pos = world_map.get_position_for_cell(nex_cell);
-                            let distance = current_cell_pos.distance(next_cell_pos);
-                            if unit.used_speed + distance > unit.speed {
-                                unit.next_cell = None;
-                                unit.move_timer = None;
-                                continue;
-                            }
-                            unit.used_speed += distance;
-                            unit.next_cell = Some(nex_cell);
-                            unit.move_timer = Some(Timer::from_seconds(5.0, TimerMode::Once));
-                        }
-                    }
-                    None => unit.goal = None,
-                }
-            }
+    fn active_player(&self) -> PlayerId {
+        self.game.active_player()
+    }
+    /// Whether the active player is a human playing on this machine.
+    fn active_player_is_human(&self) -> bool {
+        self.players
+            .get(&self.active_player())
+            .is_some_and(Player::is_local_human)
+    }
+    /// The player whose view is on screen, the active player unless the computer is playing.
+    fn viewing_player(&self) -> PlayerId {
+        if self.active_player_is_human() {
+            return self.active_player();
         }
+        self.players
+            .values()
+            .filter(|p| p.is_local_human())
+            .min_by_key(|p| p.order)
+            .map_or(self.active_player(), |p| p.id)
     }
 }
+/// Walks units along the cells the match has moved them through.
 fn move_unit(
     mut units: Query<(&mut Unit, &mut Transform)>,
     world_map: Res<WorldMap>,
     time: Res<Time>,
 ) {
     for (mut unit, mut transform) in units.iter_mut() {
-        if let Some(next_cell) = unit.next_cell {
-            if unit.current_cell == next_cell {
-                unit.next_cell = None;
-                continue;
-            }
-            let current_cell = unit.current_cell.0;
-            let move_timer = unit.move_timer.as_mut().unwrap();
-            move_timer.tick(time.delta());
+        let Some(next_cell) = unit.moves.front().copied() else {
+            continue;
+        };
+        let current_cell = unit.current_cell;
+        let move_timer = unit
+            .move_timer
+            .get_or_insert_with(|| Timer::from_seconds(5.0, TimerMode::Once));
+        move_timer.tick(time.delta());
 
-            let next_cell_pos = world_map.get_position_for_cell(next_cell);
-            if move_timer.is_finished() {
-                *transform = Transform::from_translation(next_cell_pos);
-                unit.current_cell = next_cell;
-                unit.next_cell = None;
-            } else {
-                let current_cell_pos = world_map.get_position_for_cell(CellId(current_cell));
-                let new_pos = current_cell_pos.lerp(next_cell_pos, move_timer.fraction());
+        let next_cell_pos = world_map.get_position_for_cell(next_cell);
+        if move_timer.is_finished() {
+            *transform = Transform::from_translation(next_cell_pos);
+            unit.current_cell = next_cell;
+            unit.moves.pop_front();
+            unit.move_timer = None;
+        } else {
+            let current_cell_pos = world_map.get_position_for_cell(current_cell);
+            let new_pos = current_cell_pos.lerp(next_cell_pos, move_timer.fraction());
 
-                *transform = Transform::from_translation(new_pos);
-            }
+            *transform = Transform::from_translation(new_pos);
         }
     }
 }
 fn click_unit(
     mut event: On<Pointer<Click>>,
-    mut commands: Commands,
     mut selection: ResMut<Selection>,
-    mut units: Query<&mut Unit>,
-    mut random: ResMut<Random<RandomRng>>,
+    units: Query<&Unit>,
     game_state: Res<GameState>,
-    world_map: Res<WorldMap>,
-    pathfinding: Res<crate::pathfinding::PathFinding>,
+    mut game_commands: MessageWriter<GameCommand>,
 ) {
     if event.button == PointerButton::Primary {
-        let controller = units.get(event.entity).unwrap().controller;
-        if controller == game_state.active_player {
+        let defender = units.get(event.entity).unwrap().id;
+        let Some(controller) = game_state.game.unit(defender).map(|u| u.controller) else {
+            return;
+        };
+        if controller == game_state.active_player() {
             *selection = Selection::Unit(event.entity);
         } else {
             match *selection {
                 Selection::None => {}
                 Selection::Unit(entity) => {
-                    let [mut attacker, mut defender] =
-                        units.get_many_mut([entity, event.entity]).unwrap();
-                    let crate::pathfinding::PathFinding { graph, nodes } = pathfinding.as_ref();
-                    let result = pathfinding::a_star(
-                        attacker.current_cell,
-                        defender.current_cell,
-                        graph,
-                        nodes,
-                        &world_map,
-                    );
-                    if let Some(result) = result {
-                        let distance = result.len() - 1;
-                        if distance <= attacker.range {
-                            let damage = random
-                                .0
-                                .as_mut()
-                                .unwrap()
-                                .sample(Uniform::new(1.0, 3.0).unwrap());
-                            defender.health -= damage;
-                            if defender.health <= 0.0 {
-                                commands.entity(event.entity).despawn();
-                            }
-                        }
-                        if distance <= defender.range {
-                            let damage = random
-                                .0
-                                .as_mut()
-                                .unwrap()
-                                .sample(Uniform::new(0.5, 1.5).unwrap());
-                            attacker.health -= damage;
-                            if attacker.health <= 0.0 {
-                                commands.entity(entity).despawn();
-                            }
-                        }
-                    }
+                    let attacker = units.get(entity).unwrap().id;
+                    game_commands.write(GameCommand(game_core::Command::Attack {
+                        attacker,
+                        defender,
+                    }));
                 }
                 Selection::Settlement(_entity) => {}
             }
@@ -1372,16 +1818,105 @@ fn click_unit(
         event.propagate(false);
     }
 }
+/// Outlines where the selected unit can still move this turn and the enemies and settlements it
+/// can attack.
+fn highlight_selection(
+    mut events: MessageReader<GameEvent>,
+    selected: Res<Selection>,
+    units: Query<&Unit>,
+    cells: Query<(&Cell, Entity)>,
+    highlights: Query<(Entity, &CellHighlight)>,
+    game_state: Res<GameState>,
+    world_map: Res<WorldMap>,
+    pathfinding: Res<crate::pathfinding::PathFinding>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    let game_changed = events.read().count() > 0;
+    if !game_changed && !selected.is_changed() {
+        return;
+    }
+    for (entity, highlight) in highlights.iter() {
+        if highlight.kind != HighlightKind::Path {
+            commands.entity(entity).despawn();
+        }
+    }
+    let Selection::Unit(unit_entity) = *selected else {
+        return;
+    };
+    let Some(unit) = units
+        .get(unit_entity)
+        .ok()
+        .and_then(|u| game_state.game.unit(u.id))
+    else {
+        return;
+    };
+    let board = pathfinding.board(&world_map);
+    let reachable = game_state.game.reachable_cells(board, unit.id);
+    let in_range = board.cells_in_range(unit.cell, unit.range);
+    let reach_material = materials.add(Color::srgb(0.3, 0.6, 1.0));
+    let target_material = materials.add(Color::srgb(0.9, 0.2, 0.2));
+    for (cell, entity) in cells.iter() {
+        let is_target = in_range.contains_key(&cell.cell_id)
+            && (game_state
+                .game
+                .unit_at(cell.cell_id)
+                .is_some_and(|u| u.controller != unit.controller)
+                || game_state
+                    .game
+                    .settlements()
+                    .any(|s| s.cell == cell.cell_id && s.controller != unit.controller));
+        let (kind, material) = if is_target {
+            (HighlightKind::Target, target_material.clone())
+        } else if cell.cell_id != unit.cell && reachable.contains_key(&cell.cell_id) {
+            (HighlightKind::Reach, reach_material.clone())
+        } else {
+            continue;
+        };
+        commands.entity(entity).with_child((
+            Mesh3d(cell.outline.clone()),
+            MeshMaterial3d(material),
+            // Just under the path preview, so the route stays visible on top.
+            Transform::from_xyz(0.0, 1.9, 0.0),
+            CellHighlight {
+                _unit: Some(unit_entity),
+                kind,
+                turn: None,
+            },
+        ));
+    }
+}
+/// Selects own settlements. With a unit selected, enemy settlements are attacked until their walls
+/// are down and then walked into.
 fn click_settlement(
     mut event: On<Pointer<Click>>,
     mut selected_unit: ResMut<Selection>,
     settlements: Query<&SettlementCenter>,
+    units: Query<&Unit>,
     game_state: Res<GameState>,
+    mut game_commands: MessageWriter<GameCommand>,
 ) {
     if event.button == PointerButton::Primary {
-        let settlement = settlements.get(event.entity).unwrap();
-        if settlement.controller == game_state.active_player {
+        let settlement = settlements.get(event.entity).unwrap().id;
+        let Some(settlement) = game_state.game.settlement(settlement) else {
+            return;
+        };
+        if settlement.controller == game_state.active_player() {
             *selected_unit = Selection::Settlement(event.entity);
+        } else if let Selection::Unit(entity) = *selected_unit
+            && let Ok(unit) = units.get(entity)
+        {
+            game_commands.write(GameCommand(if settlement.breached() {
+                game_core::Command::MoveUnit {
+                    unit: unit.id,
+                    goal: Some(settlement.cell),
+                }
+            } else {
+                game_core::Command::AttackSettlement {
+                    attacker: unit.id,
+                    settlement: settlement.id,
+                }
+            }));
         }
         event.propagate(false);
     }
@@ -1394,6 +1929,18 @@ struct Cell {
 #[derive(Component)]
 struct CellHighlight {
     _unit: Option<Entity>,
+    kind: HighlightKind,
+    /// Turns until a planned move gets here, set on the last cell reached each turn.
+    turn: Option<u32>,
+}
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum HighlightKind {
+    /// The route a move would take, follows the pointer.
+    Path,
+    /// Where the selected unit can still get to this turn.
+    Reach,
+    /// An enemy unit or settlement the selected unit can attack from where it stands.
+    Target,
 }
 
 #[derive(Resource)]
@@ -1402,142 +1949,63 @@ enum Selection {
     Unit(Entity),
     Settlement(Entity),
 }
+impl Selection {
+    /// What's selected, by its id in the match.
+    fn id(
+        &self,
+        units: &Query<(Entity, &Unit)>,
+        settlements: &Query<(Entity, &SettlementCenter)>,
+    ) -> Option<SelectedId> {
+        match *self {
+            Selection::None => None,
+            Selection::Unit(entity) => units.get(entity).ok().map(|(_, u)| SelectedId::Unit(u.id)),
+            Selection::Settlement(entity) => settlements
+                .get(entity)
+                .ok()
+                .map(|(_, s)| SelectedId::Settlement(s.id)),
+        }
+    }
+    /// Selects what `id` refers to, or nothing once it's gone or no longer `player`'s.
+    fn from_id(
+        id: Option<SelectedId>,
+        player: PlayerId,
+        units: &Query<(Entity, &Unit)>,
+        settlements: &Query<(Entity, &SettlementCenter)>,
+    ) -> Self {
+        match id {
+            None => Selection::None,
+            Some(SelectedId::Unit(id)) => units
+                .iter()
+                .find(|(_, u)| u.id == id)
+                .map_or(Selection::None, |(entity, _)| Selection::Unit(entity)),
+            Some(SelectedId::Settlement(id)) => settlements
+                .iter()
+                .find(|(_, s)| s.id == id && s.controller == player)
+                .map_or(Selection::None, |(entity, _)| Selection::Settlement(entity)),
+        }
+    }
+}
+/// A [`Selection`] by id, so it outlives the entities and can be saved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+enum SelectedId {
+    Unit(game_core::UnitId),
+    Settlement(game_core::SettlementId),
+}
 
+/// The on-screen counterpart of a [`game_core::Unit`].
 #[derive(Component, Clone, Debug)]
 struct Unit {
-    name: String,
-    max_health: f32,
-    health: f32,
-    range: usize,
-    controller: PlayerId,
-    speed: f32,
-    used_speed: f32,
+    id: game_core::UnitId,
+    /// The cell the unit is drawn at, trails the match while moves are animated.
     current_cell: CellId,
-    next_cell: Option<CellId>,
-    goal: Option<CellId>,
+    moves: VecDeque<CellId>,
     move_timer: Option<Timer>,
     icon: egui::TextureId,
 }
 
+/// The on-screen counterpart of a [`game_core::Settlement`].
 #[derive(Component)]
 struct SettlementCenter {
-    cell: CellId,
+    id: game_core::SettlementId,
     controller: PlayerId,
-    construction: Option<ConstructionJob>,
-    production: f32,
-    available_constructions: Vec<ConstructionJob>,
-    name: String,
-    controlled_cells: Vec<CellId>,
-    turns_till_growth: u8,
-}
-trait Construction {
-    fn add_progress(&mut self, progress: f32) -> bool;
-    fn cost(&self) -> f32;
-    fn progress(&self) -> f32;
-}
-
-#[derive(Debug, Clone)]
-struct UnitConstuction {
-    cost: f32,
-    progress: f32,
-    template: Box<UnitTemplate>,
-    name: String,
-}
-impl UnitConstuction {
-    fn get_template(&self, cell: CellId) -> UnitTemplate {
-        UnitTemplate {
-            unit: Unit {
-                current_cell: cell,
-                ..self.template.unit.clone()
-            },
-            ..*self.template.clone()
-        }
-    }
-}
-
-impl Construction for UnitConstuction {
-    fn add_progress(&mut self, progress: f32) -> bool {
-        self.progress += progress;
-        self.progress >= self.cost
-    }
-
-    fn cost(&self) -> f32 {
-        self.cost
-    }
-
-    fn progress(&self) -> f32 {
-        self.progress
-    }
-}
-#[derive(Clone)]
-struct SinkConstuction {
-    cost: f32,
-    progress: f32,
-}
-impl Construction for SinkConstuction {
-    fn add_progress(&mut self, progress: f32) -> bool {
-        self.progress += progress;
-        self.progress >= self.cost
-    }
-
-    fn cost(&self) -> f32 {
-        self.cost
-    }
-
-    fn progress(&self) -> f32 {
-        self.progress
-    }
-}
-#[derive(Bundle, Clone, Debug)]
-struct UnitTemplate {
-    unit: Unit,
-    mesh: Mesh3d,
-    material: MeshMaterial3d<StandardMaterial>,
-}
-#[derive(Clone)]
-enum ConstructionJob {
-    Unit(UnitConstuction),
-    Sink(SinkConstuction),
-}
-impl ConstructionJob {
-    pub fn progress_label(&self, ui: &mut Ui) {
-        match self {
-            ConstructionJob::Unit(unit_constuction) => {
-                ui.label(format!(
-                    "{}: {}/{}",
-                    unit_constuction.name,
-                    unit_constuction.progress(),
-                    unit_constuction.cost()
-                ));
-            }
-            ConstructionJob::Sink(sink) => {
-                ui.label(format!("Sink: {}/{}", sink.progress(), sink.cost()));
-            }
-        }
-    }
-    pub fn available_button(&self, ui: &mut Ui, enabled: bool) -> Response {
-        match self {
-            ConstructionJob::Unit(unit_constuction) => ui.add_enabled(
-                enabled,
-                egui::widgets::Button::new(format!(
-                    "{}: {}",
-                    unit_constuction.name, unit_constuction.cost
-                )),
-            ),
-            ConstructionJob::Sink(sink) => ui.add_enabled(
-                enabled,
-                egui::widgets::Button::new(format!("Sink: {}", sink.cost)),
-            ),
-        }
-    }
-    pub fn increase(&mut self) {
-        match self {
-            ConstructionJob::Unit(unit_constuction) => {
-                unit_constuction.cost = unit_constuction.cost.powf(1.5);
-            }
-            ConstructionJob::Sink(sink_constuction) => {
-                sink_constuction.cost = sink_constuction.cost.powf(1.5)
-            }
-        }
-    }
 }
@@ -4,11 +4,16 @@ use bevy::{
 };
 use bevy_egui::{EguiContexts, EguiPrimaryContextPass};
 use bevy_kira_audio::{AudioChannel, AudioControl};
+use bevy_persistent::Persistent;
 use menu::{FullscreenMode, Settings};
+use rand::SeedableRng;
+
+use std::path::PathBuf;
 
 use crate::{
-    AppState, AudioSettings, Civilisation, GameState, LLMProvider, LLMSettings, Music,
-    VideoSettings,
+    AppState, AudioSettings, Civilisation, GameState, ImprovementAsset, LLMProvider, LLMSettings,
+    LoadedFolders, Music, Random, RandomRng, TechTreeAsset, TerrainProfileAsset, VideoSettings,
+    WorldPresets, net, save,
 };
 pub struct MenuPlugin;
 impl Plugin for MenuPlugin {
@@ -27,6 +32,16 @@ impl Plugin for MenuPlugin {
             EguiPrimaryContextPass,
             new_game_menu.run_if(in_state(MenuState::NewGame)),
         );
+        app.init_resource::<SaveList>();
+        app.add_systems(OnEnter(MenuState::LoadGame), list_saves);
+        app.add_systems(
+            EguiPrimaryContextPass,
+            load_game_menu.run_if(in_state(MenuState::LoadGame)),
+        );
+        app.add_systems(
+            EguiPrimaryContextPass,
+            lan_menu.run_if(in_state(MenuState::Lan)),
+        );
     }
 }
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
@@ -49,6 +64,8 @@ enum MenuState {
     Main,
     Settings,
     NewGame,
+    LoadGame,
+    Lan,
 }
 const MENU_OFFSET_X: f32 = -200.0; // negative = left of center
 const MENU_WIDTH: f32 = 220.0;
@@ -64,6 +81,12 @@ fn main_menu(
         menu::MainMenuAction::NewGame => {
             next_menu_state.set(MenuState::NewGame);
         }
+        menu::MainMenuAction::LoadGame => {
+            next_menu_state.set(MenuState::LoadGame);
+        }
+        menu::MainMenuAction::LanGame => {
+            next_menu_state.set(MenuState::Lan);
+        }
         menu::MainMenuAction::Settings => {
             next_menu_state.set(MenuState::Settings);
         }
@@ -79,48 +102,329 @@ fn new_game_menu(
     mut contexts: EguiContexts,
     mut next_state: ResMut<NextState<AppState>>,
     mut next_menu_state: ResMut<NextState<MenuState>>,
-    //mut params: ResMut<crate::generate::WorldGenerationParams>,
-    mut temp_params: Local<Option<world_generation::WorldGenerationParams>>,
-    mut player_count: Local<Option<u8>>,
+    mut settings: Local<Option<menu::NewWorldSettings<AssetId<Civilisation>>>>,
+    mut world_presets: ResMut<Persistent<WorldPresets>>,
     civs: Res<Assets<Civilisation>>,
-    mut selected_civs: Local<Option<Vec<Option<AssetId<Civilisation>>>>>,
+    mut random: ResMut<Random<RandomRng>>,
+    folders: Res<LoadedFolders>,
+    tech_trees: Res<Assets<TechTreeAsset>>,
+    improvements: Res<Assets<ImprovementAsset>>,
+    profiles: Res<Assets<TerrainProfileAsset>>,
 ) {
     let ctx = contexts.ctx_mut().unwrap();
-    let temp_params =
-        temp_params.get_or_insert_with(|| crate::generate::WorldType::Default.get_params());
-    let player_count = player_count.get_or_insert_with(|| 1);
-    let selected_civs = selected_civs.get_or_insert_with(|| vec![None, None, None, None]);
-    let mut settings = menu::NewWorldSettings {
-        world_type: temp_params.world_type,
-        player_count: *player_count as usize,
-        selected_civs: selected_civs.clone(),
-    };
+    let default_profile = world_generation::TerrainProfile::default();
+    let settings = settings.get_or_insert_with(|| menu::NewWorldSettings {
+        params: crate::generate::WorldType::Default.get_params(),
+        seed: crate::seed_of(random.0.as_ref().unwrap()),
+        preset_name: String::new(),
+        profile: default_profile.name.clone(),
+        player_count: 1,
+        selected_civs: vec![None, None, None, None],
+        player_ais: vec![None; 4],
+        player_pins: vec![String::new(); 4],
+        victories: game_core::Victory::ALL.map(|v| (v, true)).to_vec(),
+    });
     let civ_map = civs
         .iter()
         .map(|(id, civ)| (id, civ.name.clone()))
         .collect::<std::collections::HashMap<_, _>>();
-    let action = menu::new_game_menu(ctx, MENU_OFFSET_X, MENU_WIDTH, &mut settings, &civ_map);
-    *temp_params = settings.world_type.get_params();
-    *player_count = settings.player_count as u8;
-    *selected_civs = settings.selected_civs.clone();
+    // The built-in profile first, then the loaded ones by name.
+    let mut profile_names = profiles
+        .iter()
+        .map(|(_, p)| p.name.clone())
+        .collect::<Vec<_>>();
+    profile_names.sort();
+    profile_names.insert(0, default_profile.name.clone());
+    let action = menu::new_game_menu(
+        ctx,
+        MENU_OFFSET_X,
+        MENU_WIDTH,
+        settings,
+        &civ_map,
+        &world_presets.presets,
+        &profile_names,
+    );
     match action {
         menu::NewGameMenuAction::None => {}
         menu::NewGameMenuAction::Start => {
-            commands.insert_resource(crate::generate::WorldGenerationParams(Some(*temp_params)));
-            //*params = crate::generate::WorldGenerationParams(Some(*temp_params));
-            commands.insert_resource(GameState::new(
+            // The menu only allows starting with a valid seed, so the same seed always gives
+            // the same world and match.
+            if let Some(rng) = crate::rng_from_seed(&settings.seed) {
+                random.0 = Some(rng);
+            }
+            commands.insert_resource(crate::generate::WorldGenerationParams(Some(
+                settings.params,
+            )));
+            let profile = profiles
+                .iter()
+                .map(|(_, p)| &**p)
+                .find(|p| p.name == settings.profile)
+                .cloned()
+                .unwrap_or(default_profile);
+            commands.insert_resource(crate::generate::WorldProfile(profile));
+            commands.remove_resource::<crate::generate::WorldGenerationRng>();
+            commands.remove_resource::<save::PendingLoad>();
+            // Sorted so the match doesn't depend on the order the files finished loading in.
+            let mut improvements = improvements
+                .iter()
+                .map(|(_, i)| (**i).clone())
+                .collect::<Vec<_>>();
+            improvements.sort_by(|a, b| a.name.cmp(&b.name));
+            let mut game_state = GameState::new(
                 settings.player_count,
                 &mut settings.selected_civs,
+                &settings.player_ais,
                 civs.as_ref(),
-            ));
+                folders
+                    .tech_tree
+                    .as_ref()
+                    .and_then(|h| tech_trees.get(h))
+                    .map(|t| (**t).clone())
+                    .unwrap_or_default(),
+                improvements,
+                random.0.as_mut().unwrap(),
+            );
+            game_state.game.set_victories(
+                settings
+                    .victories
+                    .iter()
+                    .filter(|(_, enabled)| *enabled)
+                    .map(|(victory, _)| *victory)
+                    .collect(),
+            );
+            for player in game_state.players.values_mut() {
+                let pin = &settings.player_pins[player.order];
+                if player.ai.is_none() && !pin.is_empty() {
+                    player.pin = Some(crate::handover::Pin::new(pin));
+                }
+            }
+            commands.insert_resource(game_state);
             next_state.set(AppState::Generating);
         }
+        menu::NewGameMenuAction::RandomizeSeed => {
+            settings.seed = crate::seed_of(&RandomRng::from_os_rng());
+        }
+        menu::NewGameMenuAction::SavePreset => {
+            let preset = menu::WorldPreset {
+                name: settings.preset_name.trim().to_string(),
+                params: settings.params,
+            };
+            world_presets
+                .update(|presets| {
+                    presets.presets.retain(|p| p.name != preset.name);
+                    presets.presets.push(preset.clone());
+                })
+                .expect("Failed to save world presets");
+        }
+        menu::NewGameMenuAction::DeletePreset(i) => {
+            world_presets
+                .update(|presets| {
+                    presets.presets.remove(i);
+                })
+                .expect("Failed to save world presets");
+        }
         menu::NewGameMenuAction::Return => {
             next_menu_state.set(MenuState::Main);
         }
     }
 }
 
+/// The save files shown in the load menu, read again each time it's opened so saves made since
+/// show up.
+#[derive(Resource, Default)]
+struct SaveList {
+    paths: Vec<PathBuf>,
+    summaries: Vec<menu::SaveSummary>,
+}
+
+fn list_saves(mut saves: ResMut<SaveList>) {
+    let paths = save::list_saves();
+    let summaries = paths
+        .iter()
+        .map(|path| {
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default()
+                .to_string();
+            let description = match save::read_save(path) {
+                Ok(save) => save
+                    .players
+                    .iter()
+                    .map(|p| p.civilisation.clone())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                Err(e) => e.to_string(),
+            };
+            menu::SaveSummary { name, description }
+        })
+        .collect();
+    *saves = SaveList { paths, summaries };
+}
+
+fn load_game_menu(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut next_menu_state: ResMut<NextState<MenuState>>,
+    civs: Res<Assets<Civilisation>>,
+    mut random: ResMut<Random<RandomRng>>,
+    saves: Res<SaveList>,
+) {
+    let ctx = contexts.ctx_mut().unwrap();
+    let action = menu::load_game_menu(ctx, MENU_OFFSET_X, MENU_WIDTH, &saves.summaries);
+    match action {
+        menu::LoadGameMenuAction::None => {}
+        menu::LoadGameMenuAction::Load(i) => {
+            let path = &saves.paths[i];
+            match save::load_game(&mut commands, path, civs.as_ref(), random.as_mut()) {
+                Ok(()) => next_state.set(AppState::Generating),
+                Err(e) => error!("Failed to load save {}: {e}", path.display()),
+            }
+        }
+        menu::LoadGameMenuAction::Return => {
+            next_menu_state.set(MenuState::Main);
+        }
+    }
+}
+
+/// Hosts or joins a two player LAN match, the host picks the world and starts it.
+fn lan_menu(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut next_menu_state: ResMut<NextState<MenuState>>,
+    mut lobby: ResMut<net::LanLobby>,
+    mut settings: Local<Option<menu::LanSettings<AssetId<Civilisation>>>>,
+    mut guest_civ: Local<Option<AssetId<Civilisation>>>,
+    civs: Res<Assets<Civilisation>>,
+    mut random: ResMut<Random<RandomRng>>,
+    folders: Res<LoadedFolders>,
+    tech_trees: Res<Assets<TechTreeAsset>>,
+    improvements: Res<Assets<ImprovementAsset>>,
+) {
+    let ctx = contexts.ctx_mut().unwrap();
+    let settings = settings.get_or_insert_with(|| menu::LanSettings {
+        host_address: net::DEFAULT_HOST_ADDRESS.to_string(),
+        join_address: net::DEFAULT_JOIN_ADDRESS.to_string(),
+        civ: None,
+        world_type: crate::generate::WorldType::Default,
+        status: String::new(),
+        connected: false,
+        can_start: false,
+    });
+    match lobby.update() {
+        Ok(None) => {}
+        Ok(Some(net::LobbyEvent::Joined { civilisation })) => {
+            *guest_civ = civs
+                .iter()
+                .find(|(_, c)| c.name == civilisation)
+                .map(|(id, _)| id);
+            settings.status = match *guest_civ {
+                Some(_) => format!("The {civilisation} joined"),
+                None => format!("The other player picked {civilisation}, which isn't installed"),
+            };
+            settings.can_start = guest_civ.is_some();
+        }
+        Ok(Some(net::LobbyEvent::Started { save, seat })) => {
+            let connection = std::mem::take(lobby.as_mut()).into_connection().unwrap();
+            match net::load_match(
+                &mut commands,
+                *save,
+                seat,
+                civs.as_ref(),
+                random.as_mut(),
+                connection,
+            ) {
+                Ok(()) => {
+                    settings.disconnect(String::new());
+                    next_state.set(AppState::Generating);
+                }
+                Err(e) => settings.disconnect(e.to_string()),
+            }
+        }
+        Err(e) => {
+            *lobby = net::LanLobby::Idle;
+            settings.disconnect(e.to_string());
+        }
+    }
+    let civ_map = civs
+        .iter()
+        .map(|(id, civ)| (id, civ.name.clone()))
+        .collect::<std::collections::HashMap<_, _>>();
+    let action = menu::lan_menu(ctx, MENU_OFFSET_X, MENU_WIDTH, settings, &civ_map);
+    match action {
+        menu::LanMenuAction::None => {}
+        menu::LanMenuAction::Host => match net::LanLobby::host(&settings.host_address) {
+            Ok(hosting) => {
+                *lobby = hosting;
+                settings.status = format!("Waiting for a player on {}", settings.host_address);
+                settings.connected = true;
+            }
+            Err(e) => settings.status = e.to_string(),
+        },
+        menu::LanMenuAction::Join => {
+            let civilisation = civs.get(settings.civ.unwrap()).unwrap().name.clone();
+            match net::LanLobby::join(&settings.join_address, civilisation) {
+                Ok(joined) => {
+                    *lobby = joined;
+                    settings.status = "Waiting for the host to start".to_string();
+                    settings.connected = true;
+                }
+                Err(e) => settings.status = e.to_string(),
+            }
+        }
+        menu::LanMenuAction::Start => {
+            let connection = std::mem::take(lobby.as_mut()).into_connection().unwrap();
+            commands.insert_resource(crate::generate::WorldGenerationParams(Some(
+                settings.world_type.get_params(),
+            )));
+            commands.insert_resource(crate::generate::WorldProfile::default());
+            commands.remove_resource::<crate::generate::WorldGenerationRng>();
+            commands.remove_resource::<save::PendingLoad>();
+            let mut improvements = improvements
+                .iter()
+                .map(|(_, i)| (**i).clone())
+                .collect::<Vec<_>>();
+            improvements.sort_by(|a, b| a.name.cmp(&b.name));
+            let mut game_state = GameState::new(
+                2,
+                &mut [settings.civ, *guest_civ],
+                &[None, None],
+                civs.as_ref(),
+                folders
+                    .tech_tree
+                    .as_ref()
+                    .and_then(|h| tech_trees.get(h))
+                    .map(|t| (**t).clone())
+                    .unwrap_or_default(),
+                improvements,
+                random.0.as_mut().unwrap(),
+            );
+            game_state
+                .game
+                .set_victories(game_core::Victory::ALL.to_vec());
+            // The host always plays first.
+            let guest = game_state
+                .players
+                .values_mut()
+                .find(|p| p.order == 1)
+                .unwrap();
+            guest.remote = true;
+            commands.insert_resource(net::Session::host(connection, guest.id));
+            commands.insert_resource(game_state);
+            settings.disconnect(String::new());
+            next_state.set(AppState::Generating);
+        }
+        menu::LanMenuAction::Return => {
+            *lobby = net::LanLobby::Idle;
+            *guest_civ = None;
+            settings.disconnect(String::new());
+            next_menu_state.set(MenuState::Main);
+        }
+    }
+}
+
 fn settings_menu(
     mut contexts: EguiContexts,
     mut next_menu_state: ResMut<NextState<MenuState>>,
@@ -7,12 +7,15 @@ use bevy::{
 };
 use bevy_egui::{EguiTextureHandle, EguiUserTextures};
 
-use crate::generate;
+use crate::{AppState, generate, generate::CellId};
 
 #[derive(Resource, Deref)]
 pub struct MinimapImage(Handle<Image>);
 #[derive(Component)]
 pub struct MinimapControlledArea(pub Entity);
+/// A cell's shape on the minimap, hidden until the viewing player explores the cell.
+#[derive(Component)]
+pub struct MinimapCell(pub CellId);
 pub fn setup(
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
@@ -58,6 +61,7 @@ pub fn spawn_minimap_camera(
     info!("World bounds: {:?}", bounds);
     commands.spawn((
         Camera2d,
+        DespawnOnExit(AppState::InGame),
         Camera {
             order: -1, // render before your main camera if you want
             ..default()
@@ -0,0 +1,409 @@
+//! LAN multiplayer, a host and one joining player run the same match in lockstep.
+//!
+//! The host sets the match up and sends it over as a compact [`SaveFile`], so the joiner
+//! regenerates the same world from the seed and params. After that only [`Command`]s cross the
+//! wire, both sides apply them to their own copy of the match and swap a hash of it after every
+//! turn to catch desyncs. Messages are RON, one per line.
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        Mutex,
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_rts_camera::RtsCamera;
+use game_core::{Command, Match, PlayerId};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    AppState, Civilisation, GameState, Random, RandomRng,
+    generate::{WorldGenerationRng, WorldMap},
+    save::{self, SaveFile},
+};
+
+/// Listens on every interface, so players elsewhere on the network can join.
+pub const DEFAULT_HOST_ADDRESS: &str = "0.0.0.0:7878";
+pub const DEFAULT_JOIN_ADDRESS: &str = "127.0.0.1:7878";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct NetPlugin;
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LanLobby>();
+        app.add_message::<RemoteCommand>();
+        app.add_systems(
+            OnEnter(AppState::InGame),
+            send_start
+                .after(crate::startup)
+                .run_if(resource_exists::<Session>),
+        );
+        app.add_systems(OnExit(AppState::InGame), leave_session);
+    }
+}
+
+/// A command issued somewhere else, by the other player in a LAN match or read from a replay.
+/// Applied without being sent back.
+#[derive(Message, Deref)]
+pub struct RemoteCommand(pub Command);
+
+#[derive(Serialize, Deserialize)]
+pub enum NetMessage {
+    /// Sent first both ways, lockstep needs both sides on the same rules.
+    Hello {
+        version: String,
+    },
+    /// The joining player's choice of civilisation.
+    Join {
+        civilisation: String,
+    },
+    /// The host's match just after setup, and the seat the joining player plays.
+    Start {
+        save: Box<SaveFile>,
+        seat: PlayerId,
+    },
+    Command(Command),
+    /// [`state_hash`] of the match once `player`'s turn on `turn` started.
+    TurnHash {
+        turn: u32,
+        player: PlayerId,
+        hash: u64,
+    },
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum NetError {
+    /// An [IO](std::io) Error
+    #[error("Network error: {0}")]
+    Io(#[from] io::Error),
+    /// A [RON](ron) serialisation Error
+    #[error("Could not write RON: {0}")]
+    Ron(#[from] ron::Error),
+    /// A [RON](ron) parse Error
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+    #[error("The other player is on version {0} (expected {})", env!("CARGO_PKG_VERSION"))]
+    VersionMismatch(String),
+    #[error("Lost connection to the other player")]
+    Disconnected,
+    #[error("The match differs between players from turn {0}")]
+    Desync(u32),
+    #[error("Could not start the match: {0}")]
+    Save(#[from] save::SaveError),
+}
+
+/// A line-based connection to the other player, read on a background thread.
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Mutex<Receiver<Result<NetMessage, NetError>>>,
+}
+impl Connection {
+    fn new(stream: TcpStream) -> Result<Self, NetError> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let message = ron::from_str::<NetMessage>(&line).map_err(NetError::from);
+                let failed = message.is_err();
+                if sender.send(message).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        let mut connection = Self {
+            stream,
+            incoming: Mutex::new(incoming),
+        };
+        connection.send(&NetMessage::Hello {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        })?;
+        Ok(connection)
+    }
+    fn send(&mut self, message: &NetMessage) -> Result<(), NetError> {
+        let mut line = ron::to_string(message)?;
+        line.push('\n');
+        self.stream.write_all(line.as_bytes())?;
+        Ok(())
+    }
+    /// The next message that has arrived, if any, never blocks.
+    fn recv(&self) -> Result<Option<NetMessage>, NetError> {
+        match self.incoming.lock().unwrap().try_recv() {
+            Ok(Ok(NetMessage::Hello { version })) if version != env!("CARGO_PKG_VERSION") => {
+                Err(NetError::VersionMismatch(version))
+            }
+            Ok(message) => message.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(NetError::Disconnected),
+        }
+    }
+}
+
+/// The LAN menu's side of the connection, before the match starts.
+#[derive(Resource, Default)]
+pub enum LanLobby {
+    #[default]
+    Idle,
+    /// Waiting for a player to join, then for the host to start.
+    Hosting {
+        listener: TcpListener,
+        guest: Option<Connection>,
+    },
+    /// Waiting for the host to start.
+    Joined(Connection),
+}
+pub enum LobbyEvent {
+    /// A player joined the host, playing `civilisation`.
+    Joined { civilisation: String },
+    /// The host started the match, the joining player plays `seat`.
+    Started { save: Box<SaveFile>, seat: PlayerId },
+}
+impl LanLobby {
+    pub fn host(address: &str) -> Result<Self, NetError> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(LanLobby::Hosting {
+            listener,
+            guest: None,
+        })
+    }
+    pub fn join(address: &str, civilisation: String) -> Result<Self, NetError> {
+        let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "address did not resolve")
+        })?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        let mut connection = Connection::new(stream)?;
+        connection.send(&NetMessage::Join { civilisation })?;
+        Ok(LanLobby::Joined(connection))
+    }
+    /// Accepts a joining player and reads whatever has arrived, called every frame.
+    pub fn update(&mut self) -> Result<Option<LobbyEvent>, NetError> {
+        let connection = match self {
+            LanLobby::Idle => return Ok(None),
+            LanLobby::Hosting { listener, guest } => {
+                if guest.is_none() {
+                    match listener.accept() {
+                        Ok((stream, address)) => {
+                            info!("Player joined from {address}");
+                            *guest = Some(Connection::new(stream)?);
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                guest.as_ref().unwrap()
+            }
+            LanLobby::Joined(connection) => &*connection,
+        };
+        while let Some(message) = connection.recv()? {
+            match message {
+                NetMessage::Hello { .. } => {}
+                NetMessage::Join { civilisation } => {
+                    return Ok(Some(LobbyEvent::Joined { civilisation }));
+                }
+                NetMessage::Start { save, seat } => {
+                    return Ok(Some(LobbyEvent::Started { save, seat }));
+                }
+                _ => warn!("Ignored an unexpected message in the lobby"),
+            }
+        }
+        Ok(None)
+    }
+    /// Ends the lobby, handing over the connection to the other player if there is one.
+    pub fn into_connection(self) -> Option<Connection> {
+        match self {
+            LanLobby::Idle => None,
+            LanLobby::Hosting { guest, .. } => guest,
+            LanLobby::Joined(connection) => Some(connection),
+        }
+    }
+}
+
+/// A LAN match in progress.
+#[derive(Resource)]
+pub struct Session {
+    connection: Connection,
+    /// On the host, the seat the joining player plays until the match has been sent to them.
+    start_seat: Option<PlayerId>,
+    /// Hashes of our match waiting for the other player's, and theirs waiting for ours.
+    hashes: BTreeMap<(u32, PlayerId), u64>,
+    peer_hashes: BTreeMap<(u32, PlayerId), u64>,
+}
+impl Session {
+    /// A session for the host, who still has to send the match to the player in `seat`.
+    pub fn host(connection: Connection, seat: PlayerId) -> Self {
+        Self {
+            start_seat: Some(seat),
+            ..Self::join(connection)
+        }
+    }
+    pub fn join(connection: Connection) -> Self {
+        Self {
+            connection,
+            start_seat: None,
+            hashes: BTreeMap::new(),
+            peer_hashes: BTreeMap::new(),
+        }
+    }
+    /// Called after each command is applied, sends the ones issued here and hashes the match
+    /// whenever a turn ends.
+    pub fn applied(
+        &mut self,
+        command: &Command,
+        local: bool,
+        game: &Match,
+    ) -> Result<(), NetError> {
+        if local {
+            self.connection
+                .send(&NetMessage::Command(command.clone()))?;
+        }
+        if *command != Command::EndTurn {
+            return Ok(());
+        }
+        let (turn, player, hash) = (game.turn(), game.active_player(), state_hash(game)?);
+        self.connection
+            .send(&NetMessage::TurnHash { turn, player, hash })?;
+        match self.peer_hashes.remove(&(turn, player)) {
+            Some(theirs) if theirs != hash => Err(NetError::Desync(turn)),
+            Some(_) => Ok(()),
+            None => {
+                self.hashes.insert((turn, player), hash);
+                Ok(())
+            }
+        }
+    }
+    fn compare(&mut self, turn: u32, player: PlayerId, theirs: u64) -> Result<(), NetError> {
+        match self.hashes.remove(&(turn, player)) {
+            Some(ours) if ours != theirs => Err(NetError::Desync(turn)),
+            Some(_) => Ok(()),
+            None => {
+                self.peer_hashes.insert((turn, player), theirs);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Loads the match the host sent, with every seat but `seat` played over `connection`.
+pub fn load_match(
+    commands: &mut Commands,
+    save: SaveFile,
+    seat: PlayerId,
+    civs: &Assets<Civilisation>,
+    random: &mut Random<RandomRng>,
+    connection: Connection,
+) -> Result<(), NetError> {
+    let mut game_state = GameState::from_save(&save, civs)?;
+    for player in game_state.players.values_mut() {
+        player.remote = player.id != seat;
+    }
+    save::begin_load(commands, save, game_state, random);
+    commands.insert_resource(Session::join(connection));
+    Ok(())
+}
+
+/// A hash of everything in the match, the same on any machine and build that writes the match
+/// out the same way.
+pub fn state_hash(game: &Match) -> Result<u64, ron::Error> {
+    let hash = blake3::hash(ron::to_string(game)?.as_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&hash.as_bytes()[..8]);
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Reads the other player's commands and hashes, playing on here if the connection drops.
+pub fn receive_commands(
+    mut commands: Commands,
+    session: Option<ResMut<Session>>,
+    mut remote_commands: MessageWriter<RemoteCommand>,
+    mut game_state: ResMut<GameState>,
+) {
+    let Some(mut session) = session else {
+        return;
+    };
+    loop {
+        let result = match session.connection.recv() {
+            Ok(Some(NetMessage::Command(command))) => {
+                remote_commands.write(RemoteCommand(command));
+                Ok(())
+            }
+            Ok(Some(NetMessage::TurnHash { turn, player, hash })) => {
+                session.compare(turn, player, hash)
+            }
+            Ok(Some(_)) => {
+                warn!("Ignored an unexpected message during the match");
+                Ok(())
+            }
+            Ok(None) => break,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => {}
+            Err(e @ NetError::Desync(_)) => report(&mut game_state, &e),
+            Err(e) => {
+                report(&mut game_state, &e);
+                for player in game_state.players.values_mut() {
+                    player.remote = false;
+                }
+                let viewing_player = game_state.viewing_player();
+                game_state
+                    .players
+                    .get_mut(&viewing_player)
+                    .unwrap()
+                    .add_notification("Every player is now played here".to_string());
+                commands.remove_resource::<Session>();
+                break;
+            }
+        }
+    }
+}
+
+/// Logs a problem with the session and tells whoever is playing here.
+pub fn report(game_state: &mut GameState, error: &NetError) {
+    error!("{error}");
+    let viewing_player = game_state.viewing_player();
+    game_state
+        .players
+        .get_mut(&viewing_player)
+        .unwrap()
+        .add_notification(error.to_string());
+}
+
+/// Sends the freshly set up match to the joining player.
+fn send_start(
+    mut session: ResMut<Session>,
+    game_state: Res<GameState>,
+    random: Res<Random<RandomRng>>,
+    world_rng: Res<WorldGenerationRng>,
+    world_map: Res<WorldMap>,
+    cameras: Query<&RtsCamera>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(seat) = session.start_seat.take() else {
+        return;
+    };
+    let save = save::save_file(&game_state, &random, &world_rng, &world_map, &cameras, true);
+    let start = NetMessage::Start {
+        save: Box::new(save),
+        seat,
+    };
+    if let Err(e) = session.connection.send(&start) {
+        error!("Failed to start the LAN match: {e}");
+        next_state.set(AppState::Menu);
+    }
+}
+
+fn leave_session(mut commands: Commands) {
+    commands.remove_resource::<Session>();
+}
@@ -3,10 +3,19 @@ use std::collections::HashMap;
 use bevy::ecs::resource::Resource;
 pub use pathfinding::*;
 use petgraph::{Graph, graph::NodeIndex};
-use world_generation::CellId;
+use world_generation::{CellId, WorldMap};
 
 #[derive(Clone, Resource, Default)]
 pub struct PathFinding {
     pub graph: Graph<CellId, f32>,
     pub nodes: HashMap<CellId, NodeIndex>,
 }
+impl PathFinding {
+    pub fn board<'a>(&'a self, world_map: &'a WorldMap) -> game_core::Board<'a> {
+        game_core::Board {
+            world_map,
+            graph: &self.graph,
+            nodes: &self.nodes,
+        }
+    }
+}
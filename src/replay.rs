@@ -0,0 +1,323 @@
+//! Command logs and replays.
+//!
+//! Every command applied to the match is logged after a snapshot of the match as it started, so
+//! the whole match can be written out and re-simulated with `--replay`.
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, EguiPrimaryContextPass, egui};
+use bevy_rts_camera::RtsCamera;
+use game_core::{Command, Match};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState, Civilisation, GameEvent, GameState, Random, RandomRng,
+    generate::{WorldGenerationRng, WorldMap},
+    net::{self, RemoteCommand},
+    save::{self, SAVE_VERSION, SaveError, SaveFile},
+};
+
+const REPLAY_EXTENSION: &str = "replay.ron";
+
+pub struct ReplayPlugin;
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CommandLog>();
+        app.add_message::<SaveReplay>();
+        app.add_systems(
+            OnEnter(AppState::Menu),
+            start_replay.run_if(resource_exists::<ReplayPath>),
+        );
+        app.add_systems(OnEnter(AppState::InGame), start_log.after(crate::startup));
+        app.add_systems(Update, save_replay.run_if(in_state(AppState::InGame)));
+        app.add_systems(
+            EguiPrimaryContextPass,
+            replay_controls.run_if(in_state(AppState::InGame).and(resource_exists::<Replay>)),
+        );
+        app.add_systems(OnExit(AppState::InGame), leave_replay);
+    }
+}
+
+/// Requests the match so far be written to a new replay file.
+#[derive(Message)]
+pub struct SaveReplay;
+
+/// The replay file passed with `--replay`, taken once it's been started.
+#[derive(Resource)]
+pub struct ReplayPath(pub Option<PathBuf>);
+
+#[derive(Serialize, Deserialize)]
+pub struct ReplayFile {
+    /// The match before the first command, the seed and world params included.
+    pub start: SaveFile,
+    pub commands: Vec<Command>,
+    /// [`net::state_hash`] of the match after each turn ended, to tell where a replay stops
+    /// matching the recording. The hash doesn't depend on the toolchain, so a recording can be
+    /// checked on another machine or a later build.
+    #[serde(default)]
+    pub hashes: Vec<u64>,
+}
+
+/// Everything that happened in the current match so far.
+#[derive(Resource, Default)]
+pub struct CommandLog {
+    start: Option<SaveFile>,
+    commands: Vec<Command>,
+    hashes: Vec<u64>,
+}
+impl CommandLog {
+    /// Logs a command that has just been applied to `game`.
+    pub fn record(&mut self, command: &Command, game: &Match) -> Result<(), ron::Error> {
+        self.commands.push(command.clone());
+        if *command == Command::EndTurn {
+            self.hashes.push(net::state_hash(game)?);
+        }
+        Ok(())
+    }
+}
+
+/// A replay being played back, every player is [`remote`](crate::Player::remote) meanwhile.
+#[derive(Resource)]
+pub struct Replay {
+    commands: VecDeque<Command>,
+    hashes: Vec<u64>,
+    /// Turns ended so far whose hashes have been checked.
+    checked: usize,
+    playing: bool,
+    /// Pause again once the current turn has been played.
+    until_turn_end: bool,
+    /// The turn the match stopped matching the recording on.
+    diverged: Option<u32>,
+}
+
+pub fn replays_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap()
+        .join(env!("CARGO_PKG_NAME"))
+        .join("replays")
+}
+
+pub fn write_replay(path: &Path, replay: &ReplayFile) -> Result<(), SaveError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let contents = ron::ser::to_string_pretty(replay, ron::ser::PrettyConfig::default())?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+pub fn read_replay(path: &Path) -> Result<ReplayFile, SaveError> {
+    let bytes = fs::read(path)?;
+    let replay = ron::de::from_bytes::<ReplayFile>(&bytes)?;
+    if replay.start.version != SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(replay.start.version));
+    }
+    Ok(replay)
+}
+
+/// Loads the replay passed with `--replay`, with every player played from the file.
+fn start_replay(
+    mut commands: Commands,
+    mut path: ResMut<ReplayPath>,
+    civs: Res<Assets<Civilisation>>,
+    mut random: ResMut<Random<RandomRng>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(path) = path.0.take() else {
+        return;
+    };
+    let replay = match read_replay(&path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            error!("Failed to read replay {}: {e}", path.display());
+            return;
+        }
+    };
+    let mut game_state = match GameState::from_save(&replay.start, civs.as_ref()) {
+        Ok(game_state) => game_state,
+        Err(e) => {
+            error!("Failed to load replay {}: {e}", path.display());
+            return;
+        }
+    };
+    for player in game_state.players.values_mut() {
+        player.remote = true;
+    }
+    info!("Playing replay: {}", path.display());
+    commands.insert_resource(Replay {
+        commands: replay.commands.into(),
+        hashes: replay.hashes,
+        checked: 0,
+        playing: false,
+        until_turn_end: false,
+        diverged: None,
+    });
+    save::begin_load(&mut commands, replay.start, game_state, random.as_mut());
+    next_state.set(AppState::Generating);
+}
+
+/// Snapshots the match as it starts, the log builds on it from here.
+fn start_log(
+    mut log: ResMut<CommandLog>,
+    game_state: Res<GameState>,
+    random: Res<Random<RandomRng>>,
+    world_rng: Res<WorldGenerationRng>,
+    world_map: Res<WorldMap>,
+    cameras: Query<&RtsCamera>,
+) {
+    *log = CommandLog {
+        start: Some(save::save_file(
+            &game_state,
+            &random,
+            &world_rng,
+            &world_map,
+            &cameras,
+            true,
+        )),
+        ..default()
+    };
+}
+
+/// Writes the log out when asked to, and once the match has been won.
+fn save_replay(
+    mut requests: MessageReader<SaveReplay>,
+    mut events: MessageReader<GameEvent>,
+    log: Res<CommandLog>,
+    replay: Option<Res<Replay>>,
+    mut game_state: ResMut<GameState>,
+) {
+    let requested = requests.read().count() > 0;
+    // A replay played to the end doesn't need writing out again.
+    let won = events
+        .read()
+        .any(|e| matches!(**e, game_core::Event::MatchWon { .. }))
+        && replay.is_none();
+    if !requested && !won {
+        return;
+    }
+    let Some(start) = log.start.as_ref() else {
+        return;
+    };
+    let replay = ReplayFile {
+        start: start.clone(),
+        commands: log.commands.clone(),
+        hashes: log.hashes.clone(),
+    };
+    let now = chrono::Local::now();
+    let path = replays_dir().join(format!(
+        "{}.{REPLAY_EXTENSION}",
+        now.format("%Y-%m-%d_%H-%M-%S")
+    ));
+    let viewing_player = game_state.viewing_player();
+    let player = game_state.players.get_mut(&viewing_player).unwrap();
+    match write_replay(&path, &replay) {
+        Ok(()) => {
+            info!("Saved replay to {}", path.display());
+            player.add_notification("Replay saved".to_string());
+        }
+        Err(e) => {
+            error!("Failed to save replay: {e}");
+            player.add_notification(format!("Failed to save replay: {e}"));
+        }
+    }
+}
+
+/// Feeds the replay's commands to the match, one a frame while playing, and checks each ended
+/// turn against the recording.
+pub fn play_replay(
+    replay: Option<ResMut<Replay>>,
+    log: Res<CommandLog>,
+    mut remote_commands: MessageWriter<RemoteCommand>,
+    mut game_state: ResMut<GameState>,
+) {
+    let Some(mut replay) = replay else {
+        return;
+    };
+    while replay.checked < log.hashes.len() {
+        let turn = replay.checked;
+        replay.checked += 1;
+        let matches = replay
+            .hashes
+            .get(turn)
+            .is_none_or(|h| *h == log.hashes[turn]);
+        if !matches && replay.diverged.is_none() {
+            let on_turn = game_state.game.turn();
+            replay.diverged = Some(on_turn);
+            warn!("Replay stopped matching the recording on turn {on_turn}");
+            let viewing_player = game_state.viewing_player();
+            game_state
+                .players
+                .get_mut(&viewing_player)
+                .unwrap()
+                .add_notification(format!(
+                    "The replay stopped matching the recording on turn {on_turn}"
+                ));
+        }
+    }
+    if !replay.playing {
+        return;
+    }
+    let Some(command) = replay.commands.pop_front() else {
+        replay.playing = false;
+        return;
+    };
+    if replay.until_turn_end && command == Command::EndTurn {
+        replay.playing = false;
+        replay.until_turn_end = false;
+    }
+    remote_commands.write(RemoteCommand(command));
+}
+
+/// Play, pause and step through a replay a turn at a time.
+fn replay_controls(
+    mut contexts: EguiContexts,
+    mut replay: ResMut<Replay>,
+    game_state: Res<GameState>,
+) -> Result {
+    let ctx = contexts.ctx_mut()?;
+    egui::Window::new("Replay")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8.0, 8.0))
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "Turn {}, {} commands left",
+                game_state.game.turn(),
+                replay.commands.len()
+            ));
+            if let Some(turn) = replay.diverged {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!("Stopped matching the recording on turn {turn}"),
+                );
+            }
+            ui.horizontal(|ui| {
+                let finished = replay.commands.is_empty();
+                let label = if replay.playing { "Pause" } else { "Play" };
+                if ui
+                    .add_enabled(!finished, egui::Button::new(label))
+                    .clicked()
+                {
+                    replay.playing = !replay.playing;
+                    replay.until_turn_end = false;
+                }
+                if ui
+                    .add_enabled(!finished, egui::Button::new("Next Turn"))
+                    .clicked()
+                {
+                    replay.playing = true;
+                    replay.until_turn_end = true;
+                }
+            });
+        });
+    Ok(())
+}
+
+fn leave_replay(mut commands: Commands, mut log: ResMut<CommandLog>) {
+    commands.remove_resource::<Replay>();
+    *log = CommandLog::default();
+}
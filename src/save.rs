@@ -0,0 +1,395 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{pbr::ScatteringMedium, prelude::*};
+use bevy_egui::EguiContexts;
+use bevy_rts_camera::RtsCamera;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    AppState, Civilisation, GameState, Player, PlayerId, Random, RandomRng, SelectedId,
+    generate::{WorldGenerationParams, WorldGenerationRng, WorldMap},
+};
+
+/// Bumped whenever [`SaveFile`] changes in a way older saves can't be read.
+pub const SAVE_VERSION: u32 = 4;
+const SAVE_EXTENSION: &str = "save.ron";
+
+pub struct SavePlugin;
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<SaveGame>();
+        app.add_systems(
+            Update,
+            (quick_save, save_game)
+                .chain()
+                .run_if(in_state(AppState::InGame)),
+        );
+        app.add_systems(
+            OnEnter(AppState::InGame),
+            spawn_saved_match.run_if(resource_exists::<PendingLoad>),
+        );
+    }
+}
+
+/// Requests the current match be written to a new save file.
+#[derive(Message)]
+pub struct SaveGame {
+    /// Store only the world params and a checksum, regenerating the map on load.
+    pub compact: bool,
+}
+
+/// A save that has been read from disk and is waiting for its world to be generated.
+#[derive(Resource)]
+pub struct PendingLoad(pub SaveFile);
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum SaveError {
+    /// An [IO](std::io) Error
+    #[error("Could not access save: {0}")]
+    Io(#[from] std::io::Error),
+    /// A [RON](ron) serialisation Error
+    #[error("Could not write RON: {0}")]
+    Ron(#[from] ron::Error),
+    /// A [RON](ron) parse Error
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+    #[error("Save version {0} is not supported (expected {SAVE_VERSION})")]
+    UnsupportedVersion(u32),
+    #[error("Civilisation {0} is not installed")]
+    MissingCivilisation(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct RngState {
+    seed: [u8; 32],
+    stream: u64,
+    word_pos: u128,
+}
+impl From<&RandomRng> for RngState {
+    fn from(rng: &RandomRng) -> Self {
+        Self {
+            seed: rng.get_seed(),
+            stream: rng.get_stream(),
+            word_pos: rng.get_word_pos(),
+        }
+    }
+}
+impl From<RngState> for RandomRng {
+    fn from(state: RngState) -> Self {
+        use rand::SeedableRng;
+        let mut rng = RandomRng::from_seed(state.seed);
+        rng.set_stream(state.stream);
+        rng.set_word_pos(state.word_pos);
+        rng
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SaveFile {
+    pub version: u32,
+    pub saved_at: String,
+    /// Base 36 seed, in the same form `--seed` accepts.
+    pub seed: String,
+    pub rng: RngState,
+    pub world_rng: RngState,
+    pub world: world_generation::StoredWorld,
+    pub game: game_core::Match,
+    pub players: Vec<PlayerSave>,
+}
+
+/// The parts of a [`Player`] that only matter for presentation.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlayerSave {
+    pub id: PlayerId,
+    pub order: usize,
+    pub civilisation: String,
+    pub settlement_names: Vec<String>,
+    pub unit_spawn_barks: HashMap<String, Vec<String>>,
+    pub notifications: Vec<String>,
+    pub ai: Option<game_core::ai::Ai>,
+    #[serde(default)]
+    pub pin: Option<crate::handover::Pin>,
+    #[serde(default)]
+    pub selection: Option<SelectedId>,
+    #[serde(default)]
+    pub camera: Option<CameraSave>,
+}
+
+/// Where a player's camera was looking.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct CameraSave {
+    pub focus: [f32; 3],
+    pub zoom: f32,
+}
+
+pub fn saves_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap()
+        .join(env!("CARGO_PKG_NAME"))
+        .join("saves")
+}
+
+pub fn write_save(path: &Path, save: &SaveFile) -> Result<(), SaveError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let contents = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())?;
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+pub fn read_save(path: &Path) -> Result<SaveFile, SaveError> {
+    let bytes = fs::read(path)?;
+    let save = ron::de::from_bytes::<SaveFile>(&bytes)?;
+    if save.version != SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(save.version));
+    }
+    Ok(save)
+}
+
+/// Lists save files, newest first.
+pub fn list_saves() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(saves_dir()) else {
+        return vec![];
+    };
+    let mut saves = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.ends_with(SAVE_EXTENSION))
+        })
+        .collect::<Vec<_>>();
+    saves.sort();
+    saves.reverse();
+    saves
+}
+
+impl GameState {
+    pub(crate) fn from_save(
+        save: &SaveFile,
+        civs: &Assets<Civilisation>,
+    ) -> Result<Self, SaveError> {
+        let mut players = HashMap::with_capacity(save.players.len());
+        for saved in save.players.iter() {
+            let civ = civs
+                .iter()
+                .find(|(_, c)| c.name == saved.civilisation)
+                .map(|(_, c)| c.clone())
+                .ok_or_else(|| SaveError::MissingCivilisation(saved.civilisation.clone()))?;
+            let mut player = Player::new(saved.order, save.players.len(), civ);
+            player.id = saved.id;
+            player.settlement_names = saved.settlement_names.clone();
+            player.unit_spawn_barks = saved.unit_spawn_barks.clone();
+            player.ai = saved.ai.clone();
+            player.pin = saved.pin.clone();
+            player.selection = saved.selection;
+            for message in saved.notifications.iter() {
+                player.add_notification(message.clone());
+            }
+            players.insert(player.id, player);
+        }
+        Ok(Self {
+            players,
+            game: save.game.clone(),
+        })
+    }
+}
+
+/// Reads a save from disk and sets up the resources needed to regenerate its world.
+pub fn load_game(
+    commands: &mut Commands,
+    path: &Path,
+    civs: &Assets<Civilisation>,
+    random: &mut Random<RandomRng>,
+) -> Result<(), SaveError> {
+    let save = read_save(path)?;
+    let game_state = GameState::from_save(&save, civs)?;
+    info!("Loading save: {}", path.display());
+    begin_load(commands, save, game_state, random);
+    Ok(())
+}
+
+/// Sets up the resources needed to regenerate the world of `save` and play on from it.
+pub fn begin_load(
+    commands: &mut Commands,
+    save: SaveFile,
+    game_state: GameState,
+    random: &mut Random<RandomRng>,
+) {
+    random.0 = Some(save.rng.into());
+    commands.insert_resource(WorldGenerationParams(Some(save.world.header().params)));
+    commands.insert_resource(WorldGenerationRng(save.world_rng.into()));
+    commands.insert_resource(game_state);
+    commands.insert_resource(PendingLoad(save));
+}
+
+fn quick_save(input: Res<ButtonInput<KeyCode>>, mut save_game: MessageWriter<SaveGame>) {
+    if input.just_pressed(KeyCode::F5) {
+        save_game.write(SaveGame { compact: true });
+    }
+}
+
+fn save_game(
+    mut requests: MessageReader<SaveGame>,
+    mut game_state: ResMut<GameState>,
+    random: Res<Random<RandomRng>>,
+    world_rng: Res<WorldGenerationRng>,
+    world_map: Res<WorldMap>,
+    cameras: Query<&RtsCamera>,
+) {
+    // Several requests in one frame collapse into one save, a full one wins.
+    let Some(compact) = requests.read().map(|r| r.compact).reduce(|a, b| a && b) else {
+        return;
+    };
+    let now = chrono::Local::now();
+    let save = save_file(
+        &game_state,
+        &random,
+        &world_rng,
+        &world_map,
+        &cameras,
+        compact,
+    );
+    let path = saves_dir().join(format!(
+        "{}.{SAVE_EXTENSION}",
+        now.format("%Y-%m-%d_%H-%M-%S")
+    ));
+    let active_player = game_state.active_player();
+    let player = game_state.players.get_mut(&active_player).unwrap();
+    match write_save(&path, &save) {
+        Ok(()) => {
+            info!("Saved game to {}", path.display());
+            player.add_notification("Game saved".to_string());
+        }
+        Err(e) => {
+            error!("Failed to save game: {e}");
+            player.add_notification(format!("Failed to save game: {e}"));
+        }
+    }
+}
+
+/// Everything needed to pick the current match up again.
+pub fn save_file(
+    game_state: &GameState,
+    random: &Random<RandomRng>,
+    world_rng: &WorldGenerationRng,
+    world_map: &WorldMap,
+    cameras: &Query<&RtsCamera>,
+    compact: bool,
+) -> SaveFile {
+    let rng = random.0.as_ref().unwrap();
+    let seed = num::BigUint::from_bytes_le(&rng.get_seed()).to_str_radix(36);
+    SaveFile {
+        version: SAVE_VERSION,
+        saved_at: chrono::Local::now().to_rfc3339(),
+        seed,
+        rng: rng.into(),
+        world_rng: (&world_rng.0).into(),
+        world: world_map.to_stored(compact),
+        game: game_state.game.clone(),
+        players: game_state
+            .players
+            .values()
+            .map(|p| PlayerSave {
+                id: p.id,
+                order: p.order,
+                civilisation: p.civ.name.clone(),
+                settlement_names: p.settlement_names.clone(),
+                unit_spawn_barks: p.unit_spawn_barks.clone(),
+                notifications: p.notifications.iter().map(|n| n.message.clone()).collect(),
+                ai: p.ai.clone(),
+                pin: p.pin.clone(),
+                selection: p.selection,
+                camera: p
+                    .camera_entity
+                    .and_then(|e| cameras.get(e).ok())
+                    .map(|c| CameraSave {
+                        focus: c.target_focus.translation.to_array(),
+                        zoom: c.target_zoom,
+                    }),
+            })
+            .collect(),
+    }
+}
+
+fn spawn_saved_match(
+    mut commands: Commands,
+    pending: Res<PendingLoad>,
+    world_map: Res<WorldMap>,
+    mut game_state: ResMut<GameState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    mut contexts: EguiContexts,
+    mut scattering_mediums: ResMut<Assets<ScatteringMedium>>,
+) {
+    let viewing_player = game_state.viewing_player();
+    let GameState { players, game } = game_state.as_mut();
+    let (min, max) = world_map.bounds();
+    let centre = ((min + max) * 0.5).extend(0.0).xzy();
+    for player in players.values_mut() {
+        player.material = materials.add(player.color);
+        let camera = pending
+            .0
+            .players
+            .iter()
+            .find(|p| p.id == player.id)
+            .and_then(|p| p.camera);
+        let focus = game
+            .settlements()
+            .find(|s| s.controller == player.id)
+            .map(|s| s.cell)
+            .or_else(|| {
+                game.units()
+                    .find(|u| u.controller == player.id)
+                    .map(|u| u.cell)
+            })
+            .map(|c| world_map.get_position_for_cell(c))
+            .unwrap_or(centre);
+        player.camera_entity = Some(crate::spawn_player_camera(
+            &mut commands,
+            &mut scattering_mediums,
+            &world_map,
+            player,
+            camera.map_or(focus, |c| Vec3::from_array(c.focus)),
+            camera.map_or(crate::DEFAULT_ZOOM, |c| c.zoom),
+            player.id == viewing_player,
+        ));
+        for settlement in game.settlements().filter(|s| s.controller == player.id) {
+            crate::spawn_settlement(&mut commands, &mut meshes, &world_map, settlement, player);
+            for (cell, improvement) in settlement.improvements.iter() {
+                crate::spawn_improvement(
+                    &mut commands,
+                    &mut meshes,
+                    asset_server.as_ref(),
+                    &world_map,
+                    crate::ImprovementMarker {
+                        settlement: settlement.id,
+                        cell: *cell,
+                    },
+                    game.improvement(improvement),
+                    player,
+                );
+            }
+        }
+        for unit in game.units().filter(|u| u.controller == player.id) {
+            crate::spawn_unit(
+                &mut commands,
+                &world_map,
+                unit,
+                player,
+                asset_server.as_ref(),
+                &mut contexts,
+            );
+        }
+    }
+    commands.remove_resource::<PendingLoad>();
+}
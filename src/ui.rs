@@ -8,16 +8,27 @@ use bevy_egui::{
     PrimaryEguiContext, egui,
 };
 
-use crate::{AppState, GameState, Selection, SettlementCenter, TurnStart, Unit, minimap};
+use bevy_rts_camera::RtsCamera;
+
+use crate::{
+    AppState, CellHighlight, GameCommand, GameState, Selection, SettlementCenter, Unit, minimap,
+    replay, save,
+};
 
 pub struct UIPlugin;
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(EguiPlugin::default());
+        app.init_resource::<ResearchScreen>();
         app.add_systems(Startup, setup_ui_camera);
         app.add_systems(
             EguiPrimaryContextPass,
-            ui_example_system.run_if(in_state(AppState::InGame)),
+            (
+                (ui_example_system, turn_markers, research_screen)
+                    .run_if(crate::handover::not_handing_over),
+                game_over_screen,
+            )
+                .run_if(in_state(AppState::InGame)),
         );
     }
 }
@@ -42,6 +53,175 @@ fn setup_ui_camera(mut commands: Commands, mut egui_global_settings: ResMut<Egui
     ));
 }
 
+/// Whether the research screen is open.
+#[derive(Resource, Default)]
+struct ResearchScreen(bool);
+
+/// Shown over the map once the match has a winner.
+fn game_over_screen(
+    mut contexts: EguiContexts,
+    game_state: Res<GameState>,
+    mut next_state: ResMut<NextState<AppState>>,
+) -> Result {
+    let Some(outcome) = game_state.game.outcome() else {
+        return Ok(());
+    };
+    let ctx = contexts.ctx_mut()?;
+    let winner = &game_state.players[&outcome.winner];
+    let mut players = game_state.players.values().collect::<Vec<_>>();
+    players.sort_by_key(|p| p.order);
+    egui::Window::new("Game Over")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            ui.heading(format!(
+                "The {} win by {} on turn {}",
+                winner.civ.name,
+                outcome.victory.condition().name(),
+                outcome.turn
+            ));
+            ui.separator();
+            egui::Grid::new("match_stats").striped(true).show(ui, |ui| {
+                for heading in ["", "Settlements", "Cells", "Units", "Techs", "Score"] {
+                    ui.strong(heading);
+                }
+                ui.end_row();
+                for player in players {
+                    let stats = game_state.game.stats(player.id);
+                    ui.label(&player.civ.name);
+                    for value in [
+                        stats.settlements,
+                        stats.cells,
+                        stats.units,
+                        stats.techs,
+                        stats.score(),
+                    ] {
+                        ui.label(value.to_string());
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            if ui.button("Return to Menu").clicked() {
+                next_state.set(AppState::Menu);
+            }
+        });
+    Ok(())
+}
+/// Lists every tech in the match for the viewing player, letting them pick what to research.
+fn research_screen(
+    mut contexts: EguiContexts,
+    mut open: ResMut<ResearchScreen>,
+    game_state: Res<GameState>,
+    mut game_commands: MessageWriter<GameCommand>,
+) -> Result {
+    if !open.0 {
+        return Ok(());
+    }
+    let ctx = contexts.ctx_mut()?;
+    let Some(player) = game_state.game.player(game_state.viewing_player()) else {
+        return Ok(());
+    };
+    let research = &player.research;
+    let tech_tree = game_state.game.tech_tree();
+    let can_choose = game_state.active_player_is_human();
+    egui::Window::new("Research")
+        .open(&mut open.0)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            match research.current.as_ref().and_then(|t| tech_tree.get(t)) {
+                Some(tech) => ui.label(format!(
+                    "Researching {}: {:.0}/{:.0}",
+                    tech.name, research.progress, tech.cost
+                )),
+                None => ui.label("Nothing is being researched"),
+            };
+            ui.separator();
+            ScrollArea::vertical().show(ui, |ui| {
+                for tech in tech_tree.techs.iter() {
+                    ui.group(|ui| {
+                        ui.horizontal(|ui| {
+                            ui.strong(&tech.name);
+                            if research.has(&tech.id) {
+                                ui.label("Researched");
+                            } else if research.current.as_ref() == Some(&tech.id) {
+                                ui.label("In progress");
+                            } else {
+                                let available =
+                                    tech_tree.available(research).any(|t| t.id == tech.id);
+                                let button =
+                                    egui::Button::new(format!("Research ({:.0})", tech.cost));
+                                if ui.add_enabled(available && can_choose, button).clicked() {
+                                    game_commands.write(GameCommand(
+                                        game_core::Command::SetResearch {
+                                            tech: tech.id.clone(),
+                                        },
+                                    ));
+                                }
+                            }
+                        });
+                        if !tech.prerequisites.is_empty() {
+                            let names = tech
+                                .prerequisites
+                                .iter()
+                                .map(|p| tech_tree.get(p).map_or(p.as_str(), |t| &t.name))
+                                .collect::<Vec<_>>();
+                            ui.label(format!("Needs {}", names.join(", ")));
+                        }
+                        let units = player
+                            .unit_types
+                            .iter()
+                            .filter(|u| u.requires.as_ref() == Some(&tech.id))
+                            .map(|u| u.name.clone());
+                        let unlocks = tech.unlocks.iter().map(|u| match u {
+                            game_core::Unlock::ProductionBonus(bonus) => {
+                                format!("+{bonus} production")
+                            }
+                            game_core::Unlock::Building(building) => building.clone(),
+                        });
+                        let unlocks = units.chain(unlocks).collect::<Vec<_>>();
+                        if !unlocks.is_empty() {
+                            ui.label(format!("Unlocks {}", unlocks.join(", ")));
+                        }
+                        ui.label(&tech.description);
+                    });
+                }
+            });
+        });
+    Ok(())
+}
+
+/// Labels the last cell of each turn along a previewed move with the number of turns to get there.
+fn turn_markers(
+    mut contexts: EguiContexts,
+    cameras: Query<(&Camera, &GlobalTransform), With<RtsCamera>>,
+    highlights: Query<(Entity, &CellHighlight, &GlobalTransform)>,
+) -> Result {
+    let Some((camera, camera_transform)) = cameras.iter().find(|(c, _)| c.is_active) else {
+        return Ok(());
+    };
+    let ctx = contexts.ctx_mut()?;
+    for (entity, highlight, transform) in highlights.iter() {
+        let Some(turn) = highlight.turn else {
+            continue;
+        };
+        let Ok(pos) = camera.world_to_viewport(camera_transform, transform.translation()) else {
+            continue;
+        };
+        egui::Area::new(egui::Id::new(("turn_marker", entity)))
+            .fixed_pos(egui::pos2(pos.x, pos.y))
+            .pivot(egui::Align2::CENTER_CENTER)
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(turn.to_string());
+                });
+            });
+    }
+    Ok(())
+}
+
 // This function runs every frame. Therefore, updating the viewport after drawing the gui.
 // With a resource which stores the dimensions of the panels, the update of the Viewport can
 // be done in another system.
@@ -51,11 +231,16 @@ fn ui_example_system(
     window: Single<&mut Window, With<PrimaryWindow>>,
     mut game_state: ResMut<GameState>,
     selected: Res<Selection>,
-    mut settlements: Query<&mut SettlementCenter>,
-    mut units: Query<&mut Unit>,
-    mut turn_start: MessageWriter<TurnStart>,
+    settlements: Query<&SettlementCenter>,
+    units: Query<&Unit>,
+    mut game_commands: MessageWriter<GameCommand>,
+    mut save_game: MessageWriter<save::SaveGame>,
+    mut save_replay: MessageWriter<replay::SaveReplay>,
     minimap: Res<minimap::MinimapImage>,
     time: Res<Time>,
+    world_map: Res<crate::generate::WorldMap>,
+    pathfinding: Res<crate::pathfinding::PathFinding>,
+    mut research_screen: ResMut<ResearchScreen>,
 ) -> Result {
     let tex_id = contexts.image_id(&**minimap).unwrap();
     let ctx = contexts.ctx_mut()?;
@@ -76,8 +261,8 @@ fn ui_example_system(
                     ScrollArea::neither().show(ui, |ui| {
                         ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
                             ui.add(image);
-                            let active_player = game_state.active_player;
-                            let player = game_state.players.get_mut(&active_player).unwrap();
+                            let viewing_player = game_state.viewing_player();
+                            let player = game_state.players.get_mut(&viewing_player).unwrap();
                             let mut remove_indices = vec![];
                             for (i, notification) in
                                 player.notifications.iter_mut().enumerate().rev()
@@ -112,23 +297,198 @@ fn ui_example_system(
                         });
                     });
                 });
-            let active_player = game_state.active_player;
-            let player = game_state.players.get_mut(&active_player).unwrap();
+            let viewing_player = game_state.viewing_player();
+            let player = game_state.players.get(&viewing_player).unwrap();
             ui.label(player.civ.name.clone());
+            let materials = game_state
+                .game
+                .player(viewing_player)
+                .map_or(0.0, |p| p.materials);
+            ui.label(format!("Materials: {materials:.1}"));
             match *selected {
                 Selection::None => {}
-                Selection::Unit(_entity) => {}
+                Selection::Unit(entity) => {
+                    if let Ok(unit) = units.get(entity)
+                        && let Some(unit) = game_state.game.unit(unit.id)
+                    {
+                        let board = pathfinding.board(&world_map);
+                        let in_range = board.cells_in_range(unit.cell, unit.range);
+                        for target in game_state
+                            .game
+                            .units()
+                            .filter(|u| u.controller != unit.controller)
+                            .filter(|u| in_range.contains_key(&u.cell))
+                        {
+                            let Ok(odds) = game_core::predict_combat(
+                                &game_state.game,
+                                board,
+                                unit.id,
+                                target.id,
+                            ) else {
+                                continue;
+                            };
+                            let counter = odds.counter_damage.map_or("nothing".to_string(), |c| {
+                                format!("{:.1}-{:.1}", c.start(), c.end())
+                            });
+                            ui.label(format!(
+                                "Attack {}: deals {:.1}-{:.1}, takes {counter}, {:.0}% to destroy",
+                                target.unit_type,
+                                odds.damage.start(),
+                                odds.damage.end(),
+                                odds.kill_chance * 100.0
+                            ));
+                        }
+                        let adjacent = board.cells_in_range(unit.cell, 1);
+                        for target in game_state
+                            .game
+                            .settlements()
+                            .filter(|s| s.controller != unit.controller)
+                            .filter(|s| in_range.contains_key(&s.cell))
+                        {
+                            if !target.breached() {
+                                let Ok(odds) = game_core::predict_siege(
+                                    &game_state.game,
+                                    board,
+                                    unit.id,
+                                    target.id,
+                                ) else {
+                                    continue;
+                                };
+                                let counter =
+                                    odds.counter_damage.map_or("nothing".to_string(), |c| {
+                                        format!("{:.1}-{:.1}", c.start(), c.end())
+                                    });
+                                ui.label(format!(
+                                    "Attack {} ({:.1}/{:.1}): deals {:.1}-{:.1}, takes {counter}",
+                                    target.name,
+                                    target.health(),
+                                    target.max_health(),
+                                    odds.damage.start(),
+                                    odds.damage.end(),
+                                ));
+                                continue;
+                            }
+                            ui.label(format!("The walls of {} are down", target.name));
+                            if unit.controller == viewing_player
+                                && adjacent.contains_key(&target.cell)
+                                && ui
+                                    .add_enabled(
+                                        game_state.active_player_is_human(),
+                                        egui::Button::new(format!("Raze {}", target.name)),
+                                    )
+                                    .on_hover_text("Move in to capture it instead")
+                                    .clicked()
+                            {
+                                game_commands.write(GameCommand(
+                                    game_core::Command::RazeSettlement {
+                                        unit: unit.id,
+                                        settlement: target.id,
+                                    },
+                                ));
+                            }
+                        }
+                        if unit.controller == viewing_player
+                            && unit.can(game_core::Ability::FoundSettlement)
+                        {
+                            let site = game_state.game.check_settlement_site(board, unit.cell);
+                            let unit = unit.id;
+                            let button = ui.add_enabled(
+                                site.is_ok() && game_state.active_player_is_human(),
+                                egui::Button::new("Found Settlement"),
+                            );
+                            let button = match site {
+                                Ok(()) => button,
+                                Err(e) => button.on_disabled_hover_text(e.to_string()),
+                            };
+                            if button.clicked() {
+                                let name = game_state
+                                    .players
+                                    .get_mut(&viewing_player)
+                                    .unwrap()
+                                    .next_settlement_name();
+                                game_commands.write(GameCommand(
+                                    game_core::Command::FoundSettlement { unit, name },
+                                ));
+                            }
+                        }
+                    }
+                }
                 Selection::Settlement(entity) => {
-                    let mut settlement = settlements.get_mut(entity).unwrap();
+                    let id = settlements.get(entity).unwrap().id;
+                    let settlement = game_state.game.settlement(id).unwrap();
                     ui.label(settlement.name.clone());
+                    let yields = game_state
+                        .game
+                        .settlement_yields(pathfinding.board(&world_map), id);
+                    ui.label(format!(
+                        "Food {:+.1}, production {:.1}, materials {:+.1}",
+                        yields.food - settlement.food_upkeep(),
+                        yields.production,
+                        yields.materials
+                    ));
+                    ui.label(format!(
+                        "Growth: {:.1}/{:.1}",
+                        settlement.food,
+                        settlement.growth_food()
+                    ));
+                    ui.label(format!(
+                        "Walls: {:.1}/{:.1}",
+                        settlement.health(),
+                        settlement.max_health()
+                    ));
                     if let Some(ref job) = settlement.construction {
-                        job.progress_label(ui);
+                        ui.label(format!("{}: {}/{}", job.name(), job.progress, job.cost));
                     } else {
                         ui.label("No Construction Queued");
                     }
-                    for job in settlement.available_constructions.clone().iter() {
-                        if job.available_button(ui, true).clicked() {
-                            settlement.construction = Some(job.clone());
+                    for (i, job) in settlement.available_constructions.iter().enumerate() {
+                        let label = if job.materials > 0.0 {
+                            format!("{}: {} ({} materials)", job.name(), job.cost, job.materials)
+                        } else {
+                            format!("{}: {}", job.name(), job.cost)
+                        };
+                        if ui
+                            .add_enabled(job.materials <= materials, egui::Button::new(label))
+                            .clicked()
+                        {
+                            game_commands.write(GameCommand(game_core::Command::SetConstruction {
+                                settlement: id,
+                                construction: i,
+                            }));
+                        }
+                    }
+                    let board = pathfinding.board(&world_map);
+                    let improvements = game_state.game.buildable_improvements(board, id);
+                    if !improvements.is_empty() {
+                        ui.separator();
+                        ui.label("Improvements");
+                    }
+                    for (cell, improvement) in improvements {
+                        let bonus = improvement.yields_on(&world_map, cell);
+                        let label = format!(
+                            "{} (+{:.1} food, +{:.1} production, +{:.1} materials): {} ({} materials)",
+                            improvement.name,
+                            bonus.food,
+                            bonus.production,
+                            bonus.materials,
+                            improvement.cost,
+                            improvement.materials
+                        );
+                        if ui
+                            .add_enabled(
+                                improvement.materials <= materials,
+                                egui::Button::new(label),
+                            )
+                            .on_hover_text(&improvement.description)
+                            .clicked()
+                        {
+                            game_commands.write(GameCommand(
+                                game_core::Command::BuildImprovement {
+                                    settlement: id,
+                                    improvement: improvement.name.clone(),
+                                    cell,
+                                },
+                            ));
                         }
                     }
                 }
@@ -167,14 +527,18 @@ fn ui_example_system(
                 match *selected {
                     Selection::None => {}
                     Selection::Unit(entity) => {
-                        let unit = units.get_mut(entity).unwrap();
-                        ui.label(unit.name.clone());
-                        ui.label(format!("Speed: {}/{}", unit.used_speed, unit.speed));
-                        ui.label(format!(
-                            "Health: {:.0}/{:.0}",
-                            unit.health.ceil(),
-                            unit.max_health.ceil()
-                        ));
+                        // The unit may have died this frame and not be despawned yet.
+                        if let Ok(unit) = units.get(entity)
+                            && let Some(unit) = game_state.game.unit(unit.id)
+                        {
+                            ui.label(unit.unit_type.clone());
+                            ui.label(format!("Speed: {}/{}", unit.used_speed, unit.speed));
+                            ui.label(format!(
+                                "Health: {:.0}/{:.0}",
+                                unit.health.ceil(),
+                                unit.max_health.ceil()
+                            ));
+                        }
                     }
                     Selection::Settlement(_entity) => {}
                 }
@@ -187,24 +551,32 @@ fn ui_example_system(
                         // These will appear stuck to the right edge:
                         if ui
                             .add_enabled(
-                                game_state.turn_ready_to_end,
+                                game_state.active_player_is_human()
+                                    && game_state.game.turn_ready_to_end(),
                                 egui::widgets::Button::new("Next Turn"),
                             )
                             .clicked()
                         {
-                            let current_player = game_state.active_player;
-                            let current_player = game_state.players.get(&current_player);
-                            if let Some(current_player) = current_player {
-                                let next_player = game_state.players.values().find(|p| {
-                                    p.order == (current_player.order + 1) % game_state.players.len()
-                                });
-                                if let Some(next_player) = next_player {
-                                    turn_start.write(TurnStart {
-                                        player: next_player.id,
-                                    });
-                                    game_state.active_player = next_player.id;
-                                }
-                            }
+                            game_commands.write(GameCommand(game_core::Command::EndTurn));
+                        }
+                        if ui
+                            .add_enabled(
+                                game_state.active_player_is_human() && game_state.game.can_undo(),
+                                egui::widgets::Button::new("Undo"),
+                            )
+                            .on_hover_text("Ctrl+Z")
+                            .clicked()
+                        {
+                            game_commands.write(GameCommand(game_core::Command::Undo));
+                        }
+                        if ui.button("Research").clicked() {
+                            research_screen.0 = !research_screen.0;
+                        }
+                        if ui.button("Save Game").clicked() {
+                            save_game.write(save::SaveGame { compact: false });
+                        }
+                        if ui.button("Save Replay").clicked() {
+                            save_replay.write(replay::SaveReplay);
                         }
                         ui.separator();
                         let version = option_env!("VERSION_TAG").unwrap_or("Custom");
@@ -255,8 +627,10 @@ fn ui_example_system(
     let size = UVec2::new(window.physical_width(), window.physical_height())
         - pos
         - UVec2::new(right as u32, bottom as u32);
-    let active_player = game_state.active_player;
-    let player = game_state.players.get(&active_player).unwrap();
+    let player = game_state
+        .players
+        .get(&game_state.viewing_player())
+        .unwrap();
     if let Some(camera_entity) = player.camera_entity {
         let mut camera = camera.get_mut(camera_entity).unwrap();
         camera.viewport = Some(Viewport {
@@ -0,0 +1,78 @@
+//! Compares [`WorldMap::get_cell_for_position`] with testing every cell polygon, as it did
+//! before the cell index, and times generating a whole `WorldType::Large` map.
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use geo::Contains;
+use glam::Vec2;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use world_generation::{CellId, WorldMap, WorldType, generate_world};
+
+fn bench_cell_lookup(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(1);
+    let world_map = generate_world(WorldType::Large.get_params(), &mut rng).unwrap();
+    let (min, max) = world_map.bounds();
+    let points = (0..1000)
+        .map(|_| {
+            Vec2::new(
+                rng.random_range(min.x..max.x),
+                rng.random_range(min.y..max.y),
+            )
+        })
+        .collect::<Vec<_>>();
+    let polygons = world_map
+        .iter_cells()
+        .map(|c| (CellId(c.site()), world_map.get_cell_shape(CellId(c.site()))))
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("cell_lookup_large_map");
+    group.bench_function("cell_index", |b| {
+        b.iter(|| {
+            for pos in points.iter() {
+                black_box(world_map.get_cell_for_position(black_box(*pos)));
+            }
+        })
+    });
+    group.sample_size(10);
+    group.bench_function("linear_scan", |b| {
+        b.iter(|| {
+            for pos in points.iter() {
+                black_box(legacy_cell_for_position(
+                    &world_map,
+                    &polygons,
+                    black_box(*pos),
+                ));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_generate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_large_map");
+    group.sample_size(10);
+    group.bench_function("generate_world", |b| {
+        b.iter(|| {
+            let mut rng = StdRng::seed_from_u64(1);
+            generate_world(black_box(WorldType::Large.get_params()), &mut rng).unwrap()
+        })
+    });
+    group.finish();
+}
+
+/// The original lookup, kept as a baseline.
+fn legacy_cell_for_position(
+    world_map: &WorldMap,
+    polygons: &[(CellId, geo::Polygon)],
+    pos: Vec2,
+) -> Option<CellId> {
+    let point =
+        geo::point!(x: (pos.x / world_map.scale) as f64, y: (pos.y / world_map.scale) as f64);
+    polygons
+        .iter()
+        .find(|(_, poly)| poly.contains(&point))
+        .map(|(cell, _)| *cell)
+}
+
+criterion_group!(benches, bench_cell_lookup, bench_generate);
+criterion_main!(benches);
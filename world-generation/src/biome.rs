@@ -0,0 +1,189 @@
+//! Temperature, moisture and biomes, worked out from the finished heightmap.
+//!
+//! Nothing here draws from the RNG, so a restored map gets the same biomes without storing them.
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use voronoice::Voronoi;
+
+use crate::CellId;
+
+/// Height of the sea surface, any cell below it is water.
+pub(crate) const SEA_LEVEL: f32 = 0.5;
+/// Land this high is mountain whatever the climate.
+const MOUNTAIN_HEIGHT: f32 = 0.75;
+/// Land this close to sea level is wet enough to turn to marsh.
+const LOWLAND_HEIGHT: f32 = 0.53;
+/// Temperature lost per unit of height above sea level.
+const LAPSE_RATE: f32 = 0.8;
+/// Share of the moisture from the nearest water kept per cell further inland.
+const INLAND_DRYING: f32 = 0.85;
+/// Share of the wind's moisture kept per cell it blows over land.
+const WIND_DRYING: f32 = 0.97;
+/// Share of the wind's moisture rained out per unit of height it climbs.
+const UPLIFT_RAIN: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Biome {
+    /// Water with no land next to it.
+    Ocean,
+    /// Shallow water along the shore.
+    Coast,
+    Tundra,
+    Desert,
+    Grassland,
+    Forest,
+    Jungle,
+    Marsh,
+    Mountain,
+}
+impl Biome {
+    pub fn is_water(self) -> bool {
+        matches!(self, Biome::Ocean | Biome::Coast)
+    }
+}
+
+/// How warm and wet a cell is, both from 0 to 1, and the biome that gives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Climate {
+    /// Warmest at the equator across the middle of the map, colder towards the top and bottom
+    /// edges and the higher up.
+    pub temperature: f32,
+    /// Wettest by the water and on slopes facing the westerly wind, driest far inland and in the
+    /// lee of high ground.
+    pub moisture: f32,
+    pub biome: Biome,
+}
+
+/// The climate of every cell, `map_height` being the height the cells are laid out over.
+pub(crate) fn classify(
+    voronoi: &Voronoi,
+    heights: &HashMap<CellId, f32>,
+    map_height: f64,
+) -> HashMap<CellId, Climate> {
+    let sites = voronoi.sites();
+    let height = |cell: usize| heights[&CellId(cell)];
+    let is_water = |cell: usize| height(cell) < SEA_LEVEL;
+
+    // Steps to the nearest water, searching out from all of it at once.
+    let mut steps = vec![usize::MAX; sites.len()];
+    let mut queue = (0..sites.len())
+        .filter(|c| is_water(*c))
+        .collect::<VecDeque<_>>();
+    for cell in queue.iter() {
+        steps[*cell] = 0;
+    }
+    while let Some(cell) = queue.pop_front() {
+        for n in voronoi.cell(cell).iter_neighbors() {
+            if steps[n] == usize::MAX {
+                steps[n] = steps[cell] + 1;
+                queue.push_back(n);
+            }
+        }
+    }
+
+    // The wind picks moisture up over water and carries it east, losing it as it goes and
+    // raining most of it out climbing high ground, which leaves the far side dry.
+    let mut order = (0..sites.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| sites[*a].x.total_cmp(&sites[*b].x));
+    let mut carried = vec![1.0f32; sites.len()];
+    let mut wind = vec![1.0f32; sites.len()];
+    for cell in order {
+        if is_water(cell) {
+            continue;
+        }
+        let upwind = voronoi
+            .cell(cell)
+            .iter_neighbors()
+            .filter(|n| sites[*n].x < sites[cell].x)
+            .collect::<Vec<_>>();
+        if upwind.is_empty() {
+            continue;
+        }
+        let count = upwind.len() as f32;
+        let incoming = upwind.iter().map(|n| carried[*n]).sum::<f32>() / count;
+        let upwind_height = upwind
+            .iter()
+            .map(|n| height(*n).max(SEA_LEVEL))
+            .sum::<f32>()
+            / count;
+        let climb = (height(cell) - upwind_height).max(0.0);
+        wind[cell] = incoming;
+        carried[cell] = incoming * WIND_DRYING * (1.0 - (climb * UPLIFT_RAIN).min(1.0));
+    }
+
+    (0..sites.len())
+        .map(|cell| {
+            let h = height(cell);
+            let latitude = ((sites[cell].y / map_height) * 2.0 - 1.0).abs() as f32;
+            let temperature =
+                ((1.0 - latitude) - (h - SEA_LEVEL).max(0.0) * LAPSE_RATE).clamp(0.0, 1.0);
+            let inland = INLAND_DRYING.powi(steps[cell].min(i32::MAX as usize) as i32);
+            let moisture = (0.5 * inland + 0.5 * wind[cell]).clamp(0.0, 1.0);
+            let biome = if is_water(cell) {
+                if voronoi.cell(cell).iter_neighbors().any(|n| !is_water(n)) {
+                    Biome::Coast
+                } else {
+                    Biome::Ocean
+                }
+            } else if h >= MOUNTAIN_HEIGHT {
+                Biome::Mountain
+            } else if temperature < 0.2 {
+                Biome::Tundra
+            } else if moisture < 0.3 {
+                Biome::Desert
+            } else if h < LOWLAND_HEIGHT && moisture > 0.7 {
+                Biome::Marsh
+            } else if temperature > 0.65 && moisture > 0.6 {
+                Biome::Jungle
+            } else if moisture > 0.5 {
+                Biome::Forest
+            } else {
+                Biome::Grassland
+            };
+            let climate = Climate {
+                temperature,
+                moisture,
+                biome,
+            };
+            (CellId(cell), climate)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{WorldType, generate_world};
+
+    #[test]
+    fn biomes_follow_the_heightmap() {
+        let mut rng = StdRng::seed_from_u64(24);
+        let world_map = generate_world(WorldType::Default.get_params(), &mut rng).unwrap();
+        let mut seen = BTreeSet::new();
+        for cell in world_map.iter_cells() {
+            let id = CellId(cell.site());
+            let biome = world_map.get_biome(id);
+            let water = world_map.get_raw_height(&id) < SEA_LEVEL;
+            assert_eq!(biome.is_water(), water, "{id:?} is {biome:?}");
+            let by_land = world_map
+                .get_neighbours(id)
+                .iter()
+                .any(|n| world_map.get_raw_height(n) >= SEA_LEVEL);
+            if water {
+                assert_eq!(biome == Biome::Coast, by_land);
+            }
+            let climate = world_map.get_climate(id);
+            assert!((0.0..=1.0).contains(&climate.temperature));
+            assert!((0.0..=1.0).contains(&climate.moisture));
+            seen.insert(biome);
+        }
+        for biome in [Biome::Ocean, Biome::Coast, Biome::Grassland, Biome::Forest] {
+            assert!(seen.contains(&biome), "no {biome:?} on the map");
+        }
+    }
+}
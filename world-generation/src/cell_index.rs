@@ -0,0 +1,112 @@
+//! Finding the cell under a point without testing every cell polygon.
+use voronoice::{Point, Voronoi};
+
+use crate::CellId;
+
+/// A grid of cells to start walking the Delaunay neighbours from, towards the site nearest a
+/// point.
+///
+/// The Voronoi cell containing a point is the one whose site is nearest to it, and any other site
+/// has a neighbour nearer to the point, so always stepping to the nearest neighbour gets there.
+/// Starting from the cell remembered for the point's bucket that only takes a step or two.
+#[derive(Clone)]
+pub(crate) struct CellIndex {
+    columns: usize,
+    rows: usize,
+    bucket_width: f64,
+    bucket_height: f64,
+    /// The cell nearest the centre of each bucket, row by row.
+    starts: Vec<CellId>,
+}
+impl CellIndex {
+    pub(crate) fn new(voronoi: &Voronoi, width: f64, height: f64) -> Self {
+        // About one site per bucket, with the buckets roughly square.
+        let sites = voronoi.sites().len();
+        let columns = ((sites as f64 * width / height).sqrt().ceil() as usize).max(1);
+        let rows = sites.div_ceil(columns).max(1);
+        let bucket_width = width / columns as f64;
+        let bucket_height = height / rows as f64;
+        let mut starts = Vec::with_capacity(columns * rows);
+        let mut start = CellId(0);
+        for row in 0..rows {
+            for column in 0..columns {
+                // Each walk starts from a bucket next to this one, so it stays short.
+                if column == 0 && row > 0 {
+                    start = starts[(row - 1) * columns];
+                }
+                let centre = Point {
+                    x: (column as f64 + 0.5) * bucket_width,
+                    y: (row as f64 + 0.5) * bucket_height,
+                };
+                start = walk(voronoi, start, &centre);
+                starts.push(start);
+            }
+        }
+        Self {
+            columns,
+            rows,
+            bucket_width,
+            bucket_height,
+            starts,
+        }
+    }
+    /// The cell whose site is nearest to `point`, points off the map get the nearest edge cell.
+    pub(crate) fn nearest(&self, voronoi: &Voronoi, point: &Point) -> CellId {
+        let column = ((point.x / self.bucket_width) as usize).min(self.columns - 1);
+        let row = ((point.y / self.bucket_height) as usize).min(self.rows - 1);
+        walk(voronoi, self.starts[row * self.columns + column], point)
+    }
+}
+
+/// Steps to whichever neighbour is nearest `point` until none is nearer than the current cell.
+fn walk(voronoi: &Voronoi, from: CellId, point: &Point) -> CellId {
+    let sites = voronoi.sites();
+    let distance = |cell: usize| {
+        let site = &sites[cell];
+        (site.x - point.x).powi(2) + (site.y - point.y).powi(2)
+    };
+    let mut current = (from.0, distance(from.0));
+    loop {
+        let nearer = voronoi
+            .cell(current.0)
+            .iter_neighbors()
+            .map(|n| (n, distance(n)))
+            .filter(|(_, d)| *d < current.1)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        match nearer {
+            Some(next) => current = next,
+            None => return CellId(current.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::Contains;
+    use glam::Vec2;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use crate::{WorldType, generate_world};
+
+    #[test]
+    fn lookups_match_testing_every_polygon() {
+        let mut rng = StdRng::seed_from_u64(21);
+        let params = WorldType::Small.get_params();
+        let world_map = generate_world(params, &mut rng).unwrap();
+        let scale = world_map.scale;
+        let sites = world_map.iter_cells().map(|c| c.site_position().clone());
+        let random = (0..500).map(|_| voronoice::Point {
+            // A margin around the map, so points off it are covered too.
+            x: rng.random_range(-1.0..params.width + 1.0),
+            y: rng.random_range(-1.0..params.height + 1.0),
+        });
+        for point in sites.chain(random).collect::<Vec<_>>() {
+            let pos = Vec2::new(point.x as f32, point.y as f32) * scale;
+            let scanned = world_map.polygons.iter().find_map(|(cell, poly)| {
+                poly.contains(&geo::point!(x: (pos.x / scale) as f64, y: (pos.y / scale) as f64))
+                    .then_some(*cell)
+            });
+            assert_eq!(world_map.get_cell_for_position(pos), scanned);
+        }
+    }
+}
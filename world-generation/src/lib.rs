@@ -1,9 +1,11 @@
 #![forbid(unsafe_code)]
 use std::{
     cmp::Ordering,
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
     hash::Hash,
-    ops::Deref,
+    iter,
+    ops::{Deref, RangeInclusive},
 };
 
 use geo::{Contains, CoordsIter, Polygon, unary_union};
@@ -13,13 +15,24 @@ use rand::{
     Rng,
     distr::{Distribution, Uniform},
 };
+use serde::{Deserialize, Serialize};
 use voronoice::*;
 
 use helpers::min_max_componentwise;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+mod biome;
+mod cell_index;
+mod river;
+mod storage;
+pub use biome::{Biome, Climate};
+use cell_index::CellIndex;
+pub use river::River;
+use river::Rivers;
+pub use storage::{GENERATOR_VERSION, StoredWorld, WorldHeader, WorldLoadError, WorldMapData};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct CellId(pub usize);
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct PlateId(usize);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct ContinentId(usize);
@@ -38,21 +51,31 @@ pub struct WorldMap {
     pub scale: f32,
     pub height_scale: f32,
     pub entity_scale: f32,
+    params: WorldGenerationParams,
+    profile: TerrainProfile,
     voronoi: Voronoi,
     cell_height: HashMap<CellId, f32>,
+    climate: HashMap<CellId, Climate>,
+    rivers: Rivers,
     polygons: HashMap<CellId, geo::Polygon>,
+    cell_index: CellIndex,
     vertex_heights: HashMap<I64Vec2, f32>,
     resources: HashMap<CellId, HashMap<String, f32>>,
 }
 impl WorldMap {
     pub fn get_cell_for_position(&self, pos: Vec2) -> Option<CellId> {
-        for (cell_id, poly) in self.polygons.iter() {
-            if poly.contains(&geo::point!(x:(pos.x/self.scale) as f64,y:(pos.y/self.scale) as f64))
-            {
-                return Some(*cell_id);
-            }
-        }
-        None
+        let point = geo::point!(x: (pos.x / self.scale) as f64, y: (pos.y / self.scale) as f64);
+        let nearest = self.cell_index.nearest(
+            &self.voronoi,
+            &Point {
+                x: point.x(),
+                y: point.y(),
+            },
+        );
+        // A point right on an edge can fall just inside the neighbour's polygon instead.
+        iter::once(nearest)
+            .chain(self.get_neighbours(nearest))
+            .find(|cell| self.polygons[cell].contains(&point))
     }
     pub fn get_position_for_cell(&self, id: CellId) -> Vec3 {
         let cell = self.voronoi.cell(id.0);
@@ -163,6 +186,29 @@ impl WorldMap {
     pub fn get_resources_for_cell(&self, id: CellId) -> Option<&HashMap<String, f32>> {
         self.resources.get(&id)
     }
+    pub fn get_climate(&self, id: CellId) -> Climate {
+        self.climate[&id]
+    }
+    pub fn get_biome(&self, id: CellId) -> Biome {
+        self.climate[&id].biome
+    }
+    pub fn rivers(&self) -> &[River] {
+        self.rivers.all()
+    }
+    /// Whether a river runs through the cell, or ends in it.
+    pub fn has_river(&self, id: CellId) -> bool {
+        self.rivers.contains(id)
+    }
+    /// Whether a river flows between the two cells, either way.
+    pub fn has_river_between(&self, a: CellId, b: CellId) -> bool {
+        self.rivers.between(a, b)
+    }
+    pub fn params(&self) -> WorldGenerationParams {
+        self.params
+    }
+    pub fn profile(&self) -> &TerrainProfile {
+        &self.profile
+    }
 }
 
 impl Deref for CellId {
@@ -189,7 +235,7 @@ impl From<ContinentId> for usize {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WorldGenerationParams {
     pub width: f64,
     pub height: f64,
@@ -202,7 +248,85 @@ pub struct WorldGenerationParams {
     pub scale: f32,
     pub world_type: WorldType,
 }
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+/// Fewest cells a map can have, any less and there's no room for more than one settlement.
+pub const MIN_CELLS: usize = 100;
+/// Most cells a map can have before generation takes too long to wait for.
+pub const MAX_CELLS: usize = 50_000;
+
+impl WorldGenerationParams {
+    /// Allowed `width` and `height`, in the units the cells are laid out in before scaling.
+    pub const SIZE_RANGE: RangeInclusive<f64> = 4.0..=64.0;
+    pub const PLATE_COUNT_RANGE: RangeInclusive<usize> = 1..=50;
+    /// Allowed cells per plate, continent or ocean.
+    pub const REGION_SIZE_RANGE: RangeInclusive<usize> = 1..=1000;
+    pub const CONTINENT_COUNT_RANGE: RangeInclusive<usize> = 1..=100;
+    pub const OCEAN_COUNT_RANGE: RangeInclusive<usize> = 0..=100;
+    pub const SCALE_RANGE: RangeInclusive<f32> = 1.0..=60.0;
+
+    /// Number of cells on the map, every continent and ocean cell.
+    pub fn cell_count(&self) -> usize {
+        self.continent_count * self.continent_size + self.ocean_count * self.ocean_size
+    }
+    /// Checks every field is in range and the map has a workable number of cells.
+    pub fn validate(&self) -> Result<(), ParamsError> {
+        fn check<T: PartialOrd + Display>(
+            name: &'static str,
+            value: T,
+            range: RangeInclusive<T>,
+        ) -> Result<(), ParamsError> {
+            if range.contains(&value) {
+                return Ok(());
+            }
+            Err(ParamsError::OutOfRange {
+                name,
+                min: range.start().to_string(),
+                max: range.end().to_string(),
+            })
+        }
+        check("Width", self.width, Self::SIZE_RANGE)?;
+        check("Height", self.height, Self::SIZE_RANGE)?;
+        check("Plate count", self.plate_count, Self::PLATE_COUNT_RANGE)?;
+        check("Plate size", self.plate_size, Self::REGION_SIZE_RANGE)?;
+        check(
+            "Continent count",
+            self.continent_count,
+            Self::CONTINENT_COUNT_RANGE,
+        )?;
+        check(
+            "Continent size",
+            self.continent_size,
+            Self::REGION_SIZE_RANGE,
+        )?;
+        check("Ocean count", self.ocean_count, Self::OCEAN_COUNT_RANGE)?;
+        check("Ocean size", self.ocean_size, Self::REGION_SIZE_RANGE)?;
+        check("Scale", self.scale, Self::SCALE_RANGE)?;
+        let cells = self.cell_count();
+        if cells < MIN_CELLS {
+            return Err(ParamsError::TooFewCells(cells));
+        }
+        if cells > MAX_CELLS {
+            return Err(ParamsError::TooManyCells(cells));
+        }
+        Ok(())
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum ParamsError {
+    #[error("{name} must be between {min} and {max}")]
+    OutOfRange {
+        name: &'static str,
+        min: String,
+        max: String,
+    },
+    #[error("The map needs at least {MIN_CELLS} cells, these params give {0}")]
+    TooFewCells(usize),
+    #[error("The map can have at most {MAX_CELLS} cells, these params give {0}")]
+    TooManyCells(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum WorldType {
     Default = 0,
     Small = 1,
@@ -257,6 +381,14 @@ impl WorldType {
 }
 pub fn generate_world<R: Rng + Clone>(
     params: WorldGenerationParams,
+    rng: &mut R,
+) -> anyhow::Result<WorldMap> {
+    generate_world_with_profile(params, &TerrainProfile::default(), rng)
+}
+/// Generates a map laid out by `params` with terrain shaped by `profile`.
+pub fn generate_world_with_profile<R: Rng + Clone>(
+    params: WorldGenerationParams,
+    profile: &TerrainProfile,
     mut rng: &mut R,
 ) -> anyhow::Result<WorldMap> {
     let WorldGenerationParams {
@@ -271,11 +403,13 @@ pub fn generate_world<R: Rng + Clone>(
         scale,
         world_type,
     } = params;
+    params.validate()?;
     let fbm = HybridMulti::<Worley>::new(rng.next_u32());
     let ridged_multi = RidgedMulti::<Perlin>::new(rng.next_u32());
     let my_voronoi = generate(&mut rng, width, height, plate_count * plate_size)?;
 
-    let mut plates: HashMap<CellId, PlateId> = HashMap::new();
+    // Ordered maps throughout, iteration order feeds the RNG and must not vary between runs.
+    let mut plates: BTreeMap<CellId, PlateId> = BTreeMap::new();
 
     rng.sample_iter(rand::distr::Uniform::new(0, plate_count * plate_size).unwrap())
         .take(plate_count)
@@ -293,8 +427,11 @@ pub fn generate_world<R: Rng + Clone>(
             }
         }
     }
-    let plates_to_cells: HashMap<PlateId, Vec<CellId>> = helpers::invert_borrowed(&plates);
-    let mut hull_plates: HashMap<PlateId, geo::Polygon> = HashMap::new();
+    let mut plates_to_cells: BTreeMap<PlateId, Vec<CellId>> = BTreeMap::new();
+    for (cell_id, plate) in plates.iter() {
+        plates_to_cells.entry(*plate).or_default().push(*cell_id);
+    }
+    let mut hull_plates: BTreeMap<PlateId, geo::Polygon> = BTreeMap::new();
     for plate in plates_to_cells.keys() {
         let mut polygons: Vec<Polygon> = vec![];
         for cell_id in plates_to_cells.get(plate).unwrap() {
@@ -323,7 +460,7 @@ pub fn generate_world<R: Rng + Clone>(
         height,
         continent_count * continent_size + ocean_count * ocean_size,
     )?;
-    let mut continents: HashMap<CellId, ContinentId> = HashMap::new();
+    let mut continents: BTreeMap<CellId, ContinentId> = BTreeMap::new();
     rng.sample_iter(rand::distr::Uniform::new(0, continent_count * continent_size).unwrap())
         .take(continent_count)
         .enumerate()
@@ -358,7 +495,7 @@ pub fn generate_world<R: Rng + Clone>(
     let continent_ids = continents
         .values()
         .copied()
-        .collect::<HashSet<ContinentId>>()
+        .collect::<BTreeSet<ContinentId>>()
         .iter()
         .copied()
         .collect::<Vec<_>>();
@@ -394,7 +531,7 @@ pub fn generate_world<R: Rng + Clone>(
 
     let neighbours = build_neighbors_from_voronoi(&continents_voronoi);
     let mut cells: Vec<Cell> = Vec::new();
-    let mut plate_to_cells: HashMap<PlateId, Vec<CellId>> = HashMap::new();
+    let mut plate_to_cells: BTreeMap<PlateId, Vec<CellId>> = BTreeMap::new();
 
     let mut cell_polys = HashMap::new();
     for v_cell in continents_voronoi.iter_cells() {
@@ -431,16 +568,7 @@ pub fn generate_world<R: Rng + Clone>(
             plate_to_cells.insert(cell.plate, vec![cell_id]);
         }
         cells.push(cell);
-        let poly = geo::Polygon::new(
-            geo::LineString::from(
-                v_cell
-                    .iter_vertices()
-                    .map(|p| geo::Coord { x: p.x, y: p.y })
-                    .collect::<Vec<_>>(),
-            ),
-            vec![],
-        );
-        cell_polys.insert(cell_id, poly);
+        cell_polys.insert(cell_id, cell_polygon(&v_cell));
     }
     println!("plates: {:?}", plate_to_cells.keys());
 
@@ -463,14 +591,17 @@ pub fn generate_world<R: Rng + Clone>(
         plates.insert(plateid, plate);
     }
 
-    let noise_scale = 50.0;
-    let cells_height = match world_type {
-        WorldType::Flat => cells
-            .iter()
-            .map(|c| (c.id, 0.5))
-            .collect::<HashMap<CellId, f32>>(),
+    let noise_scale = profile.noise_scale;
+    let (cells_height, rivers) = match world_type {
+        WorldType::Flat => (
+            cells
+                .iter()
+                .map(|c| (c.id, 0.5))
+                .collect::<HashMap<CellId, f32>>(),
+            vec![],
+        ),
         _ => {
-            let mut h = generate_heightmap(&cells, plates, |p| {
+            let (mut h, rivers) = generate_heightmap(&cells, plates, profile, |p| {
                 // Simple FBM + ridged noise
                 let a = fbm.get([p.x as f64 * noise_scale, p.y as f64 * noise_scale]) as f32 * 0.5;
                 let b = ridged_multi.get([p.x as f64 * noise_scale, p.y as f64 * noise_scale])
@@ -489,14 +620,15 @@ pub fn generate_world<R: Rng + Clone>(
                 h.iter().cloned().fold(f32::MIN, f32::max),
                 h.iter().cloned().fold(f32::MAX, f32::min)
             );
-            cells
+            let cells_height = cells
                 .iter()
                 .enumerate()
                 .map(|(i, c)| (c.id, h[i]))
-                .collect::<HashMap<CellId, f32>>()
+                .collect::<HashMap<CellId, f32>>();
+            (cells_height, rivers)
         }
     };
-    let fertility_noise_scale = 25.0;
+    let fertility_noise_scale = profile.fertility_noise_scale;
     let fertitlity_fbm = Fbm::<Perlin>::new(rng.next_u32());
     let mineral_multi = HybridMulti::<Worley>::new(rng.next_u32());
     let resources = build_resource_maps(
@@ -537,6 +669,11 @@ pub fn generate_world<R: Rng + Clone>(
         scale,
         height_scale: 7.5,
         entity_scale: 0.75,
+        params,
+        profile: profile.clone(),
+        cell_index: CellIndex::new(&continents_voronoi, width, height),
+        climate: biome::classify(&continents_voronoi, &cells_height, height),
+        rivers: Rivers::new(rivers),
         voronoi: continents_voronoi,
         cell_height: cells_height,
         polygons: cell_polys,
@@ -924,62 +1061,64 @@ fn distance_to_boundary(cells: &[Cell], edges: &[BoundaryEdge]) -> BoundaryDista
 /// - Parameters are typically tuned together: amplitudes set magnitudes,
 ///   length/width parameters control spatial scales, and sigma/delta values
 ///   control spreads and offsets. Noise weights modulate detail and roughness.
-struct Params {
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
     // Baseline
     ///baseline amplitude applied to continental regions.
-    a_cont: f32,
+    pub a_cont: f32,
     ///baseline amplitude/depth applied to ocean basins.
-    a_ocean: f32,
+    pub a_ocean: f32,
     // Mountains/trench/arc
     ///amplitude of mountain ranges (height of peaks).
-    a_mtn: f32,
+    pub a_mtn: f32,
     ///characteristic horizontal scale/width of mountain belts.
-    w_mtn: f32,
+    pub w_mtn: f32,
     ///placement/periodicity parameter affecting mountain distribution.
-    p_mtn: f32,
+    pub p_mtn: f32,
     ///amplitude/depth of oceanic trenches.
-    a_trench: f32,
+    pub a_trench: f32,
     ///spatial spread (standard deviation) of trench features.
-    sigma_tr: f32,
+    pub sigma_tr: f32,
     ///amplitude of volcanic arc features (elevation or depression).
-    a_arc: f32,
+    pub a_arc: f32,
     ///lateral offset applied to arc placement relative to boundaries.
-    delta_arc: f32,
+    pub delta_arc: f32,
     ///spatial spread (standard deviation) of arc features.
-    sigma_arc: f32,
+    pub sigma_arc: f32,
     // MOR & transform
     ///amplitude of mid‑ocean ridge features.
-    a_mor: f32,
+    pub a_mor: f32,
     ///characteristic width/scale of ridge crests.
-    w_mor: f32,
+    pub w_mor: f32,
     ///periodicity/phase parameter for ridge segmentation.
-    p_mor: f32,
+    pub p_mor: f32,
     ///amplitude for transform fault / fracture zone features.
-    a_tr: f32,
+    pub a_tr: f32,
     ///width/scale of transform features.
-    w_tr: f32,
+    pub w_tr: f32,
     // Coast & shelves
     ///amplitude/height of lowland plains on continents.
-    a_plains: f32,
+    pub a_plains: f32,
     ///horizontal extent/characteristic length of plains.
-    l_plains: f32,
+    pub l_plains: f32,
     ///depth of the continental shelf (positive downward).
-    d_shelf: f32,
+    pub d_shelf: f32,
     ///horizontal length/extent of the shelf region.
-    l_shelf: f32,
+    pub l_shelf: f32,
     ///vertical drop across the continental slope.
-    d_slope: f32,
+    pub d_slope: f32,
     ///horizontal length of the slope transition.
-    l_slope: f32,
+    pub l_slope: f32,
     ///depth of the abyssal plain / deep ocean basins.
-    d_abyss: f32,
+    pub d_abyss: f32,
     // Noise
     ///amount of positional warping applied to sampled coordinates (noise warp).
-    warp: f32,
+    pub warp: f32,
     ///blending weight for FBM (fractional Brownian motion) noise component.
-    w_fbm: f32,
+    pub w_fbm: f32,
     ///blending weight for ridge-style noise component.
-    w_ridge: f32,
+    pub w_ridge: f32,
 }
 
 impl Default for Params {
@@ -1013,6 +1152,42 @@ impl Default for Params {
         }
     }
 }
+/// Everything that shapes the terrain besides the map's layout, so map types can ship as data.
+///
+/// Loaded from `assets/world-profiles/*.profile.ron`, anything left out keeps its default.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainProfile {
+    pub name: String,
+    /// How the heightmap layers are weighted.
+    pub heights: Params,
+    /// Frequency of the height and mineral noise, higher gives smaller features.
+    pub noise_scale: f64,
+    /// Frequency of the fertility noise.
+    pub fertility_noise_scale: f64,
+    /// Share of all cells that has to drain through a cell before a river carves it.
+    pub river_share: f32,
+    /// Fewest cells that make a river however small the map is.
+    pub min_river_flow: usize,
+}
+impl TerrainProfile {
+    /// Fewest cells that have to drain through a cell for a river to run through it.
+    pub(crate) fn river_threshold(&self, cell_count: usize) -> usize {
+        ((self.river_share * cell_count as f32) as usize).max(self.min_river_flow)
+    }
+}
+impl Default for TerrainProfile {
+    fn default() -> Self {
+        Self {
+            name: "Standard".to_string(),
+            heights: Params::default(),
+            noise_scale: 50.0,
+            fertility_noise_scale: 25.0,
+            river_share: 0.005,
+            min_river_flow: 3,
+        }
+    }
+}
 fn laplacian_smooth(cells: &[Cell], h: &mut [f32], iters: usize) {
     let mut tmp = h.to_vec();
     for _ in 0..iters {
@@ -1121,39 +1296,9 @@ fn assemble_height(
     h
 }
 
-fn carve_rivers(cells: &[Cell], h: &mut [f32], threshold: usize) {
-    let n = cells.len();
-    // Choose steepest neighbor as downslope pointer
-    let mut to = vec![None::<CellId>; n];
-    let mut indeg = vec![0usize; n];
-    for (i, c) in cells.iter().enumerate() {
-        let mut best = None::<(f32, CellId)>;
-        for &nb in &c.neighbors {
-            let s = h[i] - h[nb.0];
-            if s > 0.0 && best.map(|(bs, _)| s > bs).unwrap_or(true) {
-                best = Some((s, nb));
-            }
-        }
-        if let Some((_, nb)) = best {
-            to[i] = Some(nb);
-            indeg[nb.0] += 1;
-        }
-    }
-
-    // Topological order (forest), accumulate flow
-    let mut flow = vec![1.0f32; n];
-    let mut q: std::collections::VecDeque<CellId> =
-        (0..n).filter(|&i| indeg[i] == 0).map(CellId).collect();
-
-    while let Some(u) = q.pop_front() {
-        if let Some(v) = to[u.0] {
-            flow[v.0] += flow[u.0];
-            indeg[v.0] -= 1;
-            if indeg[v.0] == 0 {
-                q.push_back(v);
-            }
-        }
-    }
+/// Lowers the cells that enough others drain through, returning the rivers that cut them.
+fn carve_rivers(cells: &[Cell], h: &mut [f32], threshold: usize) -> Vec<River> {
+    let (to, flow) = river::drainage(h, |i| cells[i].neighbors.iter().copied());
 
     // Carve: width ~ sqrt(flow), depth ~ flow^β (keep small)
     let a_river = 0.35f32;
@@ -1172,12 +1317,15 @@ fn carve_rivers(cells: &[Cell], h: &mut [f32], threshold: usize) {
             }
         }
     }
+    // Negative heights end up below sea level once normalized.
+    river::trace(&to, &flow, threshold, |i| h[i] >= 0.0)
 }
 fn generate_heightmap(
     cells: &[Cell],
     plates: HashMap<PlateId, Plate>,
+    profile: &TerrainProfile,
     mut noise: impl FnMut(glam::Vec2) -> (f32, f32),
-) -> Vec<f32> {
+) -> (Vec<f32>, Vec<River>) {
     // Distances
     let d_coast = signed_coast_distance(cells);
     let edges = classify_boundaries(cells, &plates);
@@ -1199,27 +1347,22 @@ fn generate_heightmap(
     };
 
     // Height layers
-    let p = Params::default();
-    let mut h = assemble_height(cells, &plates, &fields, &mut noise, &p);
+    let mut h = assemble_height(cells, &plates, &fields, &mut noise, &profile.heights);
 
     // Rivers & erosion
-    carve_rivers(
-        cells,
-        &mut h,
-        ((0.005 * cells.len() as f32) as usize).max(3),
-    );
+    let rivers = carve_rivers(cells, &mut h, profile.river_threshold(cells.len()));
 
     // Normalize sea level to desired ratio (optional if you’ve fixed land/ocean)
     //set_sea_level(&mut h, 0.67, None);
 
-    h
+    (h, rivers)
 }
 
 fn smoothstep(e0: f32, e1: f32, x: f32) -> f32 {
     let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
     t * t * (3.0 - 2.0 * t)
 }
-fn set_neighbour_plate(cell: &VoronoiCell, plates: &mut HashMap<CellId, PlateId>, i: PlateId) {
+fn set_neighbour_plate(cell: &VoronoiCell, plates: &mut BTreeMap<CellId, PlateId>, i: PlateId) {
     for n in cell
         .iter_neighbors()
         .filter(|c| !plates.contains_key(&CellId(*c)))
@@ -1232,9 +1375,9 @@ fn set_neighbour_plate(cell: &VoronoiCell, plates: &mut HashMap<CellId, PlateId>
 fn set_neighbour_continent<R: Rng>(
     rng: &mut R,
     cell: &VoronoiCell,
-    continents: &mut HashMap<CellId, ContinentId>,
+    continents: &mut BTreeMap<CellId, ContinentId>,
     i: ContinentId,
-    hulls: &HashMap<PlateId, geo::Polygon>,
+    hulls: &BTreeMap<PlateId, geo::Polygon>,
 ) {
     let current_plate_id = hulls.keys().find(|plate_id| {
         let hull = &hulls[plate_id];
@@ -1278,10 +1421,15 @@ fn generate<R: Rng>(
         })
         .collect();
 
-    let my_voronoi = build_voronoi(sites, width, height)?;
+    let my_voronoi = build_voronoi(sites, width, height, 5)?;
     Ok(my_voronoi)
 }
-fn build_voronoi(sites: Vec<Point>, width: f64, height: f64) -> anyhow::Result<Voronoi> {
+fn build_voronoi(
+    sites: Vec<Point>,
+    width: f64,
+    height: f64,
+    lloyd_relaxation_iterations: usize,
+) -> anyhow::Result<Voronoi> {
     let my_voronoi = VoronoiBuilder::default()
         .set_sites(sites)
         .set_bounding_box(BoundingBox::new(
@@ -1292,12 +1440,23 @@ fn build_voronoi(sites: Vec<Point>, width: f64, height: f64) -> anyhow::Result<V
             width,
             height,
         ))
-        .set_lloyd_relaxation_iterations(5)
+        .set_lloyd_relaxation_iterations(lloyd_relaxation_iterations)
         .build()
         .expect("Failed to build Voronoi diagram");
     Ok(my_voronoi)
 }
 
+fn cell_polygon(cell: &VoronoiCell) -> geo::Polygon {
+    geo::Polygon::new(
+        geo::LineString::from(
+            cell.iter_vertices()
+                .map(|p| geo::Coord { x: p.x, y: p.y })
+                .collect::<Vec<_>>(),
+        ),
+        vec![],
+    )
+}
+
 fn build_resource_maps(
     cells: &mut [Cell],
     cell_heights: &HashMap<CellId, f32>,
@@ -1331,3 +1490,40 @@ struct ResourceType {
     mineral_weight: f32,
     height_range: Option<(f64, f64)>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_valid_and_out_of_range_params_are_not() {
+        for world_type in [
+            WorldType::Default,
+            WorldType::Small,
+            WorldType::Large,
+            WorldType::Flat,
+        ] {
+            assert_eq!(world_type.get_params().validate(), Ok(()));
+        }
+        let params = WorldGenerationParams {
+            plate_count: 0,
+            ..WorldType::Default.get_params()
+        };
+        assert!(matches!(
+            params.validate(),
+            Err(ParamsError::OutOfRange {
+                name: "Plate count",
+                ..
+            })
+        ));
+        let params = WorldGenerationParams {
+            continent_size: 1000,
+            ocean_size: 1000,
+            ..WorldType::Large.get_params()
+        };
+        assert_eq!(
+            params.validate(),
+            Err(ParamsError::TooManyCells(params.cell_count()))
+        );
+    }
+}
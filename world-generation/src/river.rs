@@ -0,0 +1,157 @@
+//! Rivers, kept from the flow graph the heightmap was carved with.
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::CellId;
+
+/// A river from its source down to the sea, a lake or the river it flows into, whichever cell it
+/// ends on is the last in `cells`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct River {
+    /// Each cell flows into the next.
+    pub cells: Vec<CellId>,
+    /// How many cells drain through its last step, bigger rivers carry more.
+    pub flow: f32,
+}
+
+/// Every river on a map, and which cells and neighbours they run through.
+#[derive(Clone)]
+pub(crate) struct Rivers {
+    rivers: Vec<River>,
+    cells: HashSet<CellId>,
+    /// Both ways round, so either cell can be looked up first.
+    steps: HashSet<(CellId, CellId)>,
+}
+impl Rivers {
+    pub(crate) fn new(rivers: Vec<River>) -> Self {
+        let mut cells = HashSet::new();
+        let mut steps = HashSet::new();
+        for river in rivers.iter() {
+            cells.extend(river.cells.iter().copied());
+            for step in river.cells.windows(2) {
+                steps.insert((step[0], step[1]));
+                steps.insert((step[1], step[0]));
+            }
+        }
+        Self {
+            rivers,
+            cells,
+            steps,
+        }
+    }
+    pub(crate) fn all(&self) -> &[River] {
+        &self.rivers
+    }
+    pub(crate) fn contains(&self, cell: CellId) -> bool {
+        self.cells.contains(&cell)
+    }
+    pub(crate) fn between(&self, a: CellId, b: CellId) -> bool {
+        self.steps.contains(&(a, b))
+    }
+}
+
+/// The neighbour each cell drains into, the one it's steepest down to, and how many cells drain
+/// through each, itself included.
+pub(crate) fn drainage<N: IntoIterator<Item = CellId>>(
+    h: &[f32],
+    neighbours: impl Fn(usize) -> N,
+) -> (Vec<Option<CellId>>, Vec<f32>) {
+    let n = h.len();
+    // Choose steepest neighbor as downslope pointer
+    let mut to = vec![None::<CellId>; n];
+    let mut indeg = vec![0usize; n];
+    for i in 0..n {
+        let mut best = None::<(f32, CellId)>;
+        for nb in neighbours(i) {
+            let s = h[i] - h[nb.0];
+            if s > 0.0 && best.map(|(bs, _)| s > bs).unwrap_or(true) {
+                best = Some((s, nb));
+            }
+        }
+        if let Some((_, nb)) = best {
+            to[i] = Some(nb);
+            indeg[nb.0] += 1;
+        }
+    }
+
+    // Topological order (forest), accumulate flow
+    let mut flow = vec![1.0f32; n];
+    let mut q: std::collections::VecDeque<CellId> =
+        (0..n).filter(|&i| indeg[i] == 0).map(CellId).collect();
+
+    while let Some(u) = q.pop_front() {
+        if let Some(v) = to[u.0] {
+            flow[v.0] += flow[u.0];
+            indeg[v.0] -= 1;
+            if indeg[v.0] == 0 {
+                q.push_back(v);
+            }
+        }
+    }
+    (to, flow)
+}
+
+/// Joins the downslope steps out of land cells drained by at least `threshold` cells into rivers.
+///
+/// `to` is the cell each cell drains into and `flow` how many cells drain through it, as worked
+/// out by [`drainage`].
+pub(crate) fn trace(
+    to: &[Option<CellId>],
+    flow: &[f32],
+    threshold: usize,
+    is_land: impl Fn(usize) -> bool,
+) -> Vec<River> {
+    let flows =
+        |cell: usize| to[cell].is_some() && is_land(cell) && flow[cell] as usize >= threshold;
+    let mut fed = vec![false; to.len()];
+    for cell in (0..to.len()).filter(|c| flows(*c)) {
+        fed[to[cell].unwrap().0] = true;
+    }
+    let mut traced = vec![false; to.len()];
+    let mut rivers = Vec::new();
+    // Every source starts a river, the ones traced later stop where they join an earlier one.
+    for source in (0..to.len()).filter(|c| flows(*c) && !fed[*c]) {
+        let mut cells = vec![CellId(source)];
+        let mut cell = source;
+        loop {
+            traced[cell] = true;
+            let next = to[cell].unwrap().0;
+            cells.push(CellId(next));
+            if !flows(next) || traced[next] {
+                break;
+            }
+            cell = next;
+        }
+        rivers.push(River {
+            cells,
+            flow: flow[cell],
+        });
+    }
+    rivers
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{WorldType, generate_world};
+
+    #[test]
+    fn rivers_step_between_neighbours() {
+        let mut rng = StdRng::seed_from_u64(25);
+        let world_map = generate_world(WorldType::Default.get_params(), &mut rng).unwrap();
+        assert!(!world_map.rivers().is_empty());
+        for river in world_map.rivers() {
+            assert!(river.cells.len() >= 2);
+            assert!(world_map.get_raw_height(&river.cells[0]) >= 0.5);
+            for step in river.cells.windows(2) {
+                assert!(world_map.get_neighbours(step[0]).contains(&step[1]));
+                assert!(world_map.has_river_between(step[0], step[1]));
+                assert!(world_map.has_river_between(step[1], step[0]));
+                assert!(world_map.has_river(step[0]) && world_map.has_river(step[1]));
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashMap};
+
+use glam::I64Vec2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use voronoice::Point;
+
+use crate::{
+    CellId, CellIndex, River, Rivers, TerrainProfile, WorldGenerationParams, WorldMap, biome,
+    build_voronoi, cell_polygon, generate_world_with_profile, river,
+};
+
+/// Bumped whenever a change to generation means the same seed and params no longer produce the
+/// same map.
+pub const GENERATOR_VERSION: u32 = 2;
+/// The first generator version whose stored maps keep their rivers.
+const RIVERS_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldHeader {
+    pub generator_version: u32,
+    pub params: WorldGenerationParams,
+    /// Maps from before profiles were added were all shaped by the default one.
+    #[serde(default)]
+    pub profile: TerrainProfile,
+}
+
+/// Everything needed to rebuild a [`WorldMap`] without running the generator.
+///
+/// Per-cell fields are indexed by [`CellId`], the Voronoi is rebuilt from `sites`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldMapData {
+    pub header: WorldHeader,
+    pub scale: f32,
+    pub height_scale: f32,
+    pub entity_scale: f32,
+    pub sites: Vec<(f64, f64)>,
+    pub heights: Vec<f32>,
+    pub vertex_heights: Vec<(i64, i64, f32)>,
+    pub resources: Vec<BTreeMap<String, f32>>,
+    /// Maps stored by generator version 1 have none, they're traced again from `heights`.
+    #[serde(default)]
+    pub rivers: Vec<River>,
+}
+
+/// A map as written to disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StoredWorld {
+    /// The whole map, loadable by any generator version.
+    Full(WorldMapData),
+    /// Only the params, the map is regenerated from the caller's RNG and checked against
+    /// `checksum` to catch generator drift.
+    Seeded { header: WorldHeader, checksum: u64 },
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum WorldLoadError {
+    #[error("Map was generated by generator version {0}, this build is {GENERATOR_VERSION}")]
+    GeneratorVersion(u32),
+    #[error("Regenerated map does not match the saved checksum ({expected:#x} != {actual:#x})")]
+    ChecksumMismatch { expected: u64, actual: u64 },
+    #[error("Stored map has {sites} sites but {heights} heights")]
+    CellCountMismatch { sites: usize, heights: usize },
+    #[error("Could not generate map: {0}")]
+    Generation(#[from] anyhow::Error),
+}
+
+impl WorldMap {
+    pub fn header(&self) -> WorldHeader {
+        WorldHeader {
+            generator_version: GENERATOR_VERSION,
+            params: self.params,
+            profile: self.profile.clone(),
+        }
+    }
+    pub fn to_data(&self) -> WorldMapData {
+        let sites = self
+            .voronoi
+            .sites()
+            .iter()
+            .map(|p| (p.x, p.y))
+            .collect::<Vec<_>>();
+        let heights = (0..sites.len())
+            .map(|i| self.get_raw_height(&CellId(i)))
+            .collect();
+        let resources = (0..sites.len())
+            .map(|i| {
+                self.resources
+                    .get(&CellId(i))
+                    .map(|r| r.iter().map(|(k, v)| (k.clone(), *v)).collect())
+                    .unwrap_or_default()
+            })
+            .collect();
+        let mut vertex_heights = self
+            .vertex_heights
+            .iter()
+            .map(|(k, v)| (k.x, k.y, *v))
+            .collect::<Vec<_>>();
+        vertex_heights.sort_by_key(|(x, y, _)| (*x, *y));
+        WorldMapData {
+            header: self.header(),
+            scale: self.scale,
+            height_scale: self.height_scale,
+            entity_scale: self.entity_scale,
+            sites,
+            heights,
+            vertex_heights,
+            resources,
+            rivers: self.rivers().to_vec(),
+        }
+    }
+    pub fn from_data(data: WorldMapData) -> Result<Self, WorldLoadError> {
+        if data.sites.len() != data.heights.len() {
+            return Err(WorldLoadError::CellCountMismatch {
+                sites: data.sites.len(),
+                heights: data.heights.len(),
+            });
+        }
+        let params = data.header.params;
+        let profile = data.header.profile;
+        let sites = data
+            .sites
+            .iter()
+            .map(|(x, y)| Point { x: *x, y: *y })
+            .collect();
+        // Sites are stored after relaxation, so rebuilding without it gives back the same cells.
+        let voronoi = build_voronoi(sites, params.width, params.height, 0)?;
+        let polygons = voronoi
+            .iter_cells()
+            .map(|c| (CellId(c.site()), cell_polygon(&c)))
+            .collect();
+        let cell_index = CellIndex::new(&voronoi, params.width, params.height);
+        let rivers = if data.header.generator_version < RIVERS_VERSION {
+            // The flow is worked out again from the heights the rivers carved, so it mostly
+            // follows the same valleys.
+            let (to, flow) = river::drainage(&data.heights, |i| {
+                voronoi.cell(i).iter_neighbors().map(CellId)
+            });
+            river::trace(
+                &to,
+                &flow,
+                profile.river_threshold(data.heights.len()),
+                |i| data.heights[i] >= biome::SEA_LEVEL,
+            )
+        } else {
+            data.rivers
+        };
+        let cell_height = data
+            .heights
+            .iter()
+            .enumerate()
+            .map(|(i, h)| (CellId(i), *h))
+            .collect();
+        // Derived from the heights, so there's nothing more to store.
+        let climate = biome::classify(&voronoi, &cell_height, params.height);
+        let resources = data
+            .resources
+            .into_iter()
+            .enumerate()
+            .filter(|(_, r)| !r.is_empty())
+            .map(|(i, r)| (CellId(i), r.into_iter().collect::<HashMap<_, _>>()))
+            .collect();
+        let vertex_heights = data
+            .vertex_heights
+            .iter()
+            .map(|(x, y, h)| (I64Vec2::new(*x, *y), *h))
+            .collect();
+        Ok(WorldMap {
+            scale: data.scale,
+            height_scale: data.height_scale,
+            entity_scale: data.entity_scale,
+            params,
+            profile,
+            voronoi,
+            cell_height,
+            climate,
+            rivers: Rivers::new(rivers),
+            polygons,
+            cell_index,
+            vertex_heights,
+            resources,
+        })
+    }
+    /// A stable hash of the generated terrain, used to detect generator drift.
+    pub fn checksum(&self) -> u64 {
+        let data = self.to_data();
+        let mut hash = Fnv1a::default();
+        hash.write_u64(data.sites.len() as u64);
+        for (x, y) in data.sites.iter() {
+            hash.write_u64(x.to_bits());
+            hash.write_u64(y.to_bits());
+        }
+        for h in data.heights.iter() {
+            hash.write_u32(h.to_bits());
+        }
+        for resources in data.resources.iter() {
+            for (name, value) in resources.iter() {
+                hash.write(name.as_bytes());
+                hash.write_u32(value.to_bits());
+            }
+        }
+        for river in data.rivers.iter() {
+            hash.write_u64(river.cells.len() as u64);
+            for cell in river.cells.iter() {
+                hash.write_u64(cell.0 as u64);
+            }
+            hash.write_u32(river.flow.to_bits());
+        }
+        hash.finish()
+    }
+    pub fn to_stored(&self, compact: bool) -> StoredWorld {
+        if compact {
+            StoredWorld::Seeded {
+                header: self.header(),
+                checksum: self.checksum(),
+            }
+        } else {
+            StoredWorld::Full(self.to_data())
+        }
+    }
+}
+
+impl StoredWorld {
+    pub fn header(&self) -> WorldHeader {
+        match self {
+            StoredWorld::Full(data) => data.header.clone(),
+            StoredWorld::Seeded { header, .. } => header.clone(),
+        }
+    }
+    /// Rebuilds the map. `rng` must be in the state the map was originally generated from, it is
+    /// only used for [`StoredWorld::Seeded`].
+    pub fn restore<R: Rng + Clone>(self, rng: &mut R) -> Result<WorldMap, WorldLoadError> {
+        match self {
+            StoredWorld::Full(data) => WorldMap::from_data(data),
+            StoredWorld::Seeded { header, checksum } => {
+                if header.generator_version != GENERATOR_VERSION {
+                    return Err(WorldLoadError::GeneratorVersion(header.generator_version));
+                }
+                let world_map = generate_world_with_profile(header.params, &header.profile, rng)?;
+                let actual = world_map.checksum();
+                if actual != checksum {
+                    return Err(WorldLoadError::ChecksumMismatch {
+                        expected: checksum,
+                        actual,
+                    });
+                }
+                Ok(world_map)
+            }
+        }
+    }
+}
+
+/// FNV-1a, hashed over little endian bytes so checksums match across platforms.
+struct Fnv1a(u64);
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+    fn write_u32(&mut self, v: u32) {
+        self.write(&v.to_le_bytes());
+    }
+    fn write_u64(&mut self, v: u64) {
+        self.write(&v.to_le_bytes());
+    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{WorldType, generate_world};
+
+    #[test]
+    fn full_round_trip_keeps_terrain() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let world_map = generate_world(WorldType::Small.get_params(), &mut rng).unwrap();
+        let restored = WorldMap::from_data(world_map.to_data()).unwrap();
+        assert_eq!(world_map.checksum(), restored.checksum());
+        for cell in world_map.iter_cells() {
+            let id = CellId(cell.site());
+            assert_eq!(world_map.get_climate(id), restored.get_climate(id));
+        }
+        assert_eq!(world_map.rivers(), restored.rivers());
+    }
+
+    #[test]
+    fn maps_stored_without_rivers_get_them_back() {
+        let mut rng = StdRng::seed_from_u64(25);
+        let world_map = generate_world(WorldType::Default.get_params(), &mut rng).unwrap();
+        let mut data = world_map.to_data();
+        data.header.generator_version = 1;
+        data.rivers.clear();
+        let restored = WorldMap::from_data(data).unwrap();
+        assert!(!restored.rivers().is_empty());
+        for river in restored.rivers() {
+            assert!(restored.get_raw_height(&river.cells[0]) >= biome::SEA_LEVEL);
+            for step in river.cells.windows(2) {
+                assert!(restored.get_neighbours(step[0]).contains(&step[1]));
+                assert!(restored.get_raw_height(&step[0]) > restored.get_raw_height(&step[1]));
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_restore_detects_drift() {
+        let rng = StdRng::seed_from_u64(7);
+        let world_map = generate_world(WorldType::Small.get_params(), &mut rng.clone()).unwrap();
+        let StoredWorld::Seeded { header, checksum } = world_map.to_stored(true) else {
+            unreachable!()
+        };
+        assert!(
+            StoredWorld::Seeded {
+                header: header.clone(),
+                checksum
+            }
+            .restore(&mut rng.clone())
+            .is_ok()
+        );
+        assert!(matches!(
+            StoredWorld::Seeded {
+                header,
+                checksum: checksum ^ 1
+            }
+            .restore(&mut rng.clone()),
+            Err(WorldLoadError::ChecksumMismatch { .. })
+        ));
+    }
+}